@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+# Tells each canister where the others live. Run after `dfx deploy`; the IDs are
+# kept in stable memory, so upgrades do not need to run it again.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+network="${1:-local}"
+canister_id() {
+  dfx canister id --network "$network" "$1"
+}
+
+ids="(record {
+  user_management = opt principal \"$(canister_id user_management)\";
+  post_management = opt principal \"$(canister_id post_management)\";
+  social_graph = opt principal \"$(canister_id social_graph)\";
+})"
+
+for canister in user_management post_management social_graph; do
+  dfx canister call --network "$network" "$canister" set_canister_ids "$ids"
+done
@@ -0,0 +1,16 @@
+#!/usr/bin/env bash
+# Regenerates each canister's .did file from the interface exported by
+# `ic_cdk::export_candid!()`, then refreshes the frontend declarations.
+#
+# Requires the wasm32-unknown-unknown target and `cargo install candid-extractor`.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+for canister in user_management post_management social_graph; do
+  cargo build --release --target wasm32-unknown-unknown --package "$canister"
+  candid-extractor "target/wasm32-unknown-unknown/release/$canister.wasm" \
+    > "src/$canister/$canister.did"
+done
+
+dfx generate
@@ -1,5 +1,5 @@
 import React, { useState } from 'react';
-import { apiClient } from '../lib/api';
+import { apiClient, describeError } from '../lib/api';
 import { PlusCircle, Image } from 'lucide-react';
 
 interface CreatePostFormProps {
@@ -21,6 +21,7 @@ export const CreatePostForm: React.FC<CreatePostFormProps> = ({ onPostCreated })
       const result = await apiClient.createPost({
         content: content.trim(),
         media_urls: mediaUrls,
+        visibility: [],
       });
 
       if ('Ok' in result) {
@@ -28,7 +29,7 @@ export const CreatePostForm: React.FC<CreatePostFormProps> = ({ onPostCreated })
         setContent('');
         setMediaUrls([]);
       } else {
-        alert('Failed to create post: ' + result.Err);
+        alert('Failed to create post: ' + describeError(result.Err));
       }
     } catch (error) {
       console.error('Error creating post:', error);
@@ -1,5 +1,5 @@
 import React, { useState } from 'react';
-import { apiClient } from '../lib/api';
+import { apiClient, describeError } from '../lib/api';
 import { User, Check } from 'lucide-react';
 
 interface CreateUserFormProps {
@@ -13,6 +13,7 @@ export const CreateUserForm: React.FC<CreateUserFormProps> = ({ onUserCreated })
   const [avatarUrl, setAvatarUrl] = useState('');
   const [loading, setLoading] = useState(false);
   const [usernameAvailable, setUsernameAvailable] = useState<boolean | null>(null);
+  const [usernameProblem, setUsernameProblem] = useState('');
   const [checkingUsername, setCheckingUsername] = useState(false);
 
   const checkUsername = async (username: string) => {
@@ -23,8 +24,9 @@ export const CreateUserForm: React.FC<CreateUserFormProps> = ({ onUserCreated })
 
     setCheckingUsername(true);
     try {
-      const available = await apiClient.isUsernameAvailable(username);
-      setUsernameAvailable(available);
+      const result = await apiClient.isUsernameAvailable(username);
+      setUsernameAvailable('Ok' in result);
+      setUsernameProblem('Err' in result ? describeError(result.Err) : '');
     } catch (error) {
       console.error('Error checking username:', error);
       setUsernameAvailable(null);
@@ -53,7 +55,7 @@ export const CreateUserForm: React.FC<CreateUserFormProps> = ({ onUserCreated })
     }
 
     if (usernameAvailable === false) {
-      alert('Username is not available');
+      alert(usernameProblem || 'Username is not available');
       return;
     }
 
@@ -69,7 +71,7 @@ export const CreateUserForm: React.FC<CreateUserFormProps> = ({ onUserCreated })
       if ('Ok' in result) {
         onUserCreated(result.Ok);
       } else {
-        alert('Failed to create user: ' + result.Err);
+        alert('Failed to create user: ' + describeError(result.Err));
       }
     } catch (error) {
       console.error('Error creating user:', error);
@@ -117,7 +119,7 @@ export const CreateUserForm: React.FC<CreateUserFormProps> = ({ onUserCreated })
           3-20 characters, letters, numbers, and underscores only
         </p>
         {usernameAvailable === false && (
-          <p className="text-sm text-red-500 mt-1">Username is not available</p>
+          <p className="text-sm text-red-500 mt-1">{usernameProblem || 'Username is not available'}</p>
         )}
       </div>
 
@@ -1,20 +1,56 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import { Heart, MessageCircle, Share, MoreHorizontal } from 'lucide-react';
 import { apiClient } from '../lib/api';
 
 interface PostCardProps {
   post: any;
+  author?: any;
   currentUser: any;
   onLike: () => void;
   onUnlike: () => void;
 }
 
-export const PostCard: React.FC<PostCardProps> = ({ post, currentUser, onLike, onUnlike }) => {
+export const PostCard: React.FC<PostCardProps> = ({ post, author, currentUser, onLike, onUnlike }) => {
   const [isLiking, setIsLiking] = useState(false);
-  
-  const isLiked = currentUser && post.likes.some((like: any) => 
-    like.toString() === currentUser.principal.toString()
-  );
+  const [isLiked, setIsLiked] = useState(false);
+  // The post a repost or quote points at; null once it turned out to be deleted.
+  const [referenced, setReferenced] = useState<any | null | undefined>(undefined);
+
+  const referencedId = post.repost_of?.[0] ?? post.quote_of?.[0];
+  const isRepost = post.repost_of?.length > 0;
+
+  useEffect(() => {
+    if (!currentUser) return;
+    apiClient
+      .hasLiked(post.id, currentUser.principal)
+      .then((result) => setIsLiked('Ok' in result && result.Ok))
+      .catch((error) => console.error('Error checking like:', error));
+  }, [post.id, currentUser]);
+
+  useEffect(() => {
+    if (referencedId === undefined) return;
+    apiClient
+      .getPost(referencedId)
+      .then((result) => setReferenced('Ok' in result ? result.Ok : null))
+      .catch(() => setReferenced(null));
+  }, [referencedId]);
+
+  const handleRepost = async () => {
+    if (!currentUser) return;
+    try {
+      await apiClient.repost(post.id);
+    } catch (error) {
+      console.error('Error reposting post:', error);
+    }
+  };
+
+  const renderReferenced = () => {
+    if (referenced === undefined) return null;
+    if (referenced === null) {
+      return <p className="text-gray-500 italic">This post is no longer available</p>;
+    }
+    return <p className="text-gray-900 whitespace-pre-wrap">{referenced.content}</p>;
+  };
   
   const isOwner = currentUser && post.author.toString() === currentUser.principal.toString();
 
@@ -25,9 +61,11 @@ export const PostCard: React.FC<PostCardProps> = ({ post, currentUser, onLike, o
     try {
       if (isLiked) {
         await apiClient.unlikePost(post.id);
+        setIsLiked(false);
         onUnlike();
       } else {
         await apiClient.likePost(post.id);
+        setIsLiked(true);
         onLike();
       }
     } catch (error) {
@@ -52,7 +90,7 @@ export const PostCard: React.FC<PostCardProps> = ({ post, currentUser, onLike, o
     <div className="bg-white rounded-lg shadow-sm border border-gray-200 p-6 hover:shadow-md transition-shadow">
       <div className="flex items-start space-x-3">
         <img
-          src={`https://images.pexels.com/photos/220453/pexels-photo-220453.jpeg?auto=compress&cs=tinysrgb&w=48&h=48&dpr=1`}
+          src={author?.avatar_url || `https://images.pexels.com/photos/220453/pexels-photo-220453.jpeg?auto=compress&cs=tinysrgb&w=48&h=48&dpr=1`}
           alt="User avatar"
           className="w-12 h-12 rounded-full object-cover"
         />
@@ -60,7 +98,7 @@ export const PostCard: React.FC<PostCardProps> = ({ post, currentUser, onLike, o
         <div className="flex-1 min-w-0">
           <div className="flex items-center justify-between mb-2">
             <div className="flex items-center space-x-2">
-              <span className="font-semibold text-gray-900">Anonymous User</span>
+              <span className="font-semibold text-gray-900">{author?.display_name || 'Anonymous User'}</span>
               <span className="text-gray-500 text-sm">
                 {formatDate(post.created_at)}
               </span>
@@ -74,7 +112,18 @@ export const PostCard: React.FC<PostCardProps> = ({ post, currentUser, onLike, o
           </div>
           
           <div className="mb-4">
-            <p className="text-gray-900 whitespace-pre-wrap">{post.content}</p>
+            {isRepost ? (
+              <>
+                <p className="text-gray-500 text-sm mb-1">Reposted</p>
+                {renderReferenced()}
+              </>
+            ) : (
+              <p className="text-gray-900 whitespace-pre-wrap">{post.content}</p>
+            )}
+
+            {referencedId !== undefined && !isRepost && (
+              <div className="mt-3 border border-gray-200 rounded-lg p-3">{renderReferenced()}</div>
+            )}
             
             {post.media_urls && post.media_urls.length > 0 && (
               <div className="mt-3 grid grid-cols-1 gap-3">
@@ -108,11 +157,15 @@ export const PostCard: React.FC<PostCardProps> = ({ post, currentUser, onLike, o
             
             <button className="flex items-center space-x-2 hover:text-blue-500 transition-colors">
               <MessageCircle className="w-5 h-5" />
-              <span>0</span>
+              <span>{Number(post.reply_count)}</span>
             </button>
             
-            <button className="flex items-center space-x-2 hover:text-green-500 transition-colors">
+            <button
+              onClick={handleRepost}
+              className="flex items-center space-x-2 hover:text-green-500 transition-colors"
+            >
               <Share className="w-5 h-5" />
+              <span>{Number(post.repost_count)}</span>
             </button>
           </div>
         </div>
@@ -39,8 +39,9 @@ export const UserCard: React.FC<UserCardProps> = ({ user, currentUser, onFollowC
         await apiClient.unfollowUser(user.principal);
         setIsFollowing(false);
       } else {
-        await apiClient.followUser(user.principal);
-        setIsFollowing(true);
+        const result = await apiClient.followUser(user.principal);
+        // A private account only receives a request until it approves.
+        setIsFollowing('Ok' in result && 'Following' in result.Ok);
       }
       onFollowChange();
     } catch (error) {
@@ -1,41 +1,248 @@
 export const idlFactory = ({ IDL }) => {
+  const UserSummary = IDL.Record({
+    'principal' : IDL.Principal,
+    'username' : IDL.Text,
+    'avatar_url' : IDL.Text,
+    'display_name' : IDL.Text,
+    'is_verified' : IDL.Bool,
+  });
+  const Visibility = IDL.Variant({
+    'FollowersOnly' : IDL.Null,
+    'Public' : IDL.Null,
+    'Unlisted' : IDL.Null,
+    'Direct' : IDL.Null,
+  });
   const CreatePostRequest = IDL.Record({
     'content' : IDL.Text,
     'media_urls' : IDL.Vec(IDL.Text),
+    'visibility' : IDL.Opt(Visibility),
+  });
+  const Mention = IDL.Record({
+    'end' : IDL.Nat64,
+    'username' : IDL.Text,
+    'user' : IDL.Principal,
+    'start' : IDL.Nat64,
   });
   const Post = IDL.Record({
     'id' : IDL.Nat64,
-    'content' : IDL.Text,
     'updated_at' : IDL.Nat64,
+    'content' : IDL.Text,
+    'reply_to' : IDL.Opt(IDL.Principal),
+    'like_count' : IDL.Nat64,
+    'parent_post_id' : IDL.Opt(IDL.Nat64),
+    'media_urls' : IDL.Vec(IDL.Text),
+    'hidden' : IDL.Bool,
     'created_at' : IDL.Nat64,
     'author' : IDL.Principal,
-    'like_count' : IDL.Nat64,
-    'likes' : IDL.Vec(IDL.Principal),
+    'repost_count' : IDL.Nat64,
+    'repost_of' : IDL.Opt(IDL.Nat64),
+    'reply_count' : IDL.Nat64,
+    'quote_of' : IDL.Opt(IDL.Nat64),
+    'mentions' : IDL.Vec(Mention),
+    'visibility' : Visibility,
+    'quote_count' : IDL.Nat64,
+    'edit_count' : IDL.Nat32,
+  });
+  const PostError = IDL.Variant({
+    'TooManyMutedWords' : IDL.Record({ 'max' : IDL.Nat32 }),
+    'EmptyContent' : IDL.Null,
+    'AlreadyReposted' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'NotAReply' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'NotShareable' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'NotFound' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'NotMuted' : IDL.Record({ 'user' : IDL.Principal }),
+    'InvalidCursor' : IDL.Null,
+    'NotAuthor' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'TooManyMutes' : IDL.Record({ 'max' : IDL.Nat32 }),
+    'Unavailable' : IDL.Record({ 'canister' : IDL.Text }),
+    'NotEditable' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'CannotMuteSelf' : IDL.Null,
+    'NotReposted' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'EditWindowClosed' : IDL.Record({ 'post_id' : IDL.Nat64 }),
+    'AnonymousCaller' : IDL.Null,
+  });
+  const Result = IDL.Variant({ 'Ok' : Post, 'Err' : PostError });
+  const Result_1 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : PostError });
+  const Page = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'items' : IDL.Vec(Post),
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : Page, 'Err' : PostError });
+  const CanisterIds = IDL.Record({
+    'social_graph' : IDL.Opt(IDL.Principal),
+    'post_management' : IDL.Opt(IDL.Principal),
+    'user_management' : IDL.Opt(IDL.Principal),
+  });
+  const LikeGiven = IDL.Record({
+    'post_id' : IDL.Nat64,
+    'liked_at' : IDL.Nat64,
+  });
+  const Page_1 = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'items' : IDL.Vec(LikeGiven),
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : Page_1, 'Err' : PostError });
+  const Mute = IDL.Record({
+    'muted_at' : IDL.Nat64,
+    'user' : IDL.Principal,
+    'expires_at' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_4 = IDL.Variant({ 'Ok' : IDL.Vec(Mute), 'Err' : PostError });
+  const Result_5 = IDL.Variant({ 'Ok' : IDL.Vec(IDL.Text), 'Err' : PostError });
+  const PostRevision = IDL.Record({
+    'content' : IDL.Text,
     'media_urls' : IDL.Vec(IDL.Text),
+    'written_at' : IDL.Nat64,
+  });
+  const Page_2 = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'items' : IDL.Vec(PostRevision),
+  });
+  const Result_6 = IDL.Variant({ 'Ok' : Page_2, 'Err' : PostError });
+  const Page_3 = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'items' : IDL.Vec(IDL.Principal),
+  });
+  const Result_7 = IDL.Variant({ 'Ok' : Page_3, 'Err' : PostError });
+  const PostWithAuthor = IDL.Record({
+    'post' : Post,
+    'author' : IDL.Opt(UserSummary),
+  });
+  const Page_4 = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'items' : IDL.Vec(PostWithAuthor),
+  });
+  const Result_8 = IDL.Variant({ 'Ok' : Page_4, 'Err' : PostError });
+  const Thread = IDL.Record({
+    'post' : Post,
+    'ancestors' : IDL.Vec(Post),
+    'replies' : Page,
+  });
+  const Result_9 = IDL.Variant({ 'Ok' : Thread, 'Err' : PostError });
+  const HashtagCount = IDL.Record({ 'tag' : IDL.Text, 'count' : IDL.Nat64 });
+  const Result_10 = IDL.Variant({ 'Ok' : IDL.Bool, 'Err' : PostError });
+  const Result_11 = IDL.Variant({ 'Ok' : Mute, 'Err' : PostError });
+  const SearchFilters = IDL.Record({
+    'author' : IDL.Opt(IDL.Principal),
+    'since' : IDL.Opt(IDL.Nat64),
+    'until' : IDL.Opt(IDL.Nat64),
   });
-  const Result = IDL.Variant({ 'Ok' : Post, 'Err' : IDL.Text });
   const UpdatePostRequest = IDL.Record({
     'content' : IDL.Opt(IDL.Text),
     'media_urls' : IDL.Opt(IDL.Vec(IDL.Text)),
   });
-  const Result_1 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text });
   return IDL.Service({
+    'account_privacy_changed' : IDL.Func([IDL.Principal, IDL.Bool], [], []),
+    'author_updated' : IDL.Func([UserSummary], [], []),
     'create_post' : IDL.Func([CreatePostRequest], [Result], []),
+    'create_quote_post' : IDL.Func([IDL.Nat64, IDL.Text], [Result], []),
+    'create_reply' : IDL.Func([IDL.Nat64, CreatePostRequest], [Result], []),
     'delete_post' : IDL.Func([IDL.Nat64], [Result_1], []),
+    'delete_user_content' : IDL.Func([IDL.Principal], [IDL.Bool], []),
+    'follower_removed' : IDL.Func([IDL.Principal, IDL.Principal], [], []),
+    'followers_added' : IDL.Func(
+        [IDL.Principal, IDL.Vec(IDL.Principal)],
+        [],
+        [],
+      ),
+    'get_authored_posts' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'get_canister_ids' : IDL.Func([], [CanisterIds], ['query']),
+    'get_edit_window' : IDL.Func([], [IDL.Opt(IDL.Nat64)], ['query']),
+    'get_home_feed' : IDL.Func(
+        [IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['composite_query'],
+      ),
+    'get_likes_given' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_3],
+        ['query'],
+      ),
+    'get_mentions' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'get_muted' : IDL.Func([], [Result_4], ['query']),
+    'get_muted_words' : IDL.Func([], [Result_5], ['query']),
     'get_post' : IDL.Func([IDL.Nat64], [Result], ['query']),
+    'get_post_history' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_6],
+        ['query'],
+      ),
+    'get_post_likers' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_7],
+        ['query'],
+      ),
+    'get_posts_by_hashtag' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
     'get_posts_by_users' : IDL.Func(
-        [IDL.Vec(IDL.Principal), IDL.Nat64, IDL.Nat64],
-        [IDL.Vec(Post)],
+        [IDL.Vec(IDL.Principal), IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
         ['query'],
       ),
     'get_recent_posts' : IDL.Func(
+        [IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'get_recent_posts_with_authors' : IDL.Func(
+        [IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_8],
+        ['composite_query'],
+      ),
+    'get_replies' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'get_thread' : IDL.Func([IDL.Nat64], [Result_9], ['query']),
+    'get_trending_hashtags' : IDL.Func(
         [IDL.Nat64, IDL.Nat64],
-        [IDL.Vec(Post)],
+        [IDL.Vec(HashtagCount)],
         ['query'],
       ),
-    'get_user_posts' : IDL.Func([IDL.Principal], [IDL.Vec(Post)], ['query']),
+    'get_user_posts' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'get_user_posts_with_authors' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_8],
+        ['composite_query'],
+      ),
+    'has_liked' : IDL.Func([IDL.Nat64, IDL.Principal], [Result_10], ['query']),
     'like_post' : IDL.Func([IDL.Nat64], [Result], []),
+    'mute_user' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Nat64)],
+        [Result_11],
+        [],
+      ),
+    'repost' : IDL.Func([IDL.Nat64], [Result], []),
+    'search_posts' : IDL.Func(
+        [IDL.Text, SearchFilters, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'set_canister_ids' : IDL.Func([CanisterIds], [], []),
+    'set_edit_window' : IDL.Func([IDL.Opt(IDL.Nat64)], [], []),
+    'set_muted_words' : IDL.Func([IDL.Vec(IDL.Text)], [Result_5], []),
+    'set_reply_hidden' : IDL.Func([IDL.Nat64, IDL.Bool], [Result], []),
+    'undo_repost' : IDL.Func([IDL.Nat64], [Result_1], []),
     'unlike_post' : IDL.Func([IDL.Nat64], [Result], []),
+    'unmute_user' : IDL.Func([IDL.Principal], [Result_1], []),
     'update_post' : IDL.Func([IDL.Nat64, UpdatePostRequest], [Result], []),
+    'user_blocked' : IDL.Func([IDL.Principal, IDL.Principal], [], []),
+    'user_unblocked' : IDL.Func([IDL.Principal, IDL.Principal], [], []),
   });
-};
\ No newline at end of file
+};
@@ -1,24 +1,61 @@
 export const idlFactory = ({ IDL }) => {
+  const GraphError = IDL.Variant({
+    'NotBlocked' : IDL.Record({ 'user' : IDL.Principal }),
+    'Blocked' : IDL.Record({ 'user' : IDL.Principal }),
+    'FollowRequestPending' : IDL.Record({ 'user' : IDL.Principal }),
+    'AlreadyFollowing' : IDL.Record({ 'user' : IDL.Principal }),
+    'AlreadyBlocked' : IDL.Record({ 'user' : IDL.Principal }),
+    'NotFollowing' : IDL.Record({ 'user' : IDL.Principal }),
+    'InvalidCursor' : IDL.Null,
+    'CannotFollowSelf' : IDL.Null,
+    'CannotBlockSelf' : IDL.Null,
+    'AnonymousCaller' : IDL.Null,
+    'NoFollowRequest' : IDL.Record({ 'user' : IDL.Principal }),
+  });
+  const Result = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : GraphError });
+  const FollowStatus = IDL.Variant({
+    'Following' : IDL.Null,
+    'Requested' : IDL.Null,
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : FollowStatus, 'Err' : GraphError });
+  const Page = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'items' : IDL.Vec(IDL.Principal),
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : Page, 'Err' : GraphError });
+  const CanisterIds = IDL.Record({
+    'social_graph' : IDL.Opt(IDL.Principal),
+    'post_management' : IDL.Opt(IDL.Principal),
+    'user_management' : IDL.Opt(IDL.Principal),
+  });
   const SocialStats = IDL.Record({
     'following_count' : IDL.Nat64,
     'followers_count' : IDL.Nat64,
   });
-  const Result = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text });
   return IDL.Service({
-    'follow_user' : IDL.Func([IDL.Principal], [Result], []),
+    'approve_follow_request' : IDL.Func([IDL.Principal], [Result], []),
+    'block_user' : IDL.Func([IDL.Principal], [Result], []),
+    'cancel_follow_request' : IDL.Func([IDL.Principal], [Result], []),
+    'follow_user' : IDL.Func([IDL.Principal], [Result_1], []),
+    'get_blocked' : IDL.Func(
+        [IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
+    'get_canister_ids' : IDL.Func([], [CanisterIds], ['query']),
     'get_follow_suggestions' : IDL.Func(
         [IDL.Principal, IDL.Nat64],
         [IDL.Vec(IDL.Principal)],
         ['query'],
       ),
     'get_followers' : IDL.Func(
-        [IDL.Principal],
-        [IDL.Vec(IDL.Principal)],
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
         ['query'],
       ),
     'get_following' : IDL.Func(
-        [IDL.Principal],
-        [IDL.Vec(IDL.Principal)],
+        [IDL.Principal, IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
         ['query'],
       ),
     'get_mutual_followers' : IDL.Func(
@@ -26,12 +63,37 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Vec(IDL.Principal)],
         ['query'],
       ),
+    'get_pending_follow_requests' : IDL.Func(
+        [IDL.Opt(IDL.Vec(IDL.Nat8)), IDL.Nat64],
+        [Result_2],
+        ['query'],
+      ),
     'get_social_stats' : IDL.Func([IDL.Principal], [SocialStats], ['query']),
+    'has_requested_follow' : IDL.Func(
+        [IDL.Principal, IDL.Principal],
+        [IDL.Bool],
+        ['query'],
+      ),
+    'is_blocked' : IDL.Func(
+        [IDL.Principal, IDL.Principal],
+        [IDL.Bool],
+        ['query'],
+      ),
     'is_following' : IDL.Func(
         [IDL.Principal, IDL.Principal],
         [IDL.Bool],
         ['query'],
       ),
+    'reject_follow_request' : IDL.Func([IDL.Principal], [Result], []),
+    'remove_user' : IDL.Func([IDL.Principal], [IDL.Bool], []),
+    'set_account_privacy' : IDL.Func([IDL.Principal, IDL.Bool], [], []),
+    'set_canister_ids' : IDL.Func([CanisterIds], [], []),
+    'sync_follower_snapshot' : IDL.Func(
+        [IDL.Opt(IDL.Tuple(IDL.Principal, IDL.Principal))],
+        [IDL.Opt(IDL.Tuple(IDL.Principal, IDL.Principal))],
+        [],
+      ),
+    'unblock_user' : IDL.Func([IDL.Principal], [Result], []),
     'unfollow_user' : IDL.Func([IDL.Principal], [Result], []),
   });
-};
\ No newline at end of file
+};
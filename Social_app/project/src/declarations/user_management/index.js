@@ -1,33 +1,115 @@
 export const idlFactory = ({ IDL }) => {
-  const CreateUserRequest = IDL.Record({
+  const UserProfile = IDL.Record({
     'bio' : IDL.Text,
+    'updated_at' : IDL.Nat64,
+    'principal' : IDL.Principal,
     'username' : IDL.Text,
     'avatar_url' : IDL.Text,
+    'is_private' : IDL.Bool,
+    'created_at' : IDL.Nat64,
     'display_name' : IDL.Text,
+    'is_verified' : IDL.Bool,
   });
-  const UserProfile = IDL.Record({
+  const UsernameRejection = IDL.Variant({
+    'TooLong' : IDL.Record({ 'max' : IDL.Nat32 }),
+    'Reserved' : IDL.Null,
+    'Confusable' : IDL.Null,
+    'TooShort' : IDL.Record({ 'min' : IDL.Nat32 }),
+    'MixedScripts' : IDL.Null,
+    'InvalidCharacter' : IDL.Record({ 'character' : IDL.Text }),
+    'Taken' : IDL.Record({ 'suggestion' : IDL.Opt(IDL.Text) }),
+  });
+  const UserError = IDL.Variant({
+    'UsernameTaken' : IDL.Record({ 'suggestion' : IDL.Opt(IDL.Text) }),
+    'AlreadyRegistered' : IDL.Null,
+    'NotFound' : IDL.Null,
+    'UsernameChangeCooldown' : IDL.Record({ 'available_at' : IDL.Nat64 }),
+    'InvalidUsername' : IDL.Record({ 'reason' : UsernameRejection }),
+    'Unavailable' : IDL.Record({ 'canister' : IDL.Text }),
+    'DeletionInProgress' : IDL.Null,
+    'AnonymousCaller' : IDL.Null,
+  });
+  const Result = IDL.Variant({ 'Ok' : UserProfile, 'Err' : UserError });
+  const CreateUserRequest = IDL.Record({
     'bio' : IDL.Text,
     'username' : IDL.Text,
+    'avatar_url' : IDL.Text,
+    'display_name' : IDL.Text,
+  });
+  const AccountTombstone = IDL.Record({
+    'content_removed' : IDL.Bool,
     'principal' : IDL.Principal,
+    'username' : IDL.Text,
+    'graph_removed' : IDL.Bool,
+    'created_at' : IDL.Nat64,
+    'requested_at' : IDL.Nat64,
+    'completed_at' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : AccountTombstone, 'Err' : UserError });
+  const DataExport = IDL.Record({
+    'format_version' : IDL.Nat32,
+    'size' : IDL.Nat64,
     'created_at' : IDL.Nat64,
+    'chunk_count' : IDL.Nat32,
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : DataExport, 'Err' : UserError });
+  const CanisterIds = IDL.Record({
+    'social_graph' : IDL.Opt(IDL.Principal),
+    'post_management' : IDL.Opt(IDL.Principal),
+    'user_management' : IDL.Opt(IDL.Principal),
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : IDL.Vec(IDL.Nat8), 'Err' : UserError });
+  const UserSummary = IDL.Record({
+    'principal' : IDL.Principal,
+    'username' : IDL.Text,
     'avatar_url' : IDL.Text,
-    'updated_at' : IDL.Nat64,
-    'is_verified' : IDL.Bool,
     'display_name' : IDL.Text,
+    'is_verified' : IDL.Bool,
   });
-  const Result = IDL.Variant({ 'Ok' : UserProfile, 'Err' : IDL.Text });
   const UpdateUserRequest = IDL.Record({
     'bio' : IDL.Opt(IDL.Text),
     'avatar_url' : IDL.Opt(IDL.Text),
     'display_name' : IDL.Opt(IDL.Text),
   });
+  const Result_4 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : UsernameRejection });
   return IDL.Service({
+    'change_username' : IDL.Func([IDL.Text], [Result], []),
     'create_user' : IDL.Func([CreateUserRequest], [Result], []),
+    'delete_account' : IDL.Func([], [Result_1], []),
+    'export_my_data' : IDL.Func([], [Result_2], []),
+    'follower_count_updated' : IDL.Func([IDL.Principal, IDL.Nat64], [], []),
+    'get_account_tombstones' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Vec(AccountTombstone)],
+        ['query'],
+      ),
     'get_all_users' : IDL.Func([], [IDL.Vec(UserProfile)], ['query']),
+    'get_canister_ids' : IDL.Func([], [CanisterIds], ['query']),
     'get_current_user' : IDL.Func([], [Result], ['query']),
+    'get_export_chunk' : IDL.Func([IDL.Nat32], [Result_3], ['query']),
+    'get_reserved_usernames' : IDL.Func([], [IDL.Vec(IDL.Text)], ['query']),
     'get_user' : IDL.Func([IDL.Principal], [Result], ['query']),
     'get_user_by_username' : IDL.Func([IDL.Text], [Result], ['query']),
+    'get_users' : IDL.Func(
+        [IDL.Vec(IDL.Principal)],
+        [IDL.Vec(UserSummary)],
+        ['query'],
+      ),
+    'get_users_by_usernames' : IDL.Func(
+        [IDL.Vec(IDL.Text)],
+        [IDL.Vec(IDL.Tuple(IDL.Text, UserSummary))],
+        ['query'],
+      ),
+    'reserve_usernames' : IDL.Func([IDL.Vec(IDL.Text)], [], []),
+    'search_users' : IDL.Func(
+        [IDL.Text, IDL.Nat64],
+        [IDL.Vec(UserSummary)],
+        ['query'],
+      ),
+    'set_account_private' : IDL.Func([IDL.Bool], [Result], []),
+    'set_canister_ids' : IDL.Func([CanisterIds], [], []),
+    'unreserve_usernames' : IDL.Func([IDL.Vec(IDL.Text)], [], []),
     'update_user' : IDL.Func([UpdateUserRequest], [Result], []),
-    'username_available' : IDL.Func([IDL.Text], [IDL.Bool], ['query']),
+    'username_available' : IDL.Func([IDL.Text], [Result_4], ['query']),
   });
-};
\ No newline at end of file
+};
@@ -15,6 +15,55 @@ const USER_CANISTER_ID = import.meta.env.VITE_CANISTER_ID_USER_MANAGEMENT || 'rd
 const POST_CANISTER_ID = import.meta.env.VITE_CANISTER_ID_POST_MANAGEMENT || 'rrkah-fqaaa-aaaaa-aaaaq-cai';
 const SOCIAL_CANISTER_ID = import.meta.env.VITE_CANISTER_ID_SOCIAL_GRAPH || 'ryjl3-tyaaa-aaaaa-aaaba-cai';
 
+// Canister errors are Candid variants, e.g. { NotAuthor: { post_id: 1n } }.
+const ERROR_MESSAGES: Record<string, (payload: any) => string> = {
+  AnonymousCaller: () => 'Please sign in first',
+  AlreadyRegistered: () => 'You already have a profile',
+  UsernameTaken: ({ suggestion }) =>
+    suggestion.length > 0 ? `Username is taken, try "${suggestion[0]}"` : 'Username is taken',
+  InvalidUsername: ({ reason }) => describeError(reason),
+  TooShort: ({ min }) => `Username must be at least ${min} characters`,
+  TooLong: ({ max }) => `Username must be at most ${max} characters`,
+  InvalidCharacter: ({ character }) => `Usernames cannot contain "${character}"`,
+  MixedScripts: () => 'Usernames cannot mix alphabets',
+  Reserved: () => 'That username is reserved',
+  Taken: ({ suggestion }) =>
+    suggestion.length > 0 ? `Username is taken, try "${suggestion[0]}"` : 'Username is taken',
+  Confusable: () => 'Username is too similar to an existing one',
+  UsernameChangeCooldown: ({ available_at }) =>
+    `You can change your username again on ${new Date(Number(available_at / 1_000_000n)).toLocaleDateString()}`,
+  NotFound: () => 'Not found',
+  DeletionInProgress: () => 'Your account is being deleted; retry the deletion to finish it',
+  Unavailable: ({ canister }) => `The ${canister} service is unavailable, please try again`,
+  EmptyContent: () => 'Post content cannot be empty',
+  NotAuthor: () => 'You can only change your own posts',
+  NotAReply: () => 'That post is not a reply',
+  NotEditable: () => 'Reposts cannot be edited',
+  EditWindowClosed: () => 'This post can no longer be edited',
+  AlreadyReposted: () => 'You already reposted this',
+  NotReposted: () => 'You have not reposted this',
+  NotShareable: () => 'Only public and unlisted posts can be shared',
+  CannotMuteSelf: () => 'You cannot mute yourself',
+  NotMuted: () => 'You have not muted this user',
+  TooManyMutes: ({ max }) => `You can mute at most ${max} accounts`,
+  TooManyMutedWords: ({ max }) => `You can mute at most ${max} words`,
+  CannotFollowSelf: () => 'You cannot follow yourself',
+  AlreadyFollowing: () => 'You already follow this user',
+  NotFollowing: () => 'You do not follow this user',
+  FollowRequestPending: () => 'You already asked to follow this user',
+  NoFollowRequest: () => 'There is no follow request from this user',
+  CannotBlockSelf: () => 'You cannot block yourself',
+  AlreadyBlocked: () => 'You already blocked this user',
+  NotBlocked: () => 'You have not blocked this user',
+  Blocked: () => 'You cannot follow this user',
+};
+
+export function describeError(error: any): string {
+  const [variant] = Object.keys(error);
+  const describe = ERROR_MESSAGES[variant];
+  return describe ? describe(error[variant]) : variant;
+}
+
 class ApiClient {
   private agent: HttpAgent;
   private userActor: any;
@@ -134,6 +183,59 @@ class ApiClient {
     }
   }
 
+  async changeUsername(username: string) {
+    await this.ensureInitialized();
+    try {
+      return await this.userActor.change_username(username);
+    } catch (error) {
+      console.error('Failed to change username:', error);
+      throw error;
+    }
+  }
+
+  async deleteAccount() {
+    await this.ensureInitialized();
+    try {
+      return await this.userActor.delete_account();
+    } catch (error) {
+      console.error('Failed to delete account:', error);
+      throw error;
+    }
+  }
+
+  // Turning an account public approves every pending follow request.
+  async setAccountPrivate(isPrivate: boolean) {
+    await this.ensureInitialized();
+    try {
+      return await this.userActor.set_account_private(isPrivate);
+    } catch (error) {
+      console.error('Failed to change account privacy:', error);
+      throw error;
+    }
+  }
+
+  // Exports the caller's data; fetch each of the returned `chunk_count` chunks with
+  // getExportChunk and concatenate them to get the JSON document.
+  async exportMyData() {
+    await this.ensureInitialized();
+    try {
+      return await this.userActor.export_my_data();
+    } catch (error) {
+      console.error('Failed to export data:', error);
+      throw error;
+    }
+  }
+
+  async getExportChunk(index: number) {
+    await this.ensureInitialized();
+    try {
+      return await this.userActor.get_export_chunk(index);
+    } catch (error) {
+      console.error('Failed to fetch export chunk:', error);
+      throw error;
+    }
+  }
+
   async isUsernameAvailable(username: string) {
     await this.ensureInitialized();
     try {
@@ -154,6 +256,16 @@ class ApiClient {
     }
   }
 
+  async searchUsers(query: string, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.userActor.search_users(query, limit);
+    } catch (error) {
+      console.error('Failed to search users:', error);
+      throw error;
+    }
+  }
+
   // Post Management
   async createPost(postData: any) {
     await this.ensureInitialized();
@@ -175,10 +287,10 @@ class ApiClient {
     }
   }
 
-  async getUserPosts(principal: Principal) {
+  async getUserPosts(principal: Principal, cursor: Uint8Array | number[] | null, limit: bigint) {
     await this.ensureInitialized();
     try {
-      return await this.postActor.get_user_posts(principal);
+      return await this.postActor.get_user_posts(principal, cursor ? [cursor] : [], limit);
     } catch (error) {
       console.error('Failed to get user posts:', error);
       throw error;
@@ -195,6 +307,16 @@ class ApiClient {
     }
   }
 
+  async getPostHistory(postId: bigint, cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_post_history(postId, cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get post history:', error);
+      throw error;
+    }
+  }
+
   async deletePost(postId: bigint) {
     await this.ensureInitialized();
     try {
@@ -225,27 +347,254 @@ class ApiClient {
     }
   }
 
-  async getRecentPosts(limit: bigint, offset: bigint) {
+  async getPostLikers(postId: bigint, cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_post_likers(postId, cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get post likers:', error);
+      throw error;
+    }
+  }
+
+  async hasLiked(postId: bigint, principal: Principal) {
     await this.ensureInitialized();
     try {
-      return await this.postActor.get_recent_posts(limit, offset);
+      return await this.postActor.has_liked(postId, principal);
+    } catch (error) {
+      console.error('Failed to check like:', error);
+      throw error;
+    }
+  }
+
+  async createReply(postId: bigint, postData: any) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.create_reply(postId, postData);
+    } catch (error) {
+      console.error('Failed to create reply:', error);
+      throw error;
+    }
+  }
+
+  async getReplies(postId: bigint, cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_replies(postId, cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get replies:', error);
+      throw error;
+    }
+  }
+
+  async getThread(postId: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_thread(postId);
+    } catch (error) {
+      console.error('Failed to get thread:', error);
+      throw error;
+    }
+  }
+
+  async setReplyHidden(replyId: bigint, hidden: boolean) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.set_reply_hidden(replyId, hidden);
+    } catch (error) {
+      console.error('Failed to hide reply:', error);
+      throw error;
+    }
+  }
+
+  async repost(postId: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.repost(postId);
+    } catch (error) {
+      console.error('Failed to repost:', error);
+      throw error;
+    }
+  }
+
+  async undoRepost(postId: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.undo_repost(postId);
+    } catch (error) {
+      console.error('Failed to undo repost:', error);
+      throw error;
+    }
+  }
+
+  async createQuotePost(postId: bigint, content: string) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.create_quote_post(postId, content);
+    } catch (error) {
+      console.error('Failed to create quote post:', error);
+      throw error;
+    }
+  }
+
+  async getPostsByHashtag(tag: string, cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_posts_by_hashtag(tag, cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get posts by hashtag:', error);
+      throw error;
+    }
+  }
+
+  async searchPosts(
+    query: string,
+    filters: { author?: Principal; since?: bigint; until?: bigint },
+    cursor: Uint8Array | number[] | null,
+    limit: bigint
+  ) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.search_posts(
+        query,
+        {
+          author: filters.author ? [filters.author] : [],
+          since: filters.since !== undefined ? [filters.since] : [],
+          until: filters.until !== undefined ? [filters.until] : [],
+        },
+        cursor ? [cursor] : [],
+        limit
+      );
+    } catch (error) {
+      console.error('Failed to search posts:', error);
+      throw error;
+    }
+  }
+
+  async getMentions(principal: Principal, cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_mentions(principal, cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get mentions:', error);
+      throw error;
+    }
+  }
+
+  async getTrendingHashtags(windowNanos: bigint, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_trending_hashtags(windowNanos, limit);
+    } catch (error) {
+      console.error('Failed to get trending hashtags:', error);
+      throw error;
+    }
+  }
+
+  async getRecentPostsWithAuthors(cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_recent_posts_with_authors(cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get recent posts with authors:', error);
+      throw error;
+    }
+  }
+
+  async getUserPostsWithAuthors(principal: Principal, cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_user_posts_with_authors(principal, cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get user posts with authors:', error);
+      throw error;
+    }
+  }
+
+  async getRecentPosts(cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_recent_posts(cursor ? [cursor] : [], limit);
     } catch (error) {
       console.error('Failed to get recent posts:', error);
       throw error;
     }
   }
 
-  async getPostsByUsers(users: Principal[], limit: bigint, offset: bigint) {
+  async getPostsByUsers(users: Principal[], cursor: Uint8Array | number[] | null, limit: bigint) {
     await this.ensureInitialized();
     try {
-      return await this.postActor.get_posts_by_users(users, limit, offset);
+      return await this.postActor.get_posts_by_users(users, cursor ? [cursor] : [], limit);
     } catch (error) {
       console.error('Failed to get posts by users:', error);
       throw error;
     }
   }
 
+  async getHomeFeed(cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_home_feed(cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get home feed:', error);
+      throw error;
+    }
+  }
+
+  // Mutes last `durationNanos`, or until unmuted when it is null.
+  async muteUser(principal: Principal, durationNanos: bigint | null) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.mute_user(principal, durationNanos === null ? [] : [durationNanos]);
+    } catch (error) {
+      console.error('Failed to mute user:', error);
+      throw error;
+    }
+  }
+
+  async unmuteUser(principal: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.unmute_user(principal);
+    } catch (error) {
+      console.error('Failed to unmute user:', error);
+      throw error;
+    }
+  }
+
+  async getMuted() {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_muted();
+    } catch (error) {
+      console.error('Failed to get muted users:', error);
+      throw error;
+    }
+  }
+
+  async setMutedWords(words: string[]) {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.set_muted_words(words);
+    } catch (error) {
+      console.error('Failed to set muted words:', error);
+      throw error;
+    }
+  }
+
+  async getMutedWords() {
+    await this.ensureInitialized();
+    try {
+      return await this.postActor.get_muted_words();
+    } catch (error) {
+      console.error('Failed to get muted words:', error);
+      throw error;
+    }
+  }
+
   // Social Graph
+  // Resolves to `{ Ok: { Following: null } }`, or `{ Ok: { Requested: null } }` when the
+  // account is private and the follow awaits approval.
   async followUser(principal: Principal) {
     await this.ensureInitialized();
     try {
@@ -266,6 +615,96 @@ class ApiClient {
     }
   }
 
+  async getPendingFollowRequests(cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.get_pending_follow_requests(cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get follow requests:', error);
+      throw error;
+    }
+  }
+
+  async approveFollowRequest(requester: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.approve_follow_request(requester);
+    } catch (error) {
+      console.error('Failed to approve follow request:', error);
+      throw error;
+    }
+  }
+
+  async rejectFollowRequest(requester: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.reject_follow_request(requester);
+    } catch (error) {
+      console.error('Failed to reject follow request:', error);
+      throw error;
+    }
+  }
+
+  async cancelFollowRequest(target: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.cancel_follow_request(target);
+    } catch (error) {
+      console.error('Failed to cancel follow request:', error);
+      throw error;
+    }
+  }
+
+  async hasRequestedFollow(requester: Principal, target: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.has_requested_follow(requester, target);
+    } catch (error) {
+      console.error('Failed to check follow request:', error);
+      throw error;
+    }
+  }
+
+  async blockUser(principal: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.block_user(principal);
+    } catch (error) {
+      console.error('Failed to block user:', error);
+      throw error;
+    }
+  }
+
+  async unblockUser(principal: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.unblock_user(principal);
+    } catch (error) {
+      console.error('Failed to unblock user:', error);
+      throw error;
+    }
+  }
+
+  async getBlocked(cursor: Uint8Array | number[] | null, limit: bigint) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.get_blocked(cursor ? [cursor] : [], limit);
+    } catch (error) {
+      console.error('Failed to get blocked users:', error);
+      throw error;
+    }
+  }
+
+  async isBlocked(blocker: Principal, blocked: Principal) {
+    await this.ensureInitialized();
+    try {
+      return await this.socialActor.is_blocked(blocker, blocked);
+    } catch (error) {
+      console.error('Failed to check block status:', error);
+      throw error;
+    }
+  }
+
   async isFollowing(follower: Principal, following: Principal) {
     await this.ensureInitialized();
     try {
@@ -276,20 +715,20 @@ class ApiClient {
     }
   }
 
-  async getFollowers(principal: Principal) {
+  async getFollowers(principal: Principal, cursor: Uint8Array | number[] | null, limit: bigint) {
     await this.ensureInitialized();
     try {
-      return await this.socialActor.get_followers(principal);
+      return await this.socialActor.get_followers(principal, cursor ? [cursor] : [], limit);
     } catch (error) {
       console.error('Failed to get followers:', error);
       throw error;
     }
   }
 
-  async getFollowing(principal: Principal) {
+  async getFollowing(principal: Principal, cursor: Uint8Array | number[] | null, limit: bigint) {
     await this.ensureInitialized();
     try {
-      return await this.socialActor.get_following(principal);
+      return await this.socialActor.get_following(principal, cursor ? [cursor] : [], limit);
     } catch (error) {
       console.error('Failed to get following:', error);
       throw error;
@@ -1,7 +1,7 @@
 import React, { useState, useEffect } from 'react';
 import { useAuth } from '../contexts/AuthContext';
 import { useNavigate } from 'react-router-dom';
-import { apiClient } from '../lib/api';
+import { apiClient, describeError } from '../lib/api';
 import { PlusCircle, Image, Film, Smile } from 'lucide-react';
 
 export const CreatePost: React.FC = () => {
@@ -44,12 +44,13 @@ export const CreatePost: React.FC = () => {
       const result = await apiClient.createPost({
         content: content.trim(),
         media_urls: mediaUrls,
+        visibility: [],
       });
 
       if ('Ok' in result) {
         navigate('/');
       } else {
-        alert('Failed to create post: ' + result.Err);
+        alert('Failed to create post: ' + describeError(result.Err));
       }
     } catch (error) {
       console.error('Error creating post:', error);
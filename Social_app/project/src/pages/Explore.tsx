@@ -10,6 +10,7 @@ export const Explore: React.FC = () => {
   const [suggestions, setSuggestions] = useState<any[]>([]);
   const [loading, setLoading] = useState(true);
   const [searchTerm, setSearchTerm] = useState('');
+  const [searchResults, setSearchResults] = useState<any[] | null>(null);
   const [currentUser, setCurrentUser] = useState<any>(null);
 
   useEffect(() => {
@@ -19,6 +20,29 @@ export const Explore: React.FC = () => {
     }
   }, [identity]);
 
+  useEffect(() => {
+    const query = searchTerm.trim();
+    if (!query) {
+      setSearchResults(null);
+      return;
+    }
+    let cancelled = false;
+    const timer = setTimeout(async () => {
+      try {
+        const results = await apiClient.searchUsers(query, BigInt(30));
+        if (!cancelled) {
+          setSearchResults(results);
+        }
+      } catch (error) {
+        console.error('Error searching users:', error);
+      }
+    }, 250);
+    return () => {
+      cancelled = true;
+      clearTimeout(timer);
+    };
+  }, [searchTerm]);
+
   const loadData = async () => {
     try {
       setLoading(true);
@@ -53,10 +77,7 @@ export const Explore: React.FC = () => {
     }
   };
 
-  const filteredUsers = users.filter(user => 
-    user.username.toLowerCase().includes(searchTerm.toLowerCase()) ||
-    user.display_name.toLowerCase().includes(searchTerm.toLowerCase())
-  );
+  const filteredUsers = searchResults ?? users;
 
   if (loading) {
     return (
@@ -37,14 +37,17 @@ export const Following: React.FC = () => {
 
       if (!principal) return;
 
+      // Get the home timeline (own posts plus followed users)
+      const feed = await apiClient.getHomeFeed(null, BigInt(20));
+      if ('Ok' in feed) {
+        setPosts(feed.Ok.items);
+      }
+
       // Get following list
-      const following = await apiClient.getFollowing(principal);
+      const followingPage = await apiClient.getFollowing(principal, null, BigInt(100));
+      const following = 'Ok' in followingPage ? followingPage.Ok.items : [];
       
       if (following.length > 0) {
-        // Get posts from followed users
-        const postsResult = await apiClient.getPostsByUsers(following, BigInt(20), BigInt(0));
-        setPosts(postsResult);
-
         // Get user details for following list
         const allUsers = await apiClient.getAllUsers();
         const followingUsersData = allUsers.filter(user => 
@@ -33,8 +33,10 @@ export const Home: React.FC = () => {
       }
 
       // Get posts
-      const postsResult = await apiClient.getRecentPosts(BigInt(20), BigInt(0));
-      setPosts(postsResult);
+      const postsResult = await apiClient.getRecentPostsWithAuthors(null, BigInt(20));
+      if ('Ok' in postsResult) {
+        setPosts(postsResult.Ok.items);
+      }
     } catch (error) {
       console.error('Error loading data:', error);
     } finally {
@@ -43,7 +45,7 @@ export const Home: React.FC = () => {
   };
 
   const handlePostCreated = (newPost: any) => {
-    setPosts([newPost, ...posts]);
+    setPosts([{ post: newPost, author: [currentUser] }, ...posts]);
   };
 
   if (loading) {
@@ -102,10 +104,11 @@ export const Home: React.FC = () => {
             </p>
           </div>
         ) : (
-          posts.map((post) => (
+          posts.map(({ post, author }) => (
             <PostCard
               key={post.id.toString()}
               post={post}
+              author={author[0]}
               currentUser={currentUser}
               onLike={loadData}
               onUnlike={loadData}
@@ -45,8 +45,10 @@ export const Profile: React.FC = () => {
           setUser(userResult.Ok);
           
           // Get user's posts
-          const userPosts = await apiClient.getUserPosts(userResult.Ok.principal);
-          setPosts(userPosts);
+          const userPosts = await apiClient.getUserPosts(userResult.Ok.principal, null, BigInt(50));
+          if ('Ok' in userPosts) {
+            setPosts(userPosts.Ok.items);
+          }
           
           // Get social stats
           const stats = await apiClient.getSocialStats(userResult.Ok.principal);
@@ -66,8 +68,10 @@ export const Profile: React.FC = () => {
             setUser(userResult.Ok);
             
             // Get user's posts
-            const userPosts = await apiClient.getUserPosts(userResult.Ok.principal);
-            setPosts(userPosts);
+            const userPosts = await apiClient.getUserPosts(userResult.Ok.principal, null, BigInt(50));
+            if ('Ok' in userPosts) {
+              setPosts(userPosts.Ok.items);
+            }
             
             // Get social stats
             const stats = await apiClient.getSocialStats(userResult.Ok.principal);
@@ -93,8 +97,9 @@ export const Profile: React.FC = () => {
         await apiClient.unfollowUser(user.principal);
         setIsFollowing(false);
       } else {
-        await apiClient.followUser(user.principal);
-        setIsFollowing(true);
+        const result = await apiClient.followUser(user.principal);
+        // A private account only receives a request until it approves.
+        setIsFollowing('Ok' in result && 'Following' in result.Ok);
       }
       
       // Refresh social stats
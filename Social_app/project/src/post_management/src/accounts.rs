@@ -0,0 +1,234 @@
+//! Account-wide indexes and the content side of account deletion and data export.
+//! Every post a user wrote, replies and reposts included, is indexed by author, so
+//! `user_management` can page through or remove an account's content in bounded
+//! batches.
+
+use crate::authors::{caller_is_user_management, AUTHOR_CACHE};
+use crate::{blocks, cleanup, likes, mutes, privacy, remove_post, AUTHORED_MEMORY_ID, POSTS};
+use candid::Principal;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::{LikeGiven, Post, PostError};
+use std::cell::RefCell;
+use std::ops::Bound;
+
+/// Posts deleted, or likes withdrawn, per call to `delete_user_content`, which keeps
+/// each call well inside the instruction limit.
+const DELETION_BATCH: usize = 500;
+
+thread_local! {
+    /// `(author, post_id)` for every stored post.
+    static AUTHORED: RefCell<StableBTreeMap<(Principal, u64), (), Memory>> = memory::init_map(AUTHORED_MEMORY_ID);
+}
+
+pub(crate) fn insert(post: &Post) {
+    AUTHORED.with(|index| index.borrow_mut().insert((post.author, post.id), ()));
+}
+
+pub(crate) fn remove(post: &Post) {
+    AUTHORED.with(|index| index.borrow_mut().remove(&(post.author, post.id)));
+}
+
+/// Indexes every stored post by author. Only needed when upgrading from a build
+/// without the index.
+pub(crate) fn rebuild() {
+    AUTHORED.with(|index| {
+        let mut index = index.borrow_mut();
+        index.clear_new();
+        POSTS.with(|posts| {
+            for (id, post) in posts.borrow().iter() {
+                index.insert((post.author, id), ());
+            }
+        });
+    });
+}
+
+/// Up to `limit` IDs above `after` of posts written by `author`, oldest first.
+pub(crate) fn authored_by(author: Principal, after: Option<u64>, limit: usize) -> Vec<u64> {
+    let start = match after {
+        Some(id) => Bound::Excluded((author, id)),
+        None => Bound::Included((author, 0)),
+    };
+    AUTHORED.with(|index| {
+        index
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .take_while(|((user, _), _)| *user == author)
+            .take(limit)
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// Deletes the next batch of `user`'s posts, or once those are gone, withdraws the next
+/// batch of their likes and then drops the blocks and mutes they made and the record
+/// of who follows them. Returns whether nothing is left.
+fn delete_batch(user: Principal) -> bool {
+    let post_ids = authored_by(user, None, DELETION_BATCH);
+    if !post_ids.is_empty() {
+        POSTS.with(|posts| {
+            let mut posts = posts.borrow_mut();
+            for id in post_ids {
+                // An earlier deletion in this batch may have taken a repost with it.
+                if let Some(post) = posts.get(&id) {
+                    remove_post(&mut posts, &post);
+                }
+            }
+        });
+        return false;
+    }
+
+    let liked = likes::liked_by(user, None, DELETION_BATCH);
+    if !liked.is_empty() {
+        POSTS.with(|posts| {
+            let mut posts = posts.borrow_mut();
+            for LikeGiven { post_id, .. } in liked {
+                if likes::remove(post_id, user) {
+                    if let Some(mut post) = posts.get(&post_id) {
+                        post.like_count = post.like_count.saturating_sub(1);
+                        posts.insert(post_id, post);
+                    }
+                }
+            }
+        });
+        return false;
+    }
+
+    if blocks::forget(user, DELETION_BATCH) > 0
+        || mutes::forget(user, DELETION_BATCH) > 0
+        || privacy::forget(user, DELETION_BATCH) > 0
+    {
+        return false;
+    }
+
+    AUTHOR_CACHE.with(|cache| cache.borrow_mut().remove(&user));
+    true
+}
+
+/// Called by `user_management` while deleting `user`'s account, until it returns `true`.
+/// Each call removes a bounded batch of their posts, then of the likes they gave, then
+/// of their blocks, mutes and followers.
+#[update(guard = "caller_is_user_management")]
+fn delete_user_content(user: Principal) -> bool {
+    let done = delete_batch(user);
+    cleanup::schedule();
+    done
+}
+
+/// Cursors of these listings hold the last post ID returned.
+fn decode_post_id(cursor: &[u8]) -> Result<u64, PostError> {
+    let id: [u8; 8] = cursor.try_into().map_err(|_| PostError::InvalidCursor)?;
+    Ok(u64::from_be_bytes(id))
+}
+
+/// Pages `items`, fetched with one extra, as the listings below do.
+fn page_by_id<T>(mut items: Vec<T>, limit: usize, id: impl Fn(&T) -> u64) -> Page<T> {
+    let next_cursor = (items.len() > limit).then(|| id(&items[limit - 1]).to_be_bytes().to_vec());
+    items.truncate(limit);
+    Page { items, next_cursor }
+}
+
+fn authored_page(user: Principal, cursor: Option<&[u8]>, limit: usize) -> Result<Page<Post>, PostError> {
+    let after = cursor.map(decode_post_id).transpose()?;
+    let posts = POSTS.with(|posts| {
+        let posts = posts.borrow();
+        authored_by(user, after, limit + 1)
+            .into_iter()
+            .filter_map(|id| posts.get(&id))
+            .collect()
+    });
+    Ok(page_by_id(posts, limit, |post: &Post| post.id))
+}
+
+fn likes_given_page(user: Principal, cursor: Option<&[u8]>, limit: usize) -> Result<Page<LikeGiven>, PostError> {
+    let after = cursor.map(decode_post_id).transpose()?;
+    let likes = likes::liked_by(user, after, limit + 1);
+    Ok(page_by_id(likes, limit, |like: &LikeGiven| like.post_id))
+}
+
+/// One page of every post `user` wrote, replies, reposts and hidden replies included,
+/// oldest first. Called by `user_management` to export the user's data.
+#[query(guard = "caller_is_user_management")]
+fn get_authored_posts(user: Principal, cursor: Option<Cursor>, limit: u64) -> Result<Page<Post>, PostError> {
+    authored_page(user, cursor.as_deref(), page_size(limit))
+}
+
+/// One page of the likes `user` gave, by post ID. Called by `user_management` to export
+/// the user's data.
+#[query(guard = "caller_is_user_management")]
+fn get_likes_given(user: Principal, cursor: Option<Cursor>, limit: u64) -> Result<Page<LikeGiven>, PostError> {
+    likes_given_page(user, cursor.as_deref(), page_size(limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::{stored_post, timeline};
+
+    #[test]
+    fn deletes_posts_then_likes_in_batches() {
+        let (leaving, staying) = (principal(1), principal(2));
+        for n in 0..DELETION_BATCH as u64 + 1 {
+            let post = insert_new_post(leaving, &format!("post {n}"), n);
+            timeline::insert(&post);
+        }
+        let kept = insert_new_post(staying, "hello", 0);
+        timeline::insert(&kept);
+        likes::insert(kept.id, leaving, 0);
+        POSTS.with(|posts| {
+            let mut posts = posts.borrow_mut();
+            let mut post = posts.get(&kept.id).unwrap();
+            post.like_count = 1;
+            posts.insert(kept.id, post);
+        });
+
+        assert!(!delete_batch(leaving));
+        assert_eq!(authored_by(leaving, None, usize::MAX).len(), 1);
+        assert!(!delete_batch(leaving));
+        assert!(authored_by(leaving, None, usize::MAX).is_empty());
+        assert_eq!(stored_post(kept.id).unwrap().like_count, 1);
+
+        assert!(!delete_batch(leaving));
+        assert_eq!(stored_post(kept.id).unwrap().like_count, 0);
+        assert!(likes::liked_by(leaving, None, usize::MAX).is_empty());
+        assert!(delete_batch(leaving));
+        assert_eq!(authored_by(staying, None, usize::MAX), vec![kept.id]);
+        assert!(timeline::by_author(leaving, leaving, None, 10).unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn rebuild_indexes_existing_posts() {
+        let post = insert_new_post(principal(3), "hi", 0);
+        AUTHORED.with(|index| index.borrow_mut().clear_new());
+
+        rebuild();
+        assert_eq!(authored_by(principal(3), None, 10), vec![post.id]);
+    }
+
+    #[test]
+    fn pages_through_posts_and_likes_for_export() {
+        let user = principal(4);
+        let ids: Vec<u64> = (0..3)
+            .map(|n| insert_new_post(user, &format!("post {n}"), n).id)
+            .collect();
+        insert_new_post(principal(5), "other", 0);
+        likes::insert(ids[0], user, 7);
+        likes::insert(ids[2], user, 8);
+
+        let first = authored_page(user, None, 2).unwrap();
+        assert_eq!(first.items.iter().map(|post| post.id).collect::<Vec<_>>(), ids[..2]);
+        let second = authored_page(user, first.next_cursor.as_deref(), 2).unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert!(second.next_cursor.is_none());
+
+        let likes = likes_given_page(user, None, 1).unwrap();
+        assert_eq!(likes.items, vec![LikeGiven { post_id: ids[0], liked_at: 7 }]);
+        let likes = likes_given_page(user, likes.next_cursor.as_deref(), 1).unwrap();
+        assert_eq!(likes.items, vec![LikeGiven { post_id: ids[2], liked_at: 8 }]);
+        assert!(likes.next_cursor.is_none());
+        assert_eq!(authored_page(user, Some(&[1]), 2).unwrap_err(), PostError::InvalidCursor);
+    }
+}
@@ -0,0 +1,104 @@
+//! Author summaries for hydrated feeds. Profiles live in `user_management`; this
+//! canister keeps a cache of their summaries that `user_management` refreshes on
+//! every profile change. Hydrated feeds are composite queries, which cannot keep what
+//! they fetch, so a summary the cache lacks is fetched again on every read until the
+//! author's next post caches it.
+
+use crate::{canister_ids, AUTHOR_CACHE_MEMORY_ID};
+use candid::Principal;
+use ic_cdk_macros::update;
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use social_types::pagination::Page;
+use social_types::{Post, PostError, PostWithAuthor, UserSummary};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+thread_local! {
+    pub(crate) static AUTHOR_CACHE: RefCell<StableBTreeMap<Principal, UserSummary, Memory>> = memory::init_map(AUTHOR_CACHE_MEMORY_ID);
+}
+
+pub(crate) fn caller_is_user_management() -> Result<(), String> {
+    if canister_ids().user_management == Some(ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("only user_management can call this method".to_string())
+    }
+}
+
+/// Called by `user_management` whenever a profile is created or changed.
+#[update(guard = "caller_is_user_management")]
+fn author_updated(summary: UserSummary) {
+    AUTHOR_CACHE.with(|cache| {
+        cache.borrow_mut().insert(summary.principal, summary);
+    });
+}
+
+/// Caches `author`'s summary if it is missing, e.g. for a profile made before
+/// `user_management` pushed changes here. Best effort: if `user_management` does not
+/// answer, feeds keep fetching the summary until a later post caches it.
+pub(crate) async fn warm(author: Principal) {
+    let cached = AUTHOR_CACHE.with(|cache| cache.borrow().contains_key(&author));
+    let Some(user_management) = canister_ids().user_management.filter(|_| !cached) else {
+        return;
+    };
+    let fetched: Result<(Vec<UserSummary>,), _> = ic_cdk::call(user_management, "get_users", (vec![author],)).await;
+    if let Ok((fetched,)) = fetched {
+        AUTHOR_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            for summary in fetched {
+                // A push from `user_management` that arrived meanwhile is newer.
+                if !cache.contains_key(&summary.principal) {
+                    cache.insert(summary.principal, summary);
+                }
+            }
+        });
+    }
+}
+
+/// Pairs each post with its author's summary, fetching the summaries the cache lacks
+/// from `user_management` in a single call without caching them. Authors that cannot
+/// be resolved are `None`.
+pub(crate) async fn with_authors(posts: Vec<Post>) -> Vec<PostWithAuthor> {
+    let mut summaries: BTreeMap<Principal, UserSummary> = BTreeMap::new();
+    let mut missing: BTreeSet<Principal> = BTreeSet::new();
+
+    AUTHOR_CACHE.with(|cache| {
+        let cache = cache.borrow();
+        for post in &posts {
+            match cache.get(&post.author) {
+                Some(summary) => {
+                    summaries.insert(post.author, summary);
+                }
+                None => {
+                    missing.insert(post.author);
+                }
+            }
+        }
+    });
+
+    if let (false, Some(user_management)) = (missing.is_empty(), canister_ids().user_management) {
+        let missing: Vec<Principal> = missing.into_iter().collect();
+        let fetched: Result<(Vec<UserSummary>,), _> =
+            ic_cdk::call(user_management, "get_users", (missing,)).await;
+        if let Ok((fetched,)) = fetched {
+            summaries.extend(fetched.into_iter().map(|summary| (summary.principal, summary)));
+        }
+    }
+
+    posts
+        .into_iter()
+        .map(|post| PostWithAuthor {
+            author: summaries.get(&post.author).cloned(),
+            post,
+        })
+        .collect()
+}
+
+/// [`with_authors`] for a page of posts, keeping its cursor.
+pub(crate) async fn page_with_authors(page: Page<Post>) -> Result<Page<PostWithAuthor>, PostError> {
+    Ok(Page {
+        items: with_authors(page.items).await,
+        next_cursor: page.next_cursor,
+    })
+}
@@ -0,0 +1,81 @@
+//! Blocks, mirrored from `social_graph`, which owns them. A blocked user is treated as
+//! unable to see the blocker's posts, so every listing skips them and liking, replying
+//! to or sharing them fails as if they did not exist.
+
+use crate::privacy::caller_is_social_graph;
+use crate::BLOCKS_MEMORY_ID;
+use candid::Principal;
+use ic_cdk_macros::update;
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use std::cell::RefCell;
+
+thread_local! {
+    /// `(blocker, blocked)`.
+    static BLOCKS: RefCell<StableBTreeMap<(Principal, Principal), (), Memory>> = memory::init_map(BLOCKS_MEMORY_ID);
+}
+
+pub(crate) fn is_blocked(blocker: Principal, blocked: Principal) -> bool {
+    BLOCKS.with(|blocks| blocks.borrow().contains_key(&(blocker, blocked)))
+}
+
+/// Drops up to `limit` of the blocks `user` made. Returns how many were dropped.
+pub(crate) fn forget(user: Principal, limit: usize) -> usize {
+    BLOCKS.with(|blocks| {
+        let mut blocks = blocks.borrow_mut();
+        let made: Vec<(Principal, Principal)> = blocks
+            .range((user, Principal::management_canister())..)
+            .take_while(|((blocker, _), _)| *blocker == user)
+            .take(limit)
+            .map(|(key, _)| key)
+            .collect();
+        for key in &made {
+            blocks.remove(key);
+        }
+        made.len()
+    })
+}
+
+/// Called by `social_graph` when `blocker` blocks `blocked`.
+#[update(guard = "caller_is_social_graph")]
+fn user_blocked(blocker: Principal, blocked: Principal) {
+    BLOCKS.with(|blocks| blocks.borrow_mut().insert((blocker, blocked), ()));
+}
+
+/// Called by `social_graph` when `blocker` unblocks `blocked`.
+#[update(guard = "caller_is_social_graph")]
+fn user_unblocked(blocker: Principal, blocked: Principal) {
+    BLOCKS.with(|blocks| blocks.borrow_mut().remove(&(blocker, blocked)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::{timeline, visible_post};
+    use social_types::PostError;
+
+    #[test]
+    fn blocked_users_cannot_see_the_blockers_posts() {
+        let (blocker, pest, bystander) = (principal(1), principal(2), principal(3));
+        let post = insert_new_post(blocker, "hello", 1);
+        timeline::insert(&post);
+        user_blocked(blocker, pest);
+
+        assert_eq!(visible_post(pest, post.id).unwrap_err(), PostError::NotFound { post_id: post.id });
+        assert!(timeline::recent(pest, None, 10).unwrap().items.is_empty());
+        assert!(timeline::by_author(pest, blocker, None, 10).unwrap().items.is_empty());
+        assert_eq!(timeline::recent(bystander, None, 10).unwrap().items.len(), 1);
+        // Blocking is one way: the blocker still sees the blocked user.
+        assert!(!is_blocked(pest, blocker));
+
+        user_unblocked(blocker, pest);
+        assert!(visible_post(pest, post.id).is_ok());
+
+        user_blocked(blocker, pest);
+        user_blocked(blocker, bystander);
+        assert_eq!(forget(blocker, 1), 1);
+        assert_eq!(forget(blocker, 10), 1);
+        assert_eq!(forget(blocker, 10), 0);
+    }
+}
@@ -0,0 +1,86 @@
+//! Deferred cleanup of deleted posts. `delete_post` removes a post and the index
+//! entries it owns at once, but the likes it collected, the replies listed under it
+//! and its reposts can be any number, so it only queues the post here. The global
+//! timer then sweeps the queue in bounded batches.
+
+use crate::{likes, replies, reposts, POSTS, POST_CLEANUP_MEMORY_ID};
+use ic_cdk::api::time;
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use social_types::timer;
+use std::cell::RefCell;
+
+/// Entries removed per firing of the timer, which keeps each firing well inside the
+/// instruction limit.
+const SWEEP_BATCH: usize = 500;
+
+thread_local! {
+    /// IDs of deleted posts whose likes, reply listings or reposts are still to be removed.
+    static PENDING: RefCell<StableBTreeMap<u64, (), Memory>> = memory::init_map(POST_CLEANUP_MEMORY_ID);
+}
+
+pub(crate) fn queue(post_id: u64) {
+    PENDING.with(|pending| pending.borrow_mut().insert(post_id, ()));
+}
+
+/// Removes up to `limit` entries left behind by deleted posts, lowest post ID first.
+fn sweep_batch(limit: usize) {
+    let mut budget = limit;
+    while budget > 0 {
+        let Some((post_id, ())) = PENDING.with(|pending| pending.borrow().first_key_value()) else {
+            return;
+        };
+        budget -= likes::forget_post(post_id, budget);
+        if budget > 0 {
+            budget -= replies::forget_thread(post_id, budget);
+        }
+        if budget > 0 {
+            budget -= POSTS.with(|posts| reposts::forget_reposts(&mut posts.borrow_mut(), post_id, budget));
+        }
+        if budget > 0 {
+            PENDING.with(|pending| pending.borrow_mut().remove(&post_id));
+        }
+    }
+}
+
+pub(crate) fn sweep() {
+    sweep_batch(SWEEP_BATCH);
+}
+
+/// Arms the global timer if deleted posts are waiting to be swept.
+pub(crate) fn schedule() {
+    if PENDING.with(|pending| !pending.borrow().is_empty()) {
+        timer::arm(time());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::insert_new_post;
+    use crate::remove_post;
+    use candid::Principal;
+
+    #[test]
+    fn sweeps_the_likes_of_a_deleted_post_in_batches() {
+        let post = insert_new_post(Principal::anonymous(), "popular", 0);
+        let likers: Vec<Principal> = (0..=SWEEP_BATCH as u32)
+            .map(|n| Principal::from_slice(&n.to_be_bytes()))
+            .collect();
+        for &liker in &likers {
+            likes::insert(post.id, liker, 0);
+        }
+
+        POSTS.with(|posts| remove_post(&mut posts.borrow_mut(), &post));
+        assert_eq!(likes::liked_by(likers[0], None, 1).len(), 1);
+
+        sweep();
+        let left = likers.iter().filter(|&&liker| !likes::liked_by(liker, None, 1).is_empty()).count();
+        assert_eq!(left, 1);
+        assert!(PENDING.with(|pending| pending.borrow().contains_key(&post.id)));
+
+        sweep();
+        assert!(likers.iter().all(|&liker| likes::liked_by(liker, None, 1).is_empty()));
+        assert!(PENDING.with(|pending| pending.borrow().is_empty()));
+    }
+}
@@ -0,0 +1,57 @@
+//! The signed-in user's home timeline: their own posts merged with the posts of
+//! everyone they follow, newest first. Only the `MAX_FEED_AUTHORS` most recently
+//! followed accounts are merged, which bounds the calls to `social_graph` and the
+//! timelines opened for every page.
+
+use crate::{canister_ids, timeline};
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_cdk_macros::query;
+use social_types::guards::authenticated_caller;
+use social_types::pagination::{page_size, Cursor, Page, MAX_PAGE_SIZE};
+use social_types::{GraphError, Post, PostError};
+use std::collections::BTreeSet;
+
+/// Upper bound on the followed accounts whose posts make up the home timeline.
+const MAX_FEED_AUTHORS: usize = 1_000;
+
+fn social_graph_unavailable() -> PostError {
+    PostError::Unavailable {
+        canister: "social_graph".to_string(),
+    }
+}
+
+/// Up to `MAX_FEED_AUTHORS` of the accounts `user` follows, most recently followed
+/// first, collected from `social_graph` one page at a time.
+async fn following(social_graph: Principal, user: Principal) -> Result<Vec<Principal>, PostError> {
+    let mut following = Vec::new();
+    let mut cursor: Option<Cursor> = None;
+    while following.len() < MAX_FEED_AUTHORS {
+        let limit = (MAX_FEED_AUTHORS - following.len()).min(MAX_PAGE_SIZE as usize) as u64;
+        let (page,): (Result<Page<Principal>, GraphError>,) =
+            ic_cdk::call(social_graph, "get_following", (user, cursor, limit))
+                .await
+                .map_err(|_| social_graph_unavailable())?;
+        let page = page.map_err(|_| social_graph_unavailable())?;
+        following.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(following),
+        }
+    }
+    Ok(following)
+}
+
+/// One page of the caller's home timeline. Resolves who the caller follows from
+/// `social_graph`, so clients never need to ship the graph back to us.
+#[query(composite = true)]
+async fn get_home_feed(cursor: Option<Cursor>, limit: u64) -> Result<Page<Post>, PostError> {
+    let caller = authenticated_caller()?;
+    let social_graph = canister_ids()
+        .social_graph
+        .ok_or_else(social_graph_unavailable)?;
+
+    let mut authors: BTreeSet<Principal> = following(social_graph, caller).await?.into_iter().collect();
+    authors.insert(caller);
+    timeline::by_authors(caller, &authors, cursor.as_deref(), page_size(limit), time())
+}
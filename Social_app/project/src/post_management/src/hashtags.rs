@@ -0,0 +1,337 @@
+//! `#hashtags` parsed out of post content. Tags are case-folded and indexed twice:
+//! by tag, to list a tag's posts newest first, and by time, to count what is trending
+//! without touching older posts. Only public posts by public accounts are indexed, so
+//! an account's posts are re-indexed in batches whenever it turns private or public.
+
+use crate::timeline::{decode_cursor, page_of, time_key};
+use crate::{accounts, privacy, HASHTAGS_MEMORY_ID, HASHTAG_REINDEX_MEMORY_ID, HASHTAG_USES_MEMORY_ID, POSTS};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::query;
+use ic_stable_structures::storable::Bound as StorableBound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::timer;
+use social_types::{Post, PostError, Visibility};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use unicode_xid::UnicodeXID;
+
+/// Longer tags are cut to this many characters.
+const MAX_TAG_CHARS: usize = 64;
+
+/// Upper bound on the tag uses `get_trending_hashtags` counts, newest first, so a
+/// long window on a busy canister still fits in one query.
+const MAX_TRENDING_SCAN: usize = 10_000;
+
+/// Posts re-indexed per firing of the timer after accounts change privacy.
+const REINDEX_BATCH: usize = 500;
+
+/// A case-folded hashtag without its `#`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Tag(String);
+
+impl Storable for Tag {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Tag(String::from_utf8(bytes.into_owned()).expect("stored hashtag is not UTF-8"))
+    }
+
+    const BOUND: StorableBound = StorableBound::Bounded {
+        max_size: (MAX_TAG_CHARS * 4) as u32,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HashtagCount {
+    pub tag: String,
+    pub count: u64,
+}
+
+thread_local! {
+    /// `(tag, created_at, post_id)`.
+    static HASHTAGS: RefCell<StableBTreeMap<(Tag, u64, u64), (), Memory>> = memory::init_map(HASHTAGS_MEMORY_ID);
+    /// `(created_at, post_id, tag)`.
+    static HASHTAG_USES: RefCell<StableBTreeMap<(u64, u64, Tag), (), Memory>> = memory::init_map(HASHTAG_USES_MEMORY_ID);
+    /// Accounts that changed privacy, to the lowest ID of their posts still to re-index.
+    static REINDEX: RefCell<StableBTreeMap<Principal, u64, Memory>> = memory::init_map(HASHTAG_REINDEX_MEMORY_ID);
+}
+
+/// Letters, digits, combining marks and connector punctuation, so tags in any script
+/// keep their diacritics and vowel signs.
+fn is_tag_char(c: char) -> bool {
+    c.is_xid_continue()
+}
+
+/// Case-folds a tag as typed, with or without its `#`. Tags made only of digits are
+/// not tags, so `#1` stays plain text.
+pub(crate) fn normalize(raw: &str) -> Option<Tag> {
+    let raw = raw.strip_prefix('#').unwrap_or(raw);
+    if raw.is_empty() || !raw.chars().all(is_tag_char) || raw.chars().all(|c| c.is_numeric()) {
+        return None;
+    }
+    Some(Tag(raw.to_lowercase().chars().take(MAX_TAG_CHARS).collect()))
+}
+
+/// Every distinct tag in `content`. A `#` only starts a tag at the beginning of a word,
+/// so anchors like `page#section` are left alone.
+pub(crate) fn extract(content: &str) -> BTreeSet<Tag> {
+    let mut tags = BTreeSet::new();
+    let mut in_word = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '#' && !in_word {
+            let mut raw = String::new();
+            while let Some(&next) = chars.peek().filter(|&&next| is_tag_char(next)) {
+                raw.push(next);
+                chars.next();
+            }
+            tags.extend(normalize(&raw));
+            in_word = !raw.is_empty();
+        } else {
+            in_word = is_tag_char(c);
+        }
+    }
+    tags
+}
+
+/// Indexes `post`'s tags. Only public posts by public accounts are listed by tag or
+/// count towards trends.
+pub(crate) fn insert(post: &Post) {
+    if post.visibility != Visibility::Public || privacy::is_private(post.author) {
+        return;
+    }
+    let (created_at, id) = time_key(post);
+    for tag in extract(&post.content) {
+        HASHTAG_USES.with(|uses| uses.borrow_mut().insert((created_at, id, tag.clone()), ()));
+        HASHTAGS.with(|index| index.borrow_mut().insert((tag, created_at, id), ()));
+    }
+}
+
+pub(crate) fn remove(post: &Post) {
+    if post.visibility != Visibility::Public {
+        return;
+    }
+    let (created_at, id) = time_key(post);
+    for tag in extract(&post.content) {
+        HASHTAG_USES.with(|uses| uses.borrow_mut().remove(&(created_at, id, tag.clone())));
+        HASHTAGS.with(|index| index.borrow_mut().remove(&(tag, created_at, id)));
+    }
+}
+
+/// Re-indexes `user`'s posts from the first, after they turned private or public.
+pub(crate) fn queue_reindex(user: Principal) {
+    REINDEX.with(|reindex| reindex.borrow_mut().insert(user, 0));
+}
+
+/// Re-indexes up to `limit` posts of the accounts that changed privacy.
+fn reindex_batch(limit: usize) {
+    let mut budget = limit;
+    while budget > 0 {
+        let Some((user, next)) = REINDEX.with(|reindex| reindex.borrow().first_key_value()) else {
+            return;
+        };
+        let ids = accounts::authored_by(user, next.checked_sub(1), budget);
+        POSTS.with(|posts| {
+            let posts = posts.borrow();
+            for post in ids.iter().filter_map(|id| posts.get(id)) {
+                remove(&post);
+                insert(&post);
+            }
+        });
+        budget -= ids.len();
+        REINDEX.with(|reindex| {
+            let mut reindex = reindex.borrow_mut();
+            match ids.last() {
+                Some(&last) if budget == 0 => reindex.insert(user, last + 1),
+                _ => reindex.remove(&user),
+            }
+        });
+    }
+}
+
+pub(crate) fn reindex() {
+    reindex_batch(REINDEX_BATCH);
+}
+
+/// Arms the global timer if accounts are waiting to be re-indexed.
+pub(crate) fn schedule_reindex() {
+    if REINDEX.with(|reindex| !reindex.borrow().is_empty()) {
+        timer::arm(time());
+    }
+}
+
+/// Indexes every stored post. Only needed when upgrading from a build without tags.
+pub(crate) fn rebuild() {
+    HASHTAGS.with(|index| index.borrow_mut().clear_new());
+    HASHTAG_USES.with(|uses| uses.borrow_mut().clear_new());
+    POSTS.with(|posts| {
+        for (_, post) in posts.borrow().iter() {
+            insert(&post);
+        }
+    });
+}
+
+/// One page of the posts tagged `tag`, newest first. `tag` may include its `#` and
+/// is matched case-insensitively.
+#[query]
+fn get_posts_by_hashtag(tag: String, cursor: Option<Cursor>, limit: u64) -> Result<Page<Post>, PostError> {
+    hashtag_page(ic_cdk::caller(), &tag, cursor.as_deref(), page_size(limit))
+}
+
+fn hashtag_page(viewer: Principal, tag: &str, cursor: Option<&[u8]>, limit: usize) -> Result<Page<Post>, PostError> {
+    let before = cursor.map(decode_cursor).transpose()?;
+    let Some(tag) = normalize(tag) else {
+        return Ok(Page {
+            items: Vec::new(),
+            next_cursor: None,
+        });
+    };
+
+    let oldest = Bound::Included((tag.clone(), 0, 0));
+    let newest = match before {
+        Some((created_at, id)) => Bound::Excluded((tag, created_at, id)),
+        None => Bound::Included((tag, u64::MAX, u64::MAX)),
+    };
+    Ok(HASHTAGS.with(|index| {
+        let index = index.borrow();
+        let keys = index
+            .range((oldest, newest))
+            .rev()
+            .map(|((_, created_at, id), _)| (created_at, id));
+        page_of(keys, viewer, limit)
+    }))
+}
+
+/// The tags used by the most posts over the last `window` nanoseconds, most used first.
+#[query]
+fn get_trending_hashtags(window: u64, limit: u64) -> Vec<HashtagCount> {
+    trending(time().saturating_sub(window), page_size(limit))
+}
+
+fn trending(since: u64, limit: usize) -> Vec<HashtagCount> {
+    let mut counts: BTreeMap<Tag, u64> = BTreeMap::new();
+    HASHTAG_USES.with(|uses| {
+        let uses = uses.borrow();
+        let recent = uses
+            .range((since, 0, Tag(String::new()))..)
+            .rev()
+            .take(MAX_TRENDING_SCAN);
+        for ((_, _, tag), _) in recent {
+            *counts.entry(tag).or_default() += 1;
+        }
+    });
+
+    let mut counts: Vec<(Tag, u64)> = counts.into_iter().collect();
+    // Most used first; ties go to the alphabetically first tag.
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    counts
+        .into_iter()
+        .take(limit)
+        .map(|(Tag(tag), count)| HashtagCount { tag, count })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use candid::Principal;
+
+    fn tags(content: &str) -> Vec<String> {
+        extract(content).into_iter().map(|Tag(tag)| tag).collect()
+    }
+
+    fn tagged(content: &str, created_at: u64) -> Post {
+        insert_new_post(Principal::anonymous(), content, created_at)
+    }
+
+    #[test]
+    fn extracts_case_folded_unicode_tags() {
+        assert_eq!(tags("Hello #Rust and #rust!"), vec!["rust"]);
+        assert_eq!(tags("#Café #日本 #हिन्दी"), vec!["café", "हिन्दी", "日本"]);
+        assert_eq!(tags("#snake_case, #end."), vec!["end", "snake_case"]);
+        assert!(tags("page#section # #1 ##").is_empty());
+        assert_eq!(tags("#2024goals"), vec!["2024goals"]);
+    }
+
+    #[test]
+    fn lists_posts_by_tag_and_follows_edits() {
+        let first = tagged("#ICP is live", 10);
+        let second = tagged("more #icp news", 20);
+        tagged("#other", 30);
+
+        let page = hashtag_page(Principal::anonymous(), "#ICP", None, 1).unwrap();
+        assert_eq!(page.items[0].id, second.id);
+        let page = hashtag_page(Principal::anonymous(), "icp", page.next_cursor.as_deref(), 1).unwrap();
+        assert_eq!(page.items[0].id, first.id);
+        assert!(page.next_cursor.is_none());
+
+        remove(&second);
+        let edited = Post {
+            content: "now about #motoko".to_string(),
+            ..second
+        };
+        insert(&edited);
+        let page = hashtag_page(Principal::anonymous(), "icp", None, 10).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(hashtag_page(Principal::anonymous(), "motoko", None, 10).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn trends_count_only_posts_inside_the_window() {
+        tagged("#old", 5);
+        tagged("#a #b", 10);
+        tagged("#b", 20);
+        tagged("#c #a #b", 30);
+
+        assert_eq!(
+            trending(10, 2),
+            vec![
+                HashtagCount {
+                    tag: "b".to_string(),
+                    count: 3
+                },
+                HashtagCount {
+                    tag: "a".to_string(),
+                    count: 2
+                },
+            ]
+        );
+        assert!(trending(31, 10).is_empty());
+    }
+
+    #[test]
+    fn private_accounts_stay_out_of_trends() {
+        let author = principal(1);
+        for created_at in 1..=3 {
+            insert_new_post(author, "#secret", created_at);
+        }
+        let count = || trending(0, 10).first().map_or(0, |trend| trend.count);
+        assert_eq!(count(), 3);
+
+        privacy::set_private(author, true);
+        queue_reindex(author);
+        reindex_batch(2);
+        assert_eq!(count(), 1);
+        reindex_batch(2);
+        assert_eq!(count(), 0);
+        assert!(REINDEX.with(|reindex| reindex.borrow().is_empty()));
+
+        insert_new_post(author, "#secret", 4);
+        assert_eq!(count(), 0);
+
+        privacy::set_private(author, false);
+        queue_reindex(author);
+        reindex_batch(10);
+        assert_eq!(count(), 4);
+    }
+}
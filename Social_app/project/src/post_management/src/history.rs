@@ -0,0 +1,189 @@
+//! Edit history. Every edit keeps the version of the post it replaces as a revision,
+//! so readers can see what changed. Controllers may set an edit window, after which
+//! posts can no longer be edited.
+
+use crate::{visible_post, EDIT_SETTINGS_MEMORY_ID, REVISIONS_MEMORY_ID};
+use candid::CandidType;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use serde::{Deserialize, Serialize};
+use social_types::guards::caller_is_controller;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::versioned::Versioned;
+use social_types::{versioned_storable, Post, PostError};
+use std::cell::RefCell;
+
+/// One version of a post's content and media.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PostRevision {
+    pub content: String,
+    pub media_urls: Vec<String>,
+    /// When this version was written: the post's creation or the edit that made it.
+    pub written_at: u64,
+}
+
+impl Versioned for PostRevision {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(PostRevision);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+struct EditSettings {
+    /// Nanoseconds after creation during which a post can be edited. `None` leaves
+    /// posts editable for good.
+    window: Option<u64>,
+}
+
+impl Versioned for EditSettings {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(EditSettings);
+
+thread_local! {
+    /// `(post_id, n)` to the version of the post that its `n + 1`th edit replaced.
+    static REVISIONS: RefCell<StableBTreeMap<(u64, u32), PostRevision, Memory>> = memory::init_map(REVISIONS_MEMORY_ID);
+    static EDIT_SETTINGS: RefCell<StableCell<EditSettings, Memory>> = memory::init_cell(EDIT_SETTINGS_MEMORY_ID, EditSettings::default());
+}
+
+fn edit_window() -> Option<u64> {
+    EDIT_SETTINGS.with(|settings| settings.borrow().get().window)
+}
+
+/// Fails once `post`'s edit window has passed at `now`.
+pub(crate) fn check_window(post: &Post, now: u64) -> Result<(), PostError> {
+    match edit_window() {
+        Some(window) if now > post.created_at.saturating_add(window) => {
+            Err(PostError::EditWindowClosed { post_id: post.id })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Keeps `post` as it stands as a revision and counts the edit about to replace it.
+pub(crate) fn record(post: &mut Post) {
+    let revision = PostRevision {
+        content: post.content.clone(),
+        media_urls: post.media_urls.clone(),
+        written_at: post.updated_at,
+    };
+    REVISIONS.with(|revisions| revisions.borrow_mut().insert((post.id, post.edit_count), revision));
+    post.edit_count += 1;
+}
+
+pub(crate) fn remove(post: &Post) {
+    REVISIONS.with(|revisions| {
+        let mut revisions = revisions.borrow_mut();
+        for n in 0..post.edit_count {
+            revisions.remove(&(post.id, n));
+        }
+    });
+}
+
+/// Cursors of the history hold how many versions came before the page.
+fn decode_version(cursor: &[u8]) -> Result<u32, PostError> {
+    let n: [u8; 4] = cursor.try_into().map_err(|_| PostError::InvalidCursor)?;
+    Ok(u32::from_be_bytes(n))
+}
+
+/// One page of the versions of `post`, oldest first and ending with the current one.
+fn history_page(post: &Post, cursor: Option<&[u8]>, limit: usize) -> Result<Page<PostRevision>, PostError> {
+    // Version `n` is the one the `n + 1`th edit replaced; the last is the current one.
+    let versions = post.edit_count.saturating_add(1);
+    let start = cursor.map(decode_version).transpose()?.unwrap_or(0).min(versions);
+    let end = start.saturating_add(limit as u32).min(versions);
+
+    let mut items: Vec<PostRevision> = REVISIONS.with(|revisions| {
+        revisions
+            .borrow()
+            .range((post.id, start)..(post.id, end.min(post.edit_count)))
+            .map(|(_, revision)| revision)
+            .collect()
+    });
+    if start < end && end == versions {
+        items.push(PostRevision {
+            content: post.content.clone(),
+            media_urls: post.media_urls.clone(),
+            written_at: post.updated_at,
+        });
+    }
+    let next_cursor = (end < versions).then(|| end.to_be_bytes().to_vec());
+    Ok(Page { items, next_cursor })
+}
+
+/// One page of the versions of `post_id`, oldest first and ending with the current one.
+#[query]
+fn get_post_history(post_id: u64, cursor: Option<Cursor>, limit: u64) -> Result<Page<PostRevision>, PostError> {
+    let post = visible_post(ic_cdk::caller(), post_id)?;
+    history_page(&post, cursor.as_deref(), page_size(limit))
+}
+
+/// Sets how long after creation posts can be edited, in nanoseconds. `None` lifts
+/// the limit. Applies to existing posts as well.
+#[update(guard = "caller_is_controller")]
+fn set_edit_window(window: Option<u64>) {
+    EDIT_SETTINGS.with(|settings| {
+        settings
+            .borrow_mut()
+            .set(EditSettings { window })
+            .expect("failed to update the edit settings");
+    });
+}
+
+#[query]
+fn get_edit_window() -> Option<u64> {
+    edit_window()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::new_post;
+
+    fn edit(post: &mut Post, content: &str, now: u64) {
+        record(post);
+        post.content = content.to_string();
+        post.updated_at = now;
+    }
+
+    #[test]
+    fn edits_keep_every_earlier_version() {
+        let mut post = insert_new_post(principal(1), "first", 10);
+        edit(&mut post, "second", 20);
+        edit(&mut post, "third", 30);
+
+        assert_eq!(post.edit_count, 2);
+        let versions = |page: &Page<PostRevision>| -> Vec<(String, u64)> {
+            page.items
+                .iter()
+                .map(|version| (version.content.clone(), version.written_at))
+                .collect()
+        };
+        let first = history_page(&post, None, 2).unwrap();
+        assert_eq!(versions(&first), vec![("first".to_string(), 10), ("second".to_string(), 20)]);
+        let second = history_page(&post, first.next_cursor.as_deref(), 2).unwrap();
+        assert_eq!(versions(&second), vec![("third".to_string(), 30)]);
+        assert!(second.next_cursor.is_none());
+        assert_eq!(history_page(&post, None, 10).unwrap().items.len(), 3);
+        assert_eq!(history_page(&post, Some(&[1]), 10).unwrap_err(), PostError::InvalidCursor);
+
+        remove(&post);
+        assert!(REVISIONS.with(|revisions| revisions.borrow().is_empty()));
+    }
+
+    #[test]
+    fn posts_become_immutable_once_the_edit_window_passes() {
+        let post = new_post(principal(1), "hello".to_string(), Vec::new(), 100);
+        assert_eq!(check_window(&post, u64::MAX), Ok(()));
+
+        set_edit_window(Some(50));
+        assert_eq!(check_window(&post, 150), Ok(()));
+        assert_eq!(check_window(&post, 151), Err(PostError::EditWindowClosed { post_id: post.id }));
+
+        set_edit_window(None);
+        assert_eq!(check_window(&post, 151), Ok(()));
+    }
+}
@@ -1,30 +1,92 @@
+mod accounts;
+mod authors;
+mod blocks;
+mod cleanup;
+mod feed;
+mod hashtags;
+mod history;
+mod likes;
+mod mentions;
+mod mutes;
+mod privacy;
+mod replies;
+mod reposts;
+mod search;
+mod timeline;
+#[cfg(test)]
+mod testing;
+
 use candid::{CandidType, Principal};
+use hashtags::HashtagCount;
+use history::PostRevision;
 use ic_cdk::api::time;
 use ic_cdk_macros::*;
-use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use mutes::Mute;
+use replies::Thread;
+use search::SearchFilters;
 use serde::{Deserialize, Serialize};
+use social_types::guards::{authenticated_caller, caller_is_controller};
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::versioned;
+use social_types::{CanisterIds, LikeGiven, Mention, Post, PostError, PostWithAuthor, UserSummary, Visibility};
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 
-type Memory = VirtualMemory<DefaultMemoryImpl>;
+const POSTS_MEMORY_ID: MemoryId = MemoryId::new(0);
+// Memory 1 held per-author lists of post IDs, since replaced by the author timeline
+// index. It is left untouched rather than reused so old data is never misread.
+const POST_COUNTER_MEMORY_ID: MemoryId = MemoryId::new(2);
+const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(3);
+const AUTHOR_CACHE_MEMORY_ID: MemoryId = MemoryId::new(4);
+const CANISTER_IDS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const TIMELINE_MEMORY_ID: MemoryId = MemoryId::new(6);
+const AUTHOR_TIMELINE_MEMORY_ID: MemoryId = MemoryId::new(7);
+const LIKES_MEMORY_ID: MemoryId = MemoryId::new(8);
+const REPLIES_MEMORY_ID: MemoryId = MemoryId::new(9);
+const REPOSTS_MEMORY_ID: MemoryId = MemoryId::new(10);
+const HASHTAGS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const HASHTAG_USES_MEMORY_ID: MemoryId = MemoryId::new(12);
+const MENTIONS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const SEARCH_POSTINGS_MEMORY_ID: MemoryId = MemoryId::new(14);
+const SEARCH_TERMS_MEMORY_ID: MemoryId = MemoryId::new(15);
+const AUTHORED_MEMORY_ID: MemoryId = MemoryId::new(16);
+const LIKED_MEMORY_ID: MemoryId = MemoryId::new(17);
+const PRIVATE_ACCOUNTS_MEMORY_ID: MemoryId = MemoryId::new(18);
+const FOLLOWER_SNAPSHOT_MEMORY_ID: MemoryId = MemoryId::new(19);
+const BLOCKS_MEMORY_ID: MemoryId = MemoryId::new(20);
+const MUTES_MEMORY_ID: MemoryId = MemoryId::new(21);
+const MUTE_EXPIRIES_MEMORY_ID: MemoryId = MemoryId::new(22);
+const MUTED_WORDS_MEMORY_ID: MemoryId = MemoryId::new(23);
+const REVISIONS_MEMORY_ID: MemoryId = MemoryId::new(24);
+const EDIT_SETTINGS_MEMORY_ID: MemoryId = MemoryId::new(25);
+const POST_CLEANUP_MEMORY_ID: MemoryId = MemoryId::new(26);
+const HASHTAG_REINDEX_MEMORY_ID: MemoryId = MemoryId::new(27);
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct Post {
-    pub id: u64,
-    pub author: Principal,
-    pub content: String,
-    pub media_urls: Vec<String>,
-    pub created_at: u64,
-    pub updated_at: u64,
-    pub likes: Vec<Principal>,
-    pub like_count: u64,
-}
+/// Bump whenever a stored type's `Versioned::VERSION` changes or an index is added,
+/// so that `post_upgrade` rewrites existing records eagerly instead of migrating them
+/// on every read.
+///
+/// 2: time-ordered post indexes.
+/// 3: likes moved out of `Post` into their own index.
+/// 4: replies.
+/// 5: reposts and quote posts.
+/// 6: hashtag indexes.
+/// 7: mentions on posts.
+/// 8: full-text search index.
+/// 9: per-author post and per-user like indexes.
+/// 10: post visibility.
+/// 11: post edit history.
+const SCHEMA_VERSION: u32 = 11;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CreatePostRequest {
     pub content: String,
     pub media_urls: Vec<String>,
+    /// `Public` when absent. Cannot be changed later.
+    pub visibility: Option<Visibility>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -33,122 +95,281 @@ pub struct UpdatePostRequest {
     pub media_urls: Option<Vec<String>>,
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct PostWithAuthor {
-    pub post: Post,
-    pub author_username: String,
-    pub author_display_name: String,
-    pub author_avatar_url: String,
+thread_local! {
+    static POSTS: RefCell<StableBTreeMap<u64, Post, Memory>> = memory::init_map(POSTS_MEMORY_ID);
+    static POST_COUNTER: RefCell<StableCell<u64, Memory>> = memory::init_cell(POST_COUNTER_MEMORY_ID, 0);
+    static SCHEMA: RefCell<StableCell<u32, Memory>> = memory::init_cell(SCHEMA_VERSION_MEMORY_ID, 0);
+    static CANISTER_IDS: RefCell<StableCell<CanisterIds, Memory>> = memory::init_cell(CANISTER_IDS_MEMORY_ID, CanisterIds::default());
 }
 
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
-    
-    static POSTS: RefCell<StableBTreeMap<u64, Post, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
-        )
-    );
-    
-    static USER_POSTS: RefCell<StableBTreeMap<Principal, Vec<u64>, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-        )
-    );
-    
-    static POST_COUNTER: RefCell<u64> = RefCell::new(0);
+/// Raises the counter to the highest stored post ID if it lags behind, e.g. after
+/// upgrading from a build that kept the counter on the heap. Returns the new value.
+fn reconcile_post_counter(
+    counter: &mut StableCell<u64, Memory>,
+    posts: &StableBTreeMap<u64, Post, Memory>,
+) -> u64 {
+    let highest_id = posts.last_key_value().map(|(id, _)| id).unwrap_or(0);
+    if *counter.get() < highest_id {
+        counter
+            .set(highest_id)
+            .expect("failed to update the post counter");
+    }
+    *counter.get()
 }
 
-#[update]
-fn create_post(request: CreatePostRequest) -> Result<Post, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot create posts".to_string());
+/// Rewrites every stored record at its current version if the stored schema predates
+/// this build, and builds any index the stored schema lacks. Records are also migrated
+/// lazily on read, so for them this only makes it permanent.
+fn migrate_storage() {
+    let stored = SCHEMA.with(|schema| *schema.borrow().get());
+    if stored >= SCHEMA_VERSION {
+        return;
     }
-    
-    if request.content.trim().is_empty() {
-        return Err("Post content cannot be empty".to_string());
+    if stored < 3 {
+        likes::import_legacy();
     }
-    
+    POSTS.with(|map| versioned::migrate_map(&mut map.borrow_mut()));
+    authors::AUTHOR_CACHE.with(|map| versioned::migrate_map(&mut map.borrow_mut()));
+    if stored < 2 {
+        timeline::rebuild();
+    }
+    if stored < 6 {
+        hashtags::rebuild();
+    }
+    if stored < 8 {
+        search::rebuild();
+    }
+    if stored < 9 {
+        accounts::rebuild();
+        likes::rebuild_liked();
+    }
+    set_schema_version();
+}
+
+fn set_schema_version() {
+    SCHEMA.with(|schema| {
+        schema
+            .borrow_mut()
+            .set(SCHEMA_VERSION)
+            .expect("failed to update the schema version");
+    });
+}
+
+#[init]
+fn init() {
+    set_schema_version();
+}
+
+/// All state lives in stable memory behind the memory manager, so there is nothing
+/// to serialize before an upgrade.
+#[pre_upgrade]
+fn pre_upgrade() {}
+
+#[post_upgrade]
+fn post_upgrade() {
+    migrate_storage();
+    POST_COUNTER.with(|counter| {
+        POSTS.with(|posts| reconcile_post_counter(&mut counter.borrow_mut(), &posts.borrow()));
+    });
+    // Upgrades clear the global timer.
+    mutes::schedule_expiry();
+    cleanup::schedule();
+    hashtags::schedule_reindex();
+}
+
+/// Entry point of the global timer armed through `social_types::timer`.
+#[export_name = "canister_global_timer"]
+extern "C" fn global_timer() {
+    ic_cdk::setup();
+    mutes::expire(time());
+    mutes::schedule_expiry();
+    cleanup::sweep();
+    cleanup::schedule();
+    hashtags::reindex();
+    hashtags::schedule_reindex();
+}
+
+fn canister_ids() -> CanisterIds {
+    CANISTER_IDS.with(|ids| ids.borrow().get().clone())
+}
+
+#[update(guard = "caller_is_controller")]
+fn set_canister_ids(ids: CanisterIds) {
+    CANISTER_IDS.with(|cell| {
+        cell.borrow_mut()
+            .set(ids)
+            .expect("failed to update the canister IDs");
+    });
+}
+
+#[query]
+fn get_canister_ids() -> CanisterIds {
+    canister_ids()
+}
+
+fn validate_content(content: &str) -> Result<(), PostError> {
+    if content.trim().is_empty() {
+        return Err(PostError::EmptyContent);
+    }
+    Ok(())
+}
+
+/// A new post by `author` that does not refer to any other post.
+fn new_post(author: Principal, content: String, media_urls: Vec<String>, now: u64) -> Post {
+    Post {
+        id: 0,
+        author,
+        content,
+        media_urls,
+        created_at: now,
+        updated_at: now,
+        like_count: 0,
+        parent_post_id: None,
+        reply_to: None,
+        reply_count: 0,
+        hidden: false,
+        repost_of: None,
+        quote_of: None,
+        repost_count: 0,
+        quote_count: 0,
+        mentions: Vec::new(),
+        visibility: Visibility::Public,
+        edit_count: 0,
+    }
+}
+
+/// Indexes `post`'s content: its words, hashtags and mentions.
+fn index_content(post: &Post) {
+    search::insert(post);
+    hashtags::insert(post);
+    mentions::insert(post);
+}
+
+fn unindex_content(post: &Post) {
+    search::remove(post);
+    hashtags::remove(post);
+    mentions::remove(post);
+}
+
+/// Assigns `post` the next ID and stores it along with the indexes derived from its
+/// content. Placing it in timelines or threads is up to the caller.
+fn insert_post(mut post: Post) -> Post {
     POST_COUNTER.with(|counter| {
         let mut counter = counter.borrow_mut();
-        *counter += 1;
-        let post_id = *counter;
-        
-        let now = time();
-        let post = Post {
-            id: post_id,
-            author: caller,
-            content: request.content,
-            media_urls: request.media_urls,
-            created_at: now,
-            updated_at: now,
-            likes: Vec::new(),
-            like_count: 0,
-        };
-        
-        POSTS.with(|posts| {
-            posts.borrow_mut().insert(post_id, post.clone());
-        });
-        
-        USER_POSTS.with(|user_posts| {
-            let mut user_posts = user_posts.borrow_mut();
-            let mut posts = user_posts.get(&caller).unwrap_or_default();
-            posts.push(post_id);
-            user_posts.insert(caller, posts);
-        });
-        
-        Ok(post)
-    })
+        post.id = *counter.get() + 1;
+        counter
+            .set(post.id)
+            .expect("failed to update the post counter");
+    });
+    POSTS.with(|posts| {
+        posts.borrow_mut().insert(post.id, post.clone());
+    });
+    accounts::insert(&post);
+    index_content(&post);
+    post
 }
 
 #[update]
-fn update_post(post_id: u64, request: UpdatePostRequest) -> Result<Post, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot update posts".to_string());
+async fn create_post(request: CreatePostRequest) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
+    validate_content(&request.content)?;
+    let visibility = request.visibility.unwrap_or_default();
+    let mentions = mentions::resolve_for(&request.content, visibility).await?;
+    authors::warm(caller).await;
+
+    let post = insert_post(Post {
+        mentions,
+        visibility,
+        ..new_post(caller, request.content, request.media_urls, time())
+    });
+    timeline::insert(&post);
+    Ok(post)
+}
+
+/// Checks that `caller` may edit `post_id` at `now` and returns it.
+fn editable_post(post_id: u64, caller: Principal, now: u64) -> Result<Post, PostError> {
+    let post = stored_post(post_id)?;
+    if post.author != caller {
+        return Err(PostError::NotAuthor { post_id });
     }
-    
-    POSTS.with(|posts| {
-        let mut posts = posts.borrow_mut();
-        
-        match posts.get(&post_id) {
-            Some(mut post) => {
-                if post.author != caller {
-                    return Err("You can only update your own posts".to_string());
-                }
-                
-                if let Some(content) = request.content {
-                    if content.trim().is_empty() {
-                        return Err("Post content cannot be empty".to_string());
-                    }
-                    post.content = content;
-                }
-                
-                if let Some(media_urls) = request.media_urls {
-                    post.media_urls = media_urls;
-                }
-                
-                post.updated_at = time();
-                posts.insert(post_id, post.clone());
-                Ok(post)
-            }
-            None => Err("Post not found".to_string()),
-        }
-    })
+    if post.repost_of.is_some() {
+        return Err(PostError::NotEditable { post_id });
+    }
+    history::check_window(&post, now)?;
+    Ok(post)
 }
 
+/// Edits one of the caller's posts or replies, keeping the version it replaces in
+/// the post's history.
 #[update]
-fn delete_post(post_id: u64) -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot delete posts".to_string());
+async fn update_post(post_id: u64, request: UpdatePostRequest) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
+    let current = editable_post(post_id, caller, time())?;
+
+    // The post is looked up again after the call, since it may have been edited or
+    // deleted in the meantime.
+    let content = match request.content.filter(|content| *content != current.content) {
+        Some(content) => {
+            validate_content(&content)?;
+            let mentions = mentions::resolve_for(&content, current.visibility).await?;
+            Some((content, mentions))
+        }
+        None => None,
+    };
+
+    let now = time();
+    let post = editable_post(post_id, caller, now)?;
+    Ok(apply_edit(post, content, request.media_urls, now))
+}
+
+/// Edits `post` at `now`, keeping the version it replaces. Fields that would not
+/// change are ignored, and an edit that changes nothing leaves the post as it was.
+fn apply_edit(
+    mut post: Post,
+    content: Option<(String, Vec<Mention>)>,
+    media_urls: Option<Vec<String>>,
+    now: u64,
+) -> Post {
+    let content = content.filter(|(content, _)| *content != post.content);
+    let media_urls = media_urls.filter(|media_urls| *media_urls != post.media_urls);
+    if content.is_none() && media_urls.is_none() {
+        return post;
     }
+
+    history::record(&mut post);
+    if let Some((content, mentions)) = content {
+        unindex_content(&post);
+        post.content = content;
+        post.mentions = mentions;
+        index_content(&post);
+    }
+    if let Some(media_urls) = media_urls {
+        post.media_urls = media_urls;
+    }
+
+    post.updated_at = now;
+    POSTS.with(|posts| posts.borrow_mut().insert(post.id, post.clone()));
+    post
+}
+
+/// Removes `post` from `POSTS` and from every index that refers to it. Its likes,
+/// reply listing and reposts are left to `cleanup`, which the caller must schedule.
+fn remove_post(posts: &mut StableBTreeMap<u64, Post, Memory>, post: &Post) {
+    posts.remove(&post.id);
+    match post.parent_post_id {
+        Some(_) => replies::remove(posts, post),
+        None => timeline::remove(post),
+    }
+    reposts::remove(posts, post);
+    cleanup::queue(post.id);
+    history::remove(post);
+    accounts::remove(post);
+    unindex_content(post);
+}
+
+#[update]
+fn delete_post(post_id: u64) -> Result<(), PostError> {
+    let caller = authenticated_caller()?;
     
     POSTS.with(|posts| {
         let mut posts = posts.borrow_mut();
@@ -156,150 +377,262 @@ fn delete_post(post_id: u64) -> Result<(), String> {
         match posts.get(&post_id) {
             Some(post) => {
                 if post.author != caller {
-                    return Err("You can only delete your own posts".to_string());
+                    return Err(PostError::NotAuthor { post_id });
                 }
                 
-                posts.remove(&post_id);
-                
-                USER_POSTS.with(|user_posts| {
-                    let mut user_posts = user_posts.borrow_mut();
-                    if let Some(mut posts) = user_posts.get(&caller) {
-                        posts.retain(|&id| id != post_id);
-                        user_posts.insert(caller, posts);
-                    }
-                });
-                
+                remove_post(&mut posts, &post);
+                cleanup::schedule();
                 Ok(())
             }
-            None => Err("Post not found".to_string()),
+            None => Err(PostError::NotFound { post_id }),
         }
     })
 }
 
 #[update]
-fn like_post(post_id: u64) -> Result<Post, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot like posts".to_string());
-    }
+fn like_post(post_id: u64) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
     
     POSTS.with(|posts| {
         let mut posts = posts.borrow_mut();
         
-        match posts.get(&post_id) {
+        match posts.get(&post_id).filter(|post| privacy::can_see(caller, post)) {
             Some(mut post) => {
-                if !post.likes.contains(&caller) {
-                    post.likes.push(caller);
+                if likes::insert(post_id, caller, time()) {
                     post.like_count += 1;
                     posts.insert(post_id, post.clone());
                 }
                 Ok(post)
             }
-            None => Err("Post not found".to_string()),
+            None => Err(PostError::NotFound { post_id }),
         }
     })
 }
 
 #[update]
-fn unlike_post(post_id: u64) -> Result<Post, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot unlike posts".to_string());
-    }
+fn unlike_post(post_id: u64) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
     
     POSTS.with(|posts| {
         let mut posts = posts.borrow_mut();
         
         match posts.get(&post_id) {
             Some(mut post) => {
-                if let Some(index) = post.likes.iter().position(|&x| x == caller) {
-                    post.likes.remove(index);
+                if likes::remove(post_id, caller) {
                     post.like_count = post.like_count.saturating_sub(1);
                     posts.insert(post_id, post.clone());
                 }
                 Ok(post)
             }
-            None => Err("Post not found".to_string()),
+            None => Err(PostError::NotFound { post_id }),
         }
     })
 }
 
-#[query]
-fn get_post(post_id: u64) -> Result<Post, String> {
+pub(crate) fn stored_post(post_id: u64) -> Result<Post, PostError> {
     POSTS.with(|posts| {
         let posts = posts.borrow();
         match posts.get(&post_id) {
             Some(post) => Ok(post.clone()),
-            None => Err("Post not found".to_string()),
+            None => Err(PostError::NotFound { post_id }),
         }
     })
 }
 
+/// `post_id`, if `viewer` may see it. A post hidden by its author's privacy is reported
+/// as missing.
+pub(crate) fn visible_post(viewer: Principal, post_id: u64) -> Result<Post, PostError> {
+    stored_post(post_id)
+        .ok()
+        .filter(|post| privacy::can_see(viewer, post))
+        .ok_or(PostError::NotFound { post_id })
+}
+
 #[query]
-fn get_user_posts(user: Principal) -> Vec<Post> {
-    USER_POSTS.with(|user_posts| {
-        let user_posts = user_posts.borrow();
-        match user_posts.get(&user) {
-            Some(post_ids) => {
-                POSTS.with(|posts| {
-                    let posts = posts.borrow();
-                    post_ids
-                        .iter()
-                        .filter_map(|&id| posts.get(&id))
-                        .collect()
-                })
-            }
-            None => Vec::new(),
-        }
-    })
+fn get_post(post_id: u64) -> Result<Post, PostError> {
+    visible_post(ic_cdk::caller(), post_id)
 }
 
+/// One page of `user`'s posts, newest first.
 #[query]
-fn get_recent_posts(limit: u64, offset: u64) -> Vec<Post> {
-    POSTS.with(|posts| {
-        let posts = posts.borrow();
-        let mut all_posts: Vec<Post> = posts.iter().map(|(_, post)| post.clone()).collect();
-        all_posts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        
-        let start = offset as usize;
-        let end = std::cmp::min(start + limit as usize, all_posts.len());
-        
-        if start >= all_posts.len() {
-            Vec::new()
-        } else {
-            all_posts[start..end].to_vec()
-        }
-    })
+fn get_user_posts(
+    user: Principal,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Post>, PostError> {
+    timeline::by_author(ic_cdk::caller(), user, cursor.as_deref(), page_size(limit))
 }
 
+/// One page of everyone's posts, newest first.
 #[query]
-fn get_posts_by_users(users: Vec<Principal>, limit: u64, offset: u64) -> Vec<Post> {
-    POSTS.with(|posts| {
-        let posts = posts.borrow();
-        let mut user_posts: Vec<Post> = posts
-            .iter()
-            .filter_map(|(_, post)| {
-                if users.contains(&post.author) {
-                    Some(post.clone())
-                } else {
-                    None
-                }
-            })
-            .collect();
-        
-        user_posts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        
-        let start = offset as usize;
-        let end = std::cmp::min(start + limit as usize, user_posts.len());
-        
-        if start >= user_posts.len() {
-            Vec::new()
-        } else {
-            user_posts[start..end].to_vec()
-        }
-    })
+fn get_recent_posts(cursor: Option<Cursor>, limit: u64) -> Result<Page<Post>, PostError> {
+    timeline::recent(ic_cdk::caller(), cursor.as_deref(), page_size(limit))
+}
+
+/// One page of the posts written by any of `users`, newest first.
+#[query]
+fn get_posts_by_users(
+    users: Vec<Principal>,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Post>, PostError> {
+    let users: BTreeSet<Principal> = users.into_iter().collect();
+    timeline::by_authors(ic_cdk::caller(), &users, cursor.as_deref(), page_size(limit), time())
+}
+
+#[query(composite = true)]
+async fn get_user_posts_with_authors(
+    user: Principal,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<PostWithAuthor>, PostError> {
+    authors::page_with_authors(get_user_posts(user, cursor, limit)?).await
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+#[query(composite = true)]
+async fn get_recent_posts_with_authors(
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<PostWithAuthor>, PostError> {
+    authors::page_with_authors(get_recent_posts(cursor, limit)?).await
+}
+
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use ic_stable_structures::memory_manager::MemoryManager;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    type State = (
+        StableBTreeMap<u64, Post, Memory>,
+        StableBTreeMap<(Principal, u64, u64), (), Memory>,
+        StableCell<u64, Memory>,
+    );
+
+    /// Opens the canister's stable structures on top of `memory` the same way a
+    /// freshly installed or upgraded module does.
+    fn open(memory: &DefaultMemoryImpl) -> State {
+        let manager = MemoryManager::init(memory.clone());
+        (
+            StableBTreeMap::init(manager.get(POSTS_MEMORY_ID)),
+            StableBTreeMap::init(manager.get(AUTHOR_TIMELINE_MEMORY_ID)),
+            StableCell::init(manager.get(POST_COUNTER_MEMORY_ID), 0).unwrap(),
+        )
+    }
+
+    fn post(id: u64, author: Principal) -> Post {
+        Post {
+            id,
+            author,
+            content: format!("post {id}"),
+            media_urls: vec![format!("https://example.com/{id}.png")],
+            created_at: id * 10,
+            updated_at: id * 10,
+            like_count: 1,
+            parent_post_id: None,
+            reply_to: None,
+            reply_count: 0,
+            hidden: false,
+            repost_of: None,
+            quote_of: None,
+            repost_count: 0,
+            quote_count: 0,
+            mentions: Vec::new(),
+            visibility: Visibility::Public,
+            edit_count: 0,
+        }
+    }
+
+    #[test]
+    fn posts_and_counter_survive_upgrade() {
+        let memory = DefaultMemoryImpl::default();
+        let author = principal(1);
+
+        {
+            let (mut posts, mut author_timeline, mut counter) = open(&memory);
+            for id in 1..=3 {
+                posts.insert(id, post(id, author));
+                author_timeline.insert((author, id * 10, id), ());
+            }
+            counter.set(3).unwrap();
+        }
+
+        let (posts, author_timeline, counter) = open(&memory);
+        assert_eq!(*counter.get(), 3);
+        assert_eq!(posts.len(), 3);
+        let restored = posts.get(&2).unwrap();
+        assert_eq!(restored.content, "post 2");
+        assert_eq!(restored.media_urls, vec!["https://example.com/2.png"]);
+        assert_eq!(restored.like_count, 1);
+        let ids: Vec<u64> = author_timeline.iter().map(|((_, _, id), _)| id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn lagging_counter_is_raised_to_highest_post_id() {
+        let memory = DefaultMemoryImpl::default();
+        let author = principal(2);
+
+        {
+            let (mut posts, _, _) = open(&memory);
+            posts.insert(1, post(1, author));
+            posts.insert(7, post(7, author));
+        }
+
+        let (posts, _, mut counter) = open(&memory);
+        assert_eq!(*counter.get(), 0);
+        assert_eq!(reconcile_post_counter(&mut counter, &posts), 7);
+
+        let (_, _, counter) = open(&memory);
+        assert_eq!(*counter.get(), 7);
+    }
+
+    #[test]
+    fn reconcile_keeps_counter_ahead_of_deleted_posts() {
+        let memory = DefaultMemoryImpl::default();
+        let (mut posts, _, mut counter) = open(&memory);
+        posts.insert(2, post(2, Principal::anonymous()));
+        counter.set(5).unwrap();
+
+        assert_eq!(reconcile_post_counter(&mut counter, &posts), 5);
+    }
+
+    #[test]
+    fn edits_that_change_nothing_are_not_recorded() {
+        let original = insert_new_post(principal(1), "hello", 10);
+
+        let unchanged = apply_edit(original.clone(), None, None, 20);
+        let same = apply_edit(
+            unchanged,
+            Some(("hello".to_string(), Vec::new())),
+            Some(Vec::new()),
+            30,
+        );
+        assert_eq!((same.edit_count, same.updated_at), (0, 10));
+        assert_eq!(stored_post(original.id).unwrap().edit_count, 0);
+
+        let edited = apply_edit(
+            same,
+            Some(("hello".to_string(), Vec::new())),
+            Some(vec!["a.png".to_string()]),
+            40,
+        );
+        assert_eq!((edited.edit_count, edited.updated_at), (1, 40));
+        assert_eq!(stored_post(original.id).unwrap().media_urls, vec!["a.png"]);
+    }
+
+    #[test]
+    fn committed_candid_matches_exported_interface() {
+        use candid_parser::utils::{service_compatible, CandidSource};
+
+        let committed = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("post_management.did");
+        service_compatible(
+            CandidSource::File(&committed),
+            CandidSource::Text(&__export_service()),
+        )
+        .expect("post_management.did is out of date; run scripts/generate_candid.sh");
+    }
+}
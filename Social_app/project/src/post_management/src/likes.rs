@@ -0,0 +1,276 @@
+//! Who liked which post. Likes are keyed by `(post_id, liker)` so checking, adding
+//! or removing one like is a single lookup, and a post's likers can be paged through
+//! without ever loading them all. A second index by liker finds the likes a user has
+//! given. Posts only carry the maintained `like_count`.
+
+use crate::{visible_post, LIKED_MEMORY_ID, LIKES_MEMORY_ID, POSTS_MEMORY_ID};
+use candid::{CandidType, Principal};
+use ic_cdk_macros::query;
+use ic_stable_structures::StableBTreeMap;
+use serde::Deserialize;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::versioned::{self, Versioned};
+use social_types::{LikeGiven, PostError};
+use std::cell::RefCell;
+use std::ops::Bound;
+
+thread_local! {
+    /// `(post_id, liker)` to the time of the like.
+    static LIKES: RefCell<StableBTreeMap<(u64, Principal), u64, Memory>> = memory::init_map(LIKES_MEMORY_ID);
+    /// `(liker, post_id)`.
+    static LIKED: RefCell<StableBTreeMap<(Principal, u64), (), Memory>> = memory::init_map(LIKED_MEMORY_ID);
+}
+
+/// Records a like. Returns `false` if `liker` already liked the post.
+pub(crate) fn insert(post_id: u64, liker: Principal, now: u64) -> bool {
+    LIKED.with(|liked| liked.borrow_mut().insert((liker, post_id), ()));
+    LIKES.with(|likes| likes.borrow_mut().insert((post_id, liker), now).is_none())
+}
+
+/// Removes a like. Returns `false` if `liker` had not liked the post.
+pub(crate) fn remove(post_id: u64, liker: Principal) -> bool {
+    LIKED.with(|liked| liked.borrow_mut().remove(&(liker, post_id)));
+    LIKES.with(|likes| likes.borrow_mut().remove(&(post_id, liker)).is_some())
+}
+
+/// Up to `limit` of the likes `liker` gave to posts with IDs above `after`, in post ID
+/// order.
+pub(crate) fn liked_by(liker: Principal, after: Option<u64>, limit: usize) -> Vec<LikeGiven> {
+    let start = match after {
+        Some(post_id) => Bound::Excluded((liker, post_id)),
+        None => Bound::Included((liker, 0)),
+    };
+    LIKED.with(|liked| {
+        liked
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .take_while(|((user, _), _)| *user == liker)
+            .take(limit)
+            .filter_map(|((_, post_id), _)| {
+                let liked_at = LIKES.with(|likes| likes.borrow().get(&(post_id, liker)))?;
+                Some(LikeGiven { post_id, liked_at })
+            })
+            .collect()
+    })
+}
+
+/// Drops up to `limit` of the likes of a deleted post. Returns how many were dropped.
+pub(crate) fn forget_post(post_id: u64, limit: usize) -> usize {
+    LIKES.with(|likes| {
+        let mut likes = likes.borrow_mut();
+        let likers: Vec<(u64, Principal)> = likes
+            .range((post_id, Principal::management_canister())..)
+            .take_while(|((id, _), _)| *id == post_id)
+            .take(limit)
+            .map(|(key, _)| key)
+            .collect();
+        for &(post_id, liker) in &likers {
+            likes.remove(&(post_id, liker));
+            LIKED.with(|liked| liked.borrow_mut().remove(&(liker, post_id)));
+        }
+        likers.len()
+    })
+}
+
+/// Builds the index by liker from the like index. Only needed when upgrading from a
+/// build without it.
+pub(crate) fn rebuild_liked() {
+    LIKED.with(|liked| {
+        let mut liked = liked.borrow_mut();
+        liked.clear_new();
+        LIKES.with(|likes| {
+            for ((post_id, liker), _) in likes.borrow().iter() {
+                liked.insert((liker, post_id), ());
+            }
+        });
+    });
+}
+
+/// The like list of a post as stored before likes had their own index.
+#[derive(CandidType, Deserialize)]
+struct LegacyLikes {
+    id: u64,
+    likes: Vec<Principal>,
+}
+
+impl Versioned for LegacyLikes {
+    const VERSION: u8 = 1;
+}
+
+/// Copies the likes embedded in version 1 posts into the like index. Must run before
+/// the posts themselves are migrated, which drops the embedded lists.
+pub(crate) fn import_legacy() {
+    let raw: StableBTreeMap<u64, Vec<u8>, Memory> = StableBTreeMap::init(memory::get(POSTS_MEMORY_ID));
+    LIKES.with(|likes| {
+        let mut likes = likes.borrow_mut();
+        for (_, bytes) in raw.iter() {
+            if versioned::stored_version(&bytes) != LegacyLikes::VERSION {
+                continue;
+            }
+            let legacy: LegacyLikes = versioned::decode(&bytes);
+            for liker in legacy.likes {
+                // The original like times were never recorded.
+                likes.insert((legacy.id, liker), 0);
+            }
+        }
+    });
+}
+
+fn decode_cursor(cursor: &[u8]) -> Result<Principal, PostError> {
+    Principal::try_from_slice(cursor).map_err(|_| PostError::InvalidCursor)
+}
+
+/// One page of the principals who liked `post_id`, in principal order, if `viewer`
+/// may see the post.
+fn likers_page(
+    viewer: Principal,
+    post_id: u64,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Principal>, PostError> {
+    visible_post(viewer, post_id)?;
+    let after = cursor.as_deref().map(decode_cursor).transpose()?;
+    let limit = page_size(limit);
+
+    let mut likers: Vec<Principal> = LIKES.with(|likes| {
+        let likes = likes.borrow();
+        let start = match after {
+            Some(after) => Bound::Excluded((post_id, after)),
+            None => Bound::Included((post_id, Principal::management_canister())),
+        };
+        likes
+            .range((start, Bound::Unbounded))
+            .take_while(|((id, _), _)| *id == post_id)
+            .take(limit + 1)
+            .map(|((_, liker), _)| liker)
+            .collect()
+    });
+
+    let next_cursor = (likers.len() > limit).then(|| likers[limit - 1].as_slice().to_vec());
+    likers.truncate(limit);
+    Ok(Page {
+        items: likers,
+        next_cursor,
+    })
+}
+
+fn is_liked(post_id: u64, liker: Principal) -> bool {
+    LIKES.with(|likes| likes.borrow().contains_key(&(post_id, liker)))
+}
+
+/// One page of the principals who liked `post_id`, in principal order.
+#[query]
+fn get_post_likers(
+    post_id: u64,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Principal>, PostError> {
+    likers_page(ic_cdk::caller(), post_id, cursor, limit)
+}
+
+/// Whether `principal` liked `post_id`.
+#[query]
+fn has_liked(post_id: u64, principal: Principal) -> Result<bool, PostError> {
+    visible_post(ic_cdk::caller(), post_id)?;
+    Ok(is_liked(post_id, principal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::{insert_post, new_post, POSTS};
+    use social_types::{Post, Visibility};
+
+    fn post() -> u64 {
+        insert_new_post(Principal::anonymous(), "post", 0).id
+    }
+
+    #[test]
+    fn pages_through_likers_of_one_post_only() {
+        let (liked, other) = (post(), post());
+        for n in 1..=3 {
+            assert!(insert(liked, principal(n), 0));
+        }
+        assert!(!insert(liked, principal(1), 0));
+        insert(other, principal(9), 0);
+
+        let first = likers_page(principal(8), liked, None, 2).unwrap();
+        assert_eq!(first.items, vec![principal(1), principal(2)]);
+        let second = likers_page(principal(8), liked, first.next_cursor, 2).unwrap();
+        assert_eq!(second.items, vec![principal(3)]);
+        assert!(second.next_cursor.is_none());
+
+        assert!(is_liked(other, principal(9)));
+        assert!(!is_liked(liked, principal(9)));
+    }
+
+    #[test]
+    fn likers_of_posts_the_viewer_cannot_see_are_not_listed() {
+        let direct = insert_post(Post {
+            visibility: Visibility::Direct,
+            ..new_post(principal(1), "secret".to_string(), Vec::new(), 0)
+        });
+        insert(direct.id, principal(1), 0);
+
+        assert_eq!(
+            likers_page(principal(2), direct.id, None, 10).unwrap_err(),
+            PostError::NotFound { post_id: direct.id }
+        );
+        assert_eq!(likers_page(principal(1), direct.id, None, 10).unwrap().items, vec![principal(1)]);
+    }
+
+    #[test]
+    fn deleting_a_post_drops_only_its_likes() {
+        insert(1, principal(1), 0);
+        insert(1, principal(2), 0);
+        insert(2, principal(1), 0);
+
+        assert_eq!(forget_post(1, 1), 1);
+        assert_eq!(forget_post(1, 10), 1);
+        assert_eq!(forget_post(1, 10), 0);
+        assert!(!is_liked(1, principal(1)));
+        assert!(!is_liked(1, principal(2)));
+        assert!(is_liked(2, principal(1)));
+        assert!(remove(2, principal(1)));
+        assert!(!remove(2, principal(1)));
+    }
+
+    #[test]
+    fn imports_likes_embedded_in_version_1_posts() {
+        #[derive(CandidType)]
+        struct PostV1 {
+            id: u64,
+            author: Principal,
+            content: String,
+            media_urls: Vec<String>,
+            created_at: u64,
+            updated_at: u64,
+            likes: Vec<Principal>,
+            like_count: u64,
+        }
+
+        let mut raw: StableBTreeMap<u64, Vec<u8>, Memory> =
+            StableBTreeMap::init(memory::get(POSTS_MEMORY_ID));
+        let legacy = PostV1 {
+            id: 7,
+            author: principal(1),
+            content: "hello".to_string(),
+            media_urls: Vec::new(),
+            created_at: 1,
+            updated_at: 1,
+            likes: vec![principal(2), principal(3)],
+            like_count: 2,
+        };
+        raw.insert(7, candid::encode_one(legacy).unwrap());
+
+        import_legacy();
+        assert!(is_liked(7, principal(2)));
+        assert!(is_liked(7, principal(3)));
+
+        let post = POSTS.with(|posts| posts.borrow().get(&7)).unwrap();
+        assert_eq!(post.like_count, 2);
+        assert_eq!(post.content, "hello");
+    }
+}
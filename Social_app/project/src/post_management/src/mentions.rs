@@ -0,0 +1,290 @@
+//! `@username` mentions. Usernames are resolved to principals through
+//! `user_management` whenever a post's content is written, so a mention keeps
+//! pointing at the same user if the name later changes hands.
+
+use crate::timeline::{decode_cursor, page_of, time_key};
+use crate::{canister_ids, MENTIONS_MEMORY_ID};
+use candid::Principal;
+use ic_cdk_macros::query;
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::{Mention, Post, PostError, UserSummary, Visibility};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use unicode_xid::UnicodeXID;
+
+/// Mentions past this many distinct usernames are left as plain text.
+const MAX_MENTIONED_USERS: usize = 20;
+
+thread_local! {
+    /// `(mentioned_user, created_at, post_id)`.
+    static MENTIONS: RefCell<StableBTreeMap<(Principal, u64, u64), (), Memory>> = memory::init_map(MENTIONS_MEMORY_ID);
+}
+
+/// An `@username` found in content, before it is known whether the user exists.
+struct Candidate {
+    username: String,
+    start: usize,
+    end: usize,
+}
+
+/// Every `@username` in `content`. An `@` only starts a mention at the beginning of a
+/// word, so addresses like `me@example.com` are left alone.
+fn scan(content: &str) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut in_word = false;
+    let mut chars = content.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == '@' && !in_word {
+            let mut end = start + c.len_utf8();
+            while let Some(&(offset, next)) = chars.peek().filter(|(_, next)| next.is_xid_continue()) {
+                end = offset + next.len_utf8();
+                chars.next();
+            }
+            let username = &content[start + 1..end];
+            if !username.is_empty() {
+                candidates.push(Candidate {
+                    username: username.to_string(),
+                    start,
+                    end,
+                });
+            }
+            in_word = !username.is_empty();
+        } else {
+            in_word = c.is_xid_continue();
+        }
+    }
+    candidates
+}
+
+/// Keeps the candidates whose name resolved to a user. `users` pairs each resolved
+/// name, exactly as it was queried, with its user, so `@Alice` and a name given up
+/// in a recent change still mention whoever it resolved to.
+fn link(candidates: Vec<Candidate>, users: Vec<(String, UserSummary)>) -> Vec<Mention> {
+    let by_username: BTreeMap<String, Principal> = users
+        .into_iter()
+        .map(|(username, user)| (username, user.principal))
+        .collect();
+    candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let user = *by_username.get(&candidate.username)?;
+            Some(Mention {
+                user,
+                username: candidate.username,
+                start: candidate.start as u64,
+                end: candidate.end as u64,
+            })
+        })
+        .collect()
+}
+
+/// The first `MAX_MENTIONED_USERS` distinct usernames among `candidates`.
+fn usernames_of(candidates: &[Candidate]) -> BTreeSet<String> {
+    let mut usernames = BTreeSet::new();
+    for candidate in candidates {
+        if usernames.len() == MAX_MENTIONED_USERS {
+            break;
+        }
+        usernames.insert(candidate.username.clone());
+    }
+    usernames
+}
+
+fn user_management_unavailable() -> PostError {
+    PostError::Unavailable {
+        canister: "user_management".to_string(),
+    }
+}
+
+/// Resolves the mentions in `content` with one call to `user_management`.
+async fn resolve(content: &str) -> Result<Vec<Mention>, PostError> {
+    let candidates = scan(content);
+    let usernames = usernames_of(&candidates);
+    if usernames.is_empty() {
+        return Ok(Vec::new());
+    }
+    let user_management = canister_ids()
+        .user_management
+        .ok_or_else(user_management_unavailable)?;
+
+    let usernames: Vec<String> = usernames.into_iter().collect();
+    let resolved: Result<(Vec<(String, UserSummary)>,), _> =
+        ic_cdk::call(user_management, "get_users_by_usernames", (usernames,)).await;
+    let (users,) = resolved.map_err(|_| user_management_unavailable())?;
+    Ok(link(candidates, users))
+}
+
+/// The mentions in `content` for a post with `visibility`. If `user_management` is
+/// unreachable, a direct post is rejected, since nobody could read it; any other post
+/// is stored without mentions.
+pub(crate) async fn resolve_for(content: &str, visibility: Visibility) -> Result<Vec<Mention>, PostError> {
+    match resolve(content).await {
+        Err(error) if visibility == Visibility::Direct => Err(error),
+        resolved => Ok(resolved.unwrap_or_default()),
+    }
+}
+
+fn mentioned_users(post: &Post) -> BTreeSet<Principal> {
+    post.mentions.iter().map(|mention| mention.user).collect()
+}
+
+pub(crate) fn insert(post: &Post) {
+    let (created_at, id) = time_key(post);
+    MENTIONS.with(|index| {
+        let mut index = index.borrow_mut();
+        for user in mentioned_users(post) {
+            index.insert((user, created_at, id), ());
+        }
+    });
+}
+
+pub(crate) fn remove(post: &Post) {
+    let (created_at, id) = time_key(post);
+    MENTIONS.with(|index| {
+        let mut index = index.borrow_mut();
+        for user in mentioned_users(post) {
+            index.remove(&(user, created_at, id));
+        }
+    });
+}
+
+/// One page of the posts that mention `user`, newest first.
+#[query]
+fn get_mentions(user: Principal, cursor: Option<Cursor>, limit: u64) -> Result<Page<Post>, PostError> {
+    mentions_page(ic_cdk::caller(), user, cursor.as_deref(), page_size(limit))
+}
+
+fn mentions_page(viewer: Principal, user: Principal, cursor: Option<&[u8]>, limit: usize) -> Result<Page<Post>, PostError> {
+    let before = cursor.map(decode_cursor).transpose()?;
+    let oldest = Bound::Included((user, 0, 0));
+    let newest = match before {
+        Some((created_at, id)) => Bound::Excluded((user, created_at, id)),
+        None => Bound::Included((user, u64::MAX, u64::MAX)),
+    };
+
+    Ok(MENTIONS.with(|index| {
+        let index = index.borrow();
+        let keys = index
+            .range((oldest, newest))
+            .rev()
+            .map(|((_, created_at, id), _)| (created_at, id));
+        page_of(keys, viewer, limit)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+    use crate::{insert_post, new_post};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// `user_management`'s answer for `queried`, which resolved to `principal` now
+    /// named `username`.
+    fn resolved(queried: &str, principal: Principal, username: &str) -> (String, UserSummary) {
+        let summary = UserSummary {
+            principal,
+            username: username.to_string(),
+            display_name: username.to_string(),
+            avatar_url: String::new(),
+            is_verified: false,
+        };
+        (queried.to_string(), summary)
+    }
+
+    /// Runs `future`, which must finish without awaiting a call.
+    fn run<F: Future>(future: F) -> F::Output {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("awaited a call"),
+        }
+    }
+
+    #[test]
+    fn links_known_usernames_with_byte_offsets() {
+        let content = "hé @alice, ping @Bob and @ghost; mail me@example.com";
+        let mentions = link(
+            scan(content),
+            vec![resolved("alice", principal(1), "alice"), resolved("Bob", principal(2), "bob")],
+        );
+
+        assert_eq!(mentions.len(), 2);
+        let alice = &mentions[0];
+        assert_eq!(alice.user, principal(1));
+        assert_eq!(&content[alice.start as usize..alice.end as usize], "@alice");
+        assert_eq!(&content[mentions[1].start as usize..mentions[1].end as usize], "@Bob");
+    }
+
+    #[test]
+    fn links_names_that_resolved_to_a_differently_named_user() {
+        let content = "@alice_old and @ａｌｉｃｅ";
+        let mentions = link(
+            scan(content),
+            vec![
+                resolved("alice_old", principal(1), "alice"),
+                resolved("ａｌｉｃｅ", principal(1), "alice"),
+            ],
+        );
+
+        assert_eq!(mentions.len(), 2);
+        assert!(mentions.iter().all(|mention| mention.user == principal(1)));
+        assert_eq!(mentions[0].username, "alice_old");
+    }
+
+    #[test]
+    fn caps_distinct_usernames_rather_than_mentions() {
+        let repeated = format!("{}@b", "@a ".repeat(MAX_MENTIONED_USERS));
+        let usernames: Vec<String> = usernames_of(&scan(&repeated)).into_iter().collect();
+        assert_eq!(usernames, vec!["a", "b"]);
+
+        let many: String = (0..MAX_MENTIONED_USERS + 5).map(|n| format!("@user{n} ")).collect();
+        assert_eq!(usernames_of(&scan(&many)).len(), MAX_MENTIONED_USERS);
+    }
+
+    #[test]
+    fn lists_posts_mentioning_a_user() {
+        let alice = principal(1);
+        let mention = |start, end| Mention {
+            user: alice,
+            username: "alice".to_string(),
+            start,
+            end,
+        };
+        let first = insert_post(Post {
+            mentions: vec![mention(0, 6)],
+            ..new_post(principal(2), "@alice hi".to_string(), Vec::new(), 10)
+        });
+        let second = insert_post(Post {
+            // Mentioning someone twice lists the post once.
+            mentions: vec![mention(0, 6), mention(7, 13)],
+            ..new_post(principal(3), "@alice @alice".to_string(), Vec::new(), 20)
+        });
+
+        let page = mentions_page(Principal::anonymous(), alice, None, 1).unwrap();
+        assert_eq!(page.items[0].id, second.id);
+        let page = mentions_page(Principal::anonymous(), alice, page.next_cursor.as_deref(), 1).unwrap();
+        assert_eq!(page.items[0].id, first.id);
+        assert!(page.next_cursor.is_none());
+
+        remove(&first);
+        assert_eq!(mentions_page(Principal::anonymous(), alice, None, 10).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn rejects_direct_posts_whose_mentions_cannot_be_resolved() {
+        // No `user_management` is configured in tests.
+        assert_eq!(
+            run(resolve_for("hi @alice", Visibility::Direct)),
+            Err(PostError::Unavailable {
+                canister: "user_management".to_string()
+            })
+        );
+        assert_eq!(run(resolve_for("hi @alice", Visibility::Public)), Ok(Vec::new()));
+        assert_eq!(run(resolve_for("hi all", Visibility::Direct)), Ok(Vec::new()));
+    }
+}
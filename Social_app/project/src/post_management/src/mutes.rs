@@ -0,0 +1,348 @@
+//! Muting. A user can mute accounts, for good or for a while, and words. Posts by a
+//! muted account, reposts of its posts and posts containing a muted word are left out
+//! of the muter's feeds: `get_posts_by_users`, the home feed and search. Mutes are
+//! private to the muter; nobody is told, and follow edges in `social_graph` stay as
+//! they are.
+
+use crate::{search, MUTED_WORDS_MEMORY_ID, MUTES_MEMORY_ID, MUTE_EXPIRIES_MEMORY_ID, POSTS};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::StableBTreeMap;
+use serde::{Deserialize, Serialize};
+use social_types::guards::authenticated_caller;
+use social_types::memory::{self, Memory};
+use social_types::timer;
+use social_types::versioned::Versioned;
+use social_types::{versioned_storable, Post, PostError};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+/// Accounts one user may have muted at a time.
+const MAX_MUTED_USERS: usize = 1_000;
+
+/// Words one user may have muted at a time.
+const MAX_MUTED_WORDS: usize = 100;
+
+/// An account the caller muted.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Mute {
+    pub user: Principal,
+    pub muted_at: u64,
+    /// When the mute lifts by itself. `None` mutes until `unmute_user`.
+    pub expires_at: Option<u64>,
+}
+
+impl Versioned for Mute {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(Mute);
+
+/// A user's muted words, each stored as its case-folded terms joined by spaces.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+struct MutedWords(Vec<String>);
+
+impl Versioned for MutedWords {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(MutedWords);
+
+thread_local! {
+    /// `(muter, muted)` to the mute.
+    static MUTES: RefCell<StableBTreeMap<(Principal, Principal), Mute, Memory>> = memory::init_map(MUTES_MEMORY_ID);
+    /// `(expires_at, muter, muted)` for every time-limited mute, soonest first.
+    static MUTE_EXPIRIES: RefCell<StableBTreeMap<(u64, Principal, Principal), (), Memory>> = memory::init_map(MUTE_EXPIRIES_MEMORY_ID);
+    static MUTED_WORDS: RefCell<StableBTreeMap<Principal, MutedWords, Memory>> = memory::init_map(MUTED_WORDS_MEMORY_ID);
+}
+
+/// Everything one viewer muted and that has not run out, loaded once per listing.
+pub(crate) struct Muted {
+    users: BTreeSet<Principal>,
+    words: Vec<Vec<String>>,
+}
+
+impl Muted {
+    /// What `viewer` muted as of `now`. A mute that ran out is ignored even before the
+    /// timer lifts it.
+    pub(crate) fn of(viewer: Principal, now: u64) -> Self {
+        let users = mutes_of(viewer)
+            .into_iter()
+            .filter(|mute| mute.expires_at.is_none_or(|expires_at| expires_at > now))
+            .map(|mute| mute.user)
+            .collect();
+        let words = MUTED_WORDS
+            .with(|words| words.borrow().get(&viewer))
+            .unwrap_or_default()
+            .0
+            .iter()
+            .map(|word| word.split(' ').map(str::to_string).collect())
+            .collect();
+        Muted { users, words }
+    }
+
+    pub(crate) fn user(&self, user: Principal) -> bool {
+        self.users.contains(&user)
+    }
+
+    /// Whether `post` is by a muted account, shares a post by one, or contains a muted
+    /// word. A repost is judged by what it shares.
+    pub(crate) fn hides(&self, post: &Post) -> bool {
+        if self.users.is_empty() && self.words.is_empty() {
+            return false;
+        }
+        if self.user(post.author) {
+            return true;
+        }
+        let original = post
+            .repost_of
+            .and_then(|original_id| POSTS.with(|posts| posts.borrow().get(&original_id)));
+        let shown = original.as_ref().unwrap_or(post);
+        if self.user(shown.author) {
+            return true;
+        }
+        let terms = search::terms_of(&shown.content);
+        self.words
+            .iter()
+            .any(|word| terms.windows(word.len()).any(|window| window == word.as_slice()))
+    }
+}
+
+fn mutes_of(muter: Principal) -> Vec<Mute> {
+    MUTES.with(|mutes| {
+        mutes
+            .borrow()
+            .range((muter, Principal::management_canister())..)
+            .take_while(|((owner, _), _)| *owner == muter)
+            .map(|(_, mute)| mute)
+            .collect()
+    })
+}
+
+/// Mutes `user` for `muter` at `now`, for `duration` nanoseconds or for good. Muting
+/// again replaces the earlier mute.
+fn mute(muter: Principal, user: Principal, duration: Option<u64>, now: u64) -> Result<Mute, PostError> {
+    if muter == user {
+        return Err(PostError::CannotMuteSelf);
+    }
+    let previous = MUTES.with(|mutes| mutes.borrow().get(&(muter, user)));
+    match &previous {
+        Some(Mute {
+            expires_at: Some(expires_at),
+            ..
+        }) => {
+            MUTE_EXPIRIES.with(|expiries| expiries.borrow_mut().remove(&(*expires_at, muter, user)));
+        }
+        Some(_) => {}
+        None if mutes_of(muter).len() >= MAX_MUTED_USERS => {
+            return Err(PostError::TooManyMutes {
+                max: MAX_MUTED_USERS as u32,
+            });
+        }
+        None => {}
+    }
+
+    let mute = Mute {
+        user,
+        muted_at: now,
+        expires_at: duration.map(|duration| now.saturating_add(duration)),
+    };
+    MUTES.with(|mutes| mutes.borrow_mut().insert((muter, user), mute.clone()));
+    if let Some(expires_at) = mute.expires_at {
+        MUTE_EXPIRIES.with(|expiries| expiries.borrow_mut().insert((expires_at, muter, user), ()));
+    }
+    Ok(mute)
+}
+
+/// Lifts `muter`'s mute of `user`. Returns `false` if there was none.
+fn unmute(muter: Principal, user: Principal) -> bool {
+    let Some(mute) = MUTES.with(|mutes| mutes.borrow_mut().remove(&(muter, user))) else {
+        return false;
+    };
+    if let Some(expires_at) = mute.expires_at {
+        MUTE_EXPIRIES.with(|expiries| expiries.borrow_mut().remove(&(expires_at, muter, user)));
+    }
+    true
+}
+
+/// Lifts every mute that ran out by `now`.
+pub(crate) fn expire(now: u64) {
+    let expired: Vec<(Principal, Principal)> = MUTE_EXPIRIES.with(|expiries| {
+        expiries
+            .borrow()
+            .iter()
+            .take_while(|((expires_at, _, _), _)| *expires_at <= now)
+            .map(|((_, muter, muted), _)| (muter, muted))
+            .collect()
+    });
+    for (muter, muted) in expired {
+        unmute(muter, muted);
+    }
+}
+
+/// Arms the global timer for the next mute to run out, if any.
+pub(crate) fn schedule_expiry() {
+    let next = MUTE_EXPIRIES.with(|expiries| expiries.borrow().first_key_value());
+    if let Some(((deadline, _, _), _)) = next {
+        timer::arm(deadline);
+    }
+}
+
+/// Drops up to `limit` of the mutes `user` made, and their muted words. Returns how
+/// many mutes were dropped.
+pub(crate) fn forget(user: Principal, limit: usize) -> usize {
+    MUTED_WORDS.with(|words| words.borrow_mut().remove(&user));
+    let muted: Vec<Principal> = mutes_of(user).into_iter().take(limit).map(|mute| mute.user).collect();
+    for &other in &muted {
+        unmute(user, other);
+    }
+    muted.len()
+}
+
+/// Case-folds `words` into the terms they match on, dropping duplicates and words
+/// without any letters or digits.
+fn normalize_words(words: Vec<String>) -> Result<Vec<String>, PostError> {
+    let mut normalized = Vec::new();
+    for word in words {
+        let terms = search::terms_of(&word);
+        if terms.is_empty() {
+            continue;
+        }
+        let word = terms.join(" ");
+        if !normalized.contains(&word) {
+            normalized.push(word);
+        }
+    }
+    if normalized.len() > MAX_MUTED_WORDS {
+        return Err(PostError::TooManyMutedWords {
+            max: MAX_MUTED_WORDS as u32,
+        });
+    }
+    Ok(normalized)
+}
+
+fn store_words(user: Principal, words: Vec<String>) {
+    MUTED_WORDS.with(|muted| {
+        let mut muted = muted.borrow_mut();
+        if words.is_empty() {
+            muted.remove(&user);
+        } else {
+            muted.insert(user, MutedWords(words));
+        }
+    });
+}
+
+/// Hides `user`'s posts, and reposts of them, from the caller's feeds and search, for
+/// `duration` nanoseconds or until unmuted. `user` is not told.
+#[update]
+fn mute_user(user: Principal, duration: Option<u64>) -> Result<Mute, PostError> {
+    let caller = authenticated_caller()?;
+    let mute = mute(caller, user, duration, time())?;
+    schedule_expiry();
+    Ok(mute)
+}
+
+#[update]
+fn unmute_user(user: Principal) -> Result<(), PostError> {
+    let caller = authenticated_caller()?;
+    if !unmute(caller, user) {
+        return Err(PostError::NotMuted { user });
+    }
+    Ok(())
+}
+
+/// The accounts the caller muted.
+#[query]
+fn get_muted() -> Result<Vec<Mute>, PostError> {
+    let caller = authenticated_caller()?;
+    Ok(mutes_of(caller))
+}
+
+/// Replaces the caller's muted words. Words match case-insensitively as whole words,
+/// and a word of several terms matches them in order. Returns the words as stored.
+#[update]
+fn set_muted_words(words: Vec<String>) -> Result<Vec<String>, PostError> {
+    let caller = authenticated_caller()?;
+    let words = normalize_words(words)?;
+    store_words(caller, words.clone());
+    Ok(words)
+}
+
+#[query]
+fn get_muted_words() -> Result<Vec<String>, PostError> {
+    let caller = authenticated_caller()?;
+    Ok(MUTED_WORDS
+        .with(|muted| muted.borrow().get(&caller))
+        .unwrap_or_default()
+        .0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::{insert_post, new_post, timeline};
+
+    fn ids(viewer: Principal, authors: &BTreeSet<Principal>) -> Vec<u64> {
+        let page = timeline::by_authors(viewer, authors, None, 10, 0).unwrap();
+        page.items.iter().map(|post| post.id).collect()
+    }
+
+    #[test]
+    fn muted_accounts_and_words_leave_the_feed() {
+        let (viewer, noisy, friend) = (principal(1), principal(2), principal(3));
+        let loud = insert_new_post(noisy, "so loud", 1);
+        let spoiler = insert_new_post(friend, "The Finale was great", 2);
+        let calm = insert_new_post(friend, "calm day", 3);
+        let repost = insert_post(Post {
+            repost_of: Some(loud.id),
+            ..new_post(friend, String::new(), Vec::new(), 4)
+        });
+        for post in [&loud, &spoiler, &calm, &repost] {
+            timeline::insert(post);
+        }
+        let authors: BTreeSet<Principal> = [noisy, friend].into_iter().collect();
+
+        mute(viewer, noisy, None, 0).unwrap();
+        let words = normalize_words(vec!["#finale".to_string(), "FINALE".to_string(), "!".to_string()]).unwrap();
+        assert_eq!(words, vec!["finale"]);
+        store_words(viewer, words);
+        assert_eq!(ids(viewer, &authors), vec![calm.id]);
+        // The muted account, and everyone else, still see everything.
+        assert_eq!(ids(noisy, &authors).len(), 4);
+
+        assert!(unmute(viewer, noisy));
+        assert!(!unmute(viewer, noisy));
+        assert_eq!(ids(viewer, &authors), vec![repost.id, calm.id, loud.id]);
+    }
+
+    #[test]
+    fn timed_mutes_expire() {
+        let (muter, muted) = (principal(1), principal(2));
+        assert_eq!(mute(muter, muter, None, 0), Err(PostError::CannotMuteSelf));
+        mute(muter, muted, Some(10), 100).unwrap();
+        // Muting again replaces the deadline.
+        mute(muter, muted, Some(50), 100).unwrap();
+
+        expire(149);
+        assert!(Muted::of(muter, 149).user(muted));
+        // Ran out, though the timer has not lifted it yet.
+        assert!(!Muted::of(muter, 150).user(muted));
+        expire(150);
+        assert!(!Muted::of(muter, 149).user(muted));
+        assert!(MUTE_EXPIRIES.with(|expiries| expiries.borrow().is_empty()));
+    }
+
+    #[test]
+    fn limits_muted_words() {
+        let words = (0..=MAX_MUTED_WORDS).map(|n| format!("word{n}")).collect();
+        assert_eq!(
+            normalize_words(words),
+            Err(PostError::TooManyMutedWords {
+                max: MAX_MUTED_WORDS as u32
+            })
+        );
+        assert_eq!(normalize_words(vec!["Two  Words".to_string()]).unwrap(), vec!["two words"]);
+    }
+}
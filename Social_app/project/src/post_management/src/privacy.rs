@@ -0,0 +1,192 @@
+//! Who may see which posts. A post's visibility decides who it is meant for; on top of
+//! that, private accounts show their posts only to the author and their followers, and
+//! nobody sees the posts of an account that blocked them.
+//!
+//! `social_graph` owns follow relationships and mirrors here which accounts are private
+//! and who follows whom, so listings can filter posts without a call per query.
+
+use crate::{blocks, canister_ids, hashtags, FOLLOWER_SNAPSHOT_MEMORY_ID, POSTS, PRIVATE_ACCOUNTS_MEMORY_ID};
+use candid::Principal;
+use ic_cdk_macros::update;
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use social_types::{Post, Visibility};
+use std::cell::RefCell;
+
+thread_local! {
+    static PRIVATE_ACCOUNTS: RefCell<StableBTreeMap<Principal, (), Memory>> = memory::init_map(PRIVATE_ACCOUNTS_MEMORY_ID);
+    /// `(account, follower)` for every follow edge in `social_graph`.
+    static FOLLOWERS: RefCell<StableBTreeMap<(Principal, Principal), (), Memory>> = memory::init_map(FOLLOWER_SNAPSHOT_MEMORY_ID);
+}
+
+pub(crate) fn caller_is_social_graph() -> Result<(), String> {
+    if canister_ids().social_graph == Some(ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("only social_graph can call this method".to_string())
+    }
+}
+
+pub(crate) fn is_private(user: Principal) -> bool {
+    PRIVATE_ACCOUNTS.with(|accounts| accounts.borrow().contains_key(&user))
+}
+
+fn is_following(follower: Principal, account: Principal) -> bool {
+    FOLLOWERS.with(|followers| followers.borrow().contains_key(&(account, follower)))
+}
+
+/// Whether `viewer` may see what `author` posts, visibility aside.
+pub(crate) fn can_view(viewer: Principal, author: Principal) -> bool {
+    if viewer == author {
+        return true;
+    }
+    !blocks::is_blocked(author, viewer) && (!is_private(author) || is_following(viewer, author))
+}
+
+/// Whether `post` itself is meant for `viewer`, whatever it shares.
+fn can_read(viewer: Principal, post: &Post) -> bool {
+    if viewer == post.author {
+        return true;
+    }
+    can_view(viewer, post.author)
+        && match post.visibility {
+            Visibility::Public | Visibility::Unlisted => true,
+            Visibility::FollowersOnly => is_following(viewer, post.author),
+            Visibility::Direct => post.mentions.iter().any(|mention| mention.user == viewer),
+        }
+}
+
+/// Whether `viewer` may see `post`. A repost is only as visible as what it shares, and
+/// a repost of a deleted post is not visible at all.
+pub(crate) fn can_see(viewer: Principal, post: &Post) -> bool {
+    if !can_read(viewer, post) {
+        return false;
+    }
+    match post.repost_of {
+        Some(original_id) => POSTS
+            .with(|posts| posts.borrow().get(&original_id))
+            .is_some_and(|original| can_read(viewer, &original)),
+        None => true,
+    }
+}
+
+pub(crate) fn set_private(user: Principal, private: bool) {
+    PRIVATE_ACCOUNTS.with(|accounts| {
+        let mut accounts = accounts.borrow_mut();
+        if private {
+            accounts.insert(user, ());
+        } else {
+            accounts.remove(&user);
+        }
+    });
+}
+
+pub(crate) fn add_followers(user: Principal, followers: Vec<Principal>) {
+    FOLLOWERS.with(|snapshot| {
+        let mut snapshot = snapshot.borrow_mut();
+        for follower in followers {
+            snapshot.insert((user, follower), ());
+        }
+    });
+}
+
+/// Drops up to `limit` of the snapshot entries for `user`'s followers, and their privacy
+/// setting along with the last of them. Returns how many entries were dropped.
+pub(crate) fn forget(user: Principal, limit: usize) -> usize {
+    let dropped = FOLLOWERS.with(|followers| {
+        let mut followers = followers.borrow_mut();
+        let stale: Vec<(Principal, Principal)> = followers
+            .range((user, Principal::management_canister())..)
+            .take_while(|((account, _), _)| *account == user)
+            .take(limit)
+            .map(|(key, _)| key)
+            .collect();
+        for key in &stale {
+            followers.remove(key);
+        }
+        stale.len()
+    });
+    if dropped == 0 {
+        set_private(user, false);
+    }
+    dropped
+}
+
+/// Called by `social_graph` when `user` turns private or public.
+#[update(guard = "caller_is_social_graph")]
+fn account_privacy_changed(user: Principal, is_private: bool) {
+    set_private(user, is_private);
+    hashtags::queue_reindex(user);
+    hashtags::schedule_reindex();
+}
+
+/// Called by `social_graph` when `user` gains followers, and in batches to fill the
+/// snapshot with follows made before it existed.
+#[update(guard = "caller_is_social_graph")]
+fn followers_added(user: Principal, followers: Vec<Principal>) {
+    add_followers(user, followers);
+}
+
+/// Called by `social_graph` when `user` loses a follower.
+#[update(guard = "caller_is_social_graph")]
+fn follower_removed(user: Principal, follower: Principal) {
+    FOLLOWERS.with(|followers| followers.borrow_mut().remove(&(user, follower)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+    use crate::new_post;
+    use social_types::Mention;
+
+    #[test]
+    fn private_accounts_are_visible_to_followers_only() {
+        let (owner, follower, stranger) = (principal(1), principal(2), principal(3));
+        assert!(can_view(stranger, owner));
+
+        set_private(owner, true);
+        add_followers(owner, vec![follower]);
+        assert!(can_view(owner, owner));
+        assert!(can_view(follower, owner));
+        assert!(!can_view(stranger, owner));
+
+        follower_removed(owner, follower);
+        assert!(!can_view(follower, owner));
+
+        // The snapshot covers public accounts too, so it survives going public and back.
+        add_followers(owner, vec![follower]);
+        set_private(owner, false);
+        assert!(can_view(stranger, owner));
+        set_private(owner, true);
+        assert!(can_view(follower, owner));
+
+        assert_eq!(forget(owner, 10), 1);
+        assert_eq!(forget(owner, 10), 0);
+        assert!(!is_private(owner));
+    }
+
+    #[test]
+    fn visibility_decides_who_reads_a_post() {
+        let (author, follower, mentioned, stranger) = (principal(1), principal(2), principal(3), principal(4));
+        add_followers(author, vec![follower]);
+        let readers = |visibility| {
+            let post = Post {
+                visibility,
+                mentions: vec![Mention {
+                    user: mentioned,
+                    username: "mentioned".to_string(),
+                    start: 0,
+                    end: 10,
+                }],
+                ..new_post(author, "@mentioned hi".to_string(), Vec::new(), 0)
+            };
+            [author, follower, mentioned, stranger].map(|viewer| can_see(viewer, &post))
+        };
+
+        assert_eq!(readers(Visibility::Public), [true; 4]);
+        assert_eq!(readers(Visibility::Unlisted), [true; 4]);
+        assert_eq!(readers(Visibility::FollowersOnly), [true, true, false, false]);
+        assert_eq!(readers(Visibility::Direct), [true, false, true, false]);
+    }
+}
@@ -0,0 +1,285 @@
+//! Replies are posts with a `parent_post_id`. They live in `POSTS` next to top-level
+//! posts, so editing, deleting and liking them goes through the usual endpoints, but
+//! they stay out of the timelines and are indexed under their parent instead.
+
+use crate::timeline::{decode_cursor, page_where, time_key};
+use crate::{
+    authors, insert_post, mentions, new_post, privacy, stored_post, validate_content, visible_post, CreatePostRequest,
+    POSTS, REPLIES_MEMORY_ID,
+};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::StableBTreeMap;
+use serde::{Deserialize, Serialize};
+use social_types::guards::authenticated_caller;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page, MAX_PAGE_SIZE};
+use social_types::{Post, PostError};
+use std::cell::RefCell;
+use std::ops::Bound;
+
+thread_local! {
+    /// `(parent_post_id, created_at, reply_id)`: replies in the order they were written.
+    static REPLIES: RefCell<StableBTreeMap<(u64, u64, u64), (), Memory>> = memory::init_map(REPLIES_MEMORY_ID);
+}
+
+/// A post in context: the chain of posts it answers, root first, and the first page
+/// of its direct replies.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Thread {
+    /// At most `MAX_PAGE_SIZE` ancestors, nearest last. Stops early at a deleted one.
+    pub ancestors: Vec<Post>,
+    pub post: Post,
+    pub replies: Page<Post>,
+}
+
+/// Removes a deleted reply from its parent's listing and count. Takes the borrowed
+/// `POSTS` map because `delete_post` already holds it.
+pub(crate) fn remove(posts: &mut StableBTreeMap<u64, Post, Memory>, reply: &Post) {
+    let Some(parent_id) = reply.parent_post_id else {
+        return;
+    };
+    let (created_at, id) = time_key(reply);
+    REPLIES.with(|replies| replies.borrow_mut().remove(&(parent_id, created_at, id)));
+    if let Some(mut parent) = posts.get(&parent_id) {
+        parent.reply_count = parent.reply_count.saturating_sub(1);
+        posts.insert(parent_id, parent);
+    }
+}
+
+/// Drops up to `limit` entries of a deleted post's reply listing. The replies themselves
+/// stay, still answering the deleted post, and their threads start after it. Returns
+/// how many entries were dropped.
+pub(crate) fn forget_thread(post_id: u64, limit: usize) -> usize {
+    REPLIES.with(|replies| {
+        let mut replies = replies.borrow_mut();
+        let keys: Vec<(u64, u64, u64)> = replies
+            .range((post_id, 0, 0)..)
+            .take_while(|((parent_id, _, _), _)| *parent_id == post_id)
+            .take(limit)
+            .map(|(key, _)| key)
+            .collect();
+        for key in &keys {
+            replies.remove(key);
+        }
+        keys.len()
+    })
+}
+
+/// Direct replies to `post_id` written after `cursor`, oldest first. Hidden replies,
+/// and replies `viewer` may not see, are skipped; a page stops early, with a cursor,
+/// once it has read `MAX_PAGE_SCAN` of them.
+fn replies_page(viewer: Principal, post_id: u64, cursor: Option<&[u8]>, limit: usize) -> Result<Page<Post>, PostError> {
+    let after = cursor.map(decode_cursor).transpose()?;
+    let start = match after {
+        Some((created_at, id)) => Bound::Excluded((post_id, created_at, id)),
+        None => Bound::Included((post_id, 0, 0)),
+    };
+
+    Ok(REPLIES.with(|replies| {
+        let replies = replies.borrow();
+        let in_thread = replies
+            .range((start, Bound::Unbounded))
+            .take_while(|((parent_id, _, _), _)| *parent_id == post_id)
+            .map(|((_, created_at, id), _)| (created_at, id));
+        page_where(in_thread, limit, |reply| !reply.hidden && privacy::can_see(viewer, reply))
+    }))
+}
+
+#[update]
+async fn create_reply(post_id: u64, request: CreatePostRequest) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
+    validate_content(&request.content)?;
+    visible_post(caller, post_id)?;
+    let visibility = request.visibility.unwrap_or_default();
+    let mentions = mentions::resolve_for(&request.content, visibility).await?;
+    authors::warm(caller).await;
+    let parent = visible_post(caller, post_id)?;
+
+    let reply = insert_post(Post {
+        parent_post_id: Some(parent.id),
+        reply_to: Some(parent.author),
+        mentions,
+        visibility,
+        ..new_post(caller, request.content, request.media_urls, time())
+    });
+    REPLIES.with(|replies| {
+        let (created_at, id) = time_key(&reply);
+        replies.borrow_mut().insert((post_id, created_at, id), ());
+    });
+    POSTS.with(|posts| {
+        let mut posts = posts.borrow_mut();
+        if let Some(mut parent) = posts.get(&post_id) {
+            parent.reply_count += 1;
+            posts.insert(post_id, parent);
+        }
+    });
+    Ok(reply)
+}
+
+/// One page of the direct replies to `post_id`, oldest first.
+#[query]
+fn get_replies(post_id: u64, cursor: Option<Cursor>, limit: u64) -> Result<Page<Post>, PostError> {
+    visible_replies(ic_cdk::caller(), post_id, cursor.as_deref(), page_size(limit))
+}
+
+fn visible_replies(viewer: Principal, post_id: u64, cursor: Option<&[u8]>, limit: usize) -> Result<Page<Post>, PostError> {
+    visible_post(viewer, post_id)?;
+    replies_page(viewer, post_id, cursor, limit)
+}
+
+/// `post_id` with the posts it answers and its first page of replies.
+#[query]
+fn get_thread(post_id: u64) -> Result<Thread, PostError> {
+    thread(ic_cdk::caller(), post_id)
+}
+
+/// Ancestors `viewer` may not see end the chain as if they were deleted.
+fn thread(viewer: Principal, post_id: u64) -> Result<Thread, PostError> {
+    let post = visible_post(viewer, post_id)?;
+
+    let mut ancestors = Vec::new();
+    let mut next = post.parent_post_id;
+    while let Some(parent_id) = next.filter(|_| ancestors.len() < MAX_PAGE_SIZE as usize) {
+        let Ok(parent) = visible_post(viewer, parent_id) else {
+            break;
+        };
+        next = parent.parent_post_id;
+        ancestors.push(parent);
+    }
+    ancestors.reverse();
+
+    let replies = replies_page(viewer, post_id, None, page_size(MAX_PAGE_SIZE))?;
+    Ok(Thread {
+        ancestors,
+        post,
+        replies,
+    })
+}
+
+/// Hides or unhides a reply. Only the author of the post it answers may do this.
+#[update]
+fn set_reply_hidden(reply_id: u64, hidden: bool) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
+    let mut reply = stored_post(reply_id)?;
+
+    let Some(parent_id) = reply.parent_post_id else {
+        return Err(PostError::NotAReply { post_id: reply_id });
+    };
+    if reply.reply_to != Some(caller) {
+        return Err(PostError::NotAuthor { post_id: parent_id });
+    }
+
+    reply.hidden = hidden;
+    POSTS.with(|posts| posts.borrow_mut().insert(reply_id, reply.clone()));
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::timeline::MAX_PAGE_SCAN;
+    use crate::{cleanup, remove_post};
+
+    /// Stores a reply the way `create_reply` does, without needing a caller.
+    fn reply(parent_id: u64, author: Principal, now: u64) -> Post {
+        let parent = stored_post(parent_id).unwrap();
+        let reply = insert_post(Post {
+            parent_post_id: Some(parent.id),
+            reply_to: Some(parent.author),
+            ..new_post(author, "reply".to_string(), Vec::new(), now)
+        });
+        REPLIES.with(|replies| {
+            let (created_at, id) = time_key(&reply);
+            replies.borrow_mut().insert((parent_id, created_at, id), ());
+        });
+        reply
+    }
+
+    fn ids(page: &Page<Post>) -> Vec<u64> {
+        page.items.iter().map(|post| post.id).collect()
+    }
+
+    #[test]
+    fn threads_show_ancestors_and_direct_replies() {
+        let root = insert_new_post(principal(1), "root", 1);
+        let first = reply(root.id, principal(2), 3);
+        let nested = reply(first.id, principal(3), 4);
+        let second = reply(root.id, principal(4), 5);
+
+        assert_eq!(first.parent_post_id, Some(root.id));
+        assert_eq!(first.reply_to, Some(principal(1)));
+
+        let thread = thread(Principal::anonymous(), nested.id).unwrap();
+        let ancestors: Vec<u64> = thread.ancestors.iter().map(|post| post.id).collect();
+        assert_eq!(ancestors, vec![root.id, first.id]);
+        assert!(thread.replies.items.is_empty());
+
+        let page = visible_replies(Principal::anonymous(), root.id, None, 1).unwrap();
+        assert_eq!(ids(&page), vec![first.id]);
+        let page = visible_replies(Principal::anonymous(), root.id, page.next_cursor.as_deref(), 1).unwrap();
+        assert_eq!(ids(&page), vec![second.id]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn hidden_and_deleted_replies_leave_the_listing() {
+        let root = insert_new_post(principal(1), "root", 1);
+        let hidden = reply(root.id, principal(2), 6);
+        let deleted = reply(root.id, principal(3), 7);
+        let kept = reply(root.id, principal(4), 8);
+        POSTS.with(|posts| {
+            let mut posts = posts.borrow_mut();
+            let mut root = posts.get(&root.id).unwrap();
+            root.reply_count = 3;
+            posts.insert(root.id, root);
+
+            let mut reply = posts.get(&hidden.id).unwrap();
+            reply.hidden = true;
+            posts.insert(hidden.id, reply);
+
+            posts.remove(&deleted.id);
+            remove(&mut posts, &deleted);
+        });
+
+        assert_eq!(ids(&visible_replies(Principal::anonymous(), root.id, None, 10).unwrap()), vec![kept.id]);
+        assert_eq!(stored_post(root.id).unwrap().reply_count, 2);
+    }
+
+    #[test]
+    fn deleting_a_post_drops_its_reply_listing() {
+        let root = insert_new_post(principal(1), "root", 1);
+        let first = reply(root.id, principal(2), 2);
+        let second = reply(root.id, principal(3), 3);
+
+        POSTS.with(|posts| remove_post(&mut posts.borrow_mut(), &root));
+        cleanup::sweep();
+
+        assert!(REPLIES.with(|replies| replies.borrow().is_empty()));
+        let thread = thread(Principal::anonymous(), second.id).unwrap();
+        assert!(thread.ancestors.is_empty());
+        assert_eq!(stored_post(first.id).unwrap().parent_post_id, Some(root.id));
+    }
+
+    #[test]
+    fn pages_stop_with_a_cursor_after_too_many_hidden_replies() {
+        let root = insert_new_post(principal(1), "root", 1);
+        let hidden: Vec<Post> = (0..MAX_PAGE_SCAN as u64).map(|n| reply(root.id, principal(2), 2 + n)).collect();
+        let kept = reply(root.id, principal(3), u64::MAX);
+        POSTS.with(|posts| {
+            let mut posts = posts.borrow_mut();
+            for reply in &hidden {
+                posts.insert(reply.id, Post { hidden: true, ..reply.clone() });
+            }
+        });
+
+        let page = visible_replies(Principal::anonymous(), root.id, None, 10).unwrap();
+        assert!(page.items.is_empty());
+        assert!(page.next_cursor.is_some());
+        let page = visible_replies(Principal::anonymous(), root.id, page.next_cursor.as_deref(), 10).unwrap();
+        assert_eq!(ids(&page), vec![kept.id]);
+        assert!(page.next_cursor.is_none());
+    }
+}
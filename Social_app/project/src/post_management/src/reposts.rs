@@ -0,0 +1,234 @@
+//! Reposts and quote posts. A repost is a post of its own with `repost_of` set and no
+//! content, so it shows up in the reposter's timeline and their followers' home feeds
+//! like anything else they write. A quote is an ordinary post with `quote_of` set.
+
+use crate::{
+    authors, cleanup, insert_post, mentions, new_post, remove_post, timeline, validate_content, visible_post, POSTS,
+    REPOSTS_MEMORY_ID,
+};
+use candid::Principal;
+use ic_cdk::api::time;
+use ic_cdk_macros::update;
+use ic_stable_structures::StableBTreeMap;
+use social_types::guards::authenticated_caller;
+use social_types::memory::{self, Memory};
+use social_types::{Post, PostError, Visibility};
+use std::cell::RefCell;
+
+thread_local! {
+    /// `(original_id, reposter)` to the ID of the repost.
+    static REPOSTS: RefCell<StableBTreeMap<(u64, Principal), u64, Memory>> = memory::init_map(REPOSTS_MEMORY_ID);
+}
+
+/// The post that sharing `post_id` actually shares: a repost stands in for its original.
+/// Only public and unlisted posts `viewer` may see can be shared.
+fn shared_post(viewer: Principal, post_id: u64) -> Result<Post, PostError> {
+    let post = visible_post(viewer, post_id)?;
+    let original = match post.repost_of {
+        Some(original_id) => visible_post(viewer, original_id)?,
+        None => post,
+    };
+    match original.visibility {
+        Visibility::Public | Visibility::Unlisted => Ok(original),
+        Visibility::FollowersOnly | Visibility::Direct => Err(PostError::NotShareable { post_id: original.id }),
+    }
+}
+
+fn update_counts(post_id: u64, change: impl FnOnce(&mut Post)) {
+    POSTS.with(|posts| {
+        let mut posts = posts.borrow_mut();
+        if let Some(mut post) = posts.get(&post_id) {
+            change(&mut post);
+            posts.insert(post_id, post);
+        }
+    });
+}
+
+/// Cleans up after a deleted post: releases the repost or quote it made. Reposts of it
+/// are left to `forget_reposts`; quotes of it are kept and simply point at a post that
+/// no longer exists. Takes the borrowed `POSTS` map because `delete_post` already
+/// holds it.
+pub(crate) fn remove(posts: &mut StableBTreeMap<u64, Post, Memory>, post: &Post) {
+    if let Some(original_id) = post.repost_of {
+        REPOSTS.with(|reposts| reposts.borrow_mut().remove(&(original_id, post.author)));
+        if let Some(mut original) = posts.get(&original_id) {
+            original.repost_count = original.repost_count.saturating_sub(1);
+            posts.insert(original_id, original);
+        }
+    }
+    if let Some(quoted_id) = post.quote_of {
+        if let Some(mut quoted) = posts.get(&quoted_id) {
+            quoted.quote_count = quoted.quote_count.saturating_sub(1);
+            posts.insert(quoted_id, quoted);
+        }
+    }
+}
+
+/// Deletes up to `limit` of the reposts of a deleted post, which have nothing left to
+/// show. Returns how many were deleted.
+pub(crate) fn forget_reposts(posts: &mut StableBTreeMap<u64, Post, Memory>, post_id: u64, limit: usize) -> usize {
+    let reposts: Vec<((u64, Principal), u64)> = REPOSTS.with(|reposts| {
+        reposts
+            .borrow()
+            .range((post_id, Principal::management_canister())..)
+            .take_while(|((original_id, _), _)| *original_id == post_id)
+            .take(limit)
+            .collect()
+    });
+    for (key, repost_id) in &reposts {
+        REPOSTS.with(|reposts| reposts.borrow_mut().remove(key));
+        if let Some(repost) = posts.get(repost_id) {
+            remove_post(posts, &repost);
+        }
+    }
+    reposts.len()
+}
+
+/// Shares `post_id` to the caller's followers. Reposting a repost shares its original.
+#[update]
+fn repost(post_id: u64) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
+    let original = shared_post(caller, post_id)?;
+
+    if REPOSTS.with(|reposts| reposts.borrow().contains_key(&(original.id, caller))) {
+        return Err(PostError::AlreadyReposted { post_id: original.id });
+    }
+
+    let repost = insert_post(Post {
+        repost_of: Some(original.id),
+        ..new_post(caller, String::new(), Vec::new(), time())
+    });
+    REPOSTS.with(|reposts| reposts.borrow_mut().insert((original.id, caller), repost.id));
+    timeline::insert(&repost);
+    update_counts(original.id, |original| original.repost_count += 1);
+    Ok(repost)
+}
+
+/// Deletes the caller's repost of `post_id`.
+#[update]
+fn undo_repost(post_id: u64) -> Result<(), PostError> {
+    let caller = authenticated_caller()?;
+    let repost_id = REPOSTS
+        .with(|reposts| reposts.borrow().get(&(post_id, caller)))
+        .ok_or(PostError::NotReposted { post_id })?;
+
+    POSTS.with(|posts| {
+        let mut posts = posts.borrow_mut();
+        if let Some(repost) = posts.get(&repost_id) {
+            remove_post(&mut posts, &repost);
+        }
+    });
+    cleanup::schedule();
+    Ok(())
+}
+
+#[update]
+async fn create_quote_post(post_id: u64, content: String) -> Result<Post, PostError> {
+    let caller = authenticated_caller()?;
+    validate_content(&content)?;
+    shared_post(caller, post_id)?;
+    let mentions = mentions::resolve_for(&content, Visibility::Public).await?;
+    authors::warm(caller).await;
+    let quoted = shared_post(caller, post_id)?;
+
+    let post = insert_post(Post {
+        quote_of: Some(quoted.id),
+        mentions,
+        ..new_post(caller, content, Vec::new(), time())
+    });
+    timeline::insert(&post);
+    update_counts(quoted.id, |quoted| quoted.quote_count += 1);
+    Ok(post)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+    use crate::stored_post;
+    use social_types::pagination::Page;
+
+    /// Stores a repost the way `repost` does, without needing a caller.
+    fn store_repost(original_id: u64, reposter: Principal, now: u64) -> Post {
+        let repost = insert_post(Post {
+            repost_of: Some(original_id),
+            ..new_post(reposter, String::new(), Vec::new(), now)
+        });
+        REPOSTS.with(|reposts| reposts.borrow_mut().insert((original_id, reposter), repost.id));
+        timeline::insert(&repost);
+        update_counts(original_id, |original| original.repost_count += 1);
+        repost
+    }
+
+    fn ids(page: &Page<Post>) -> Vec<u64> {
+        page.items.iter().map(|post| post.id).collect()
+    }
+
+    #[test]
+    fn reposts_enter_only_the_reposters_timeline() {
+        let original = insert_new_post(principal(1), "hello", 1);
+        timeline::insert(&original);
+        let repost = store_repost(original.id, principal(2), 2);
+
+        assert_eq!(ids(&timeline::by_author(principal(3), principal(2), None, 10).unwrap()), vec![repost.id]);
+        assert_eq!(ids(&timeline::recent(principal(3), None, 10).unwrap()), vec![original.id]);
+        assert_eq!(stored_post(original.id).unwrap().repost_count, 1);
+        assert_eq!(shared_post(principal(3), repost.id).unwrap().id, original.id);
+    }
+
+    #[test]
+    fn only_public_and_unlisted_posts_can_be_shared() {
+        let author = principal(1);
+        let post = |visibility| {
+            insert_post(Post {
+                visibility,
+                ..new_post(author, "hello".to_string(), Vec::new(), 1)
+            })
+        };
+
+        assert!(shared_post(author, post(Visibility::Public).id).is_ok());
+        assert!(shared_post(author, post(Visibility::Unlisted).id).is_ok());
+        let followers_only = post(Visibility::FollowersOnly);
+        assert_eq!(
+            shared_post(author, followers_only.id).unwrap_err(),
+            PostError::NotShareable { post_id: followers_only.id }
+        );
+        let direct = post(Visibility::Direct);
+        assert_eq!(
+            shared_post(author, direct.id).unwrap_err(),
+            PostError::NotShareable { post_id: direct.id }
+        );
+    }
+
+    #[test]
+    fn deleting_the_original_drops_reposts_but_keeps_quotes() {
+        let original = insert_new_post(principal(1), "hello", 1);
+        timeline::insert(&original);
+        let repost = store_repost(original.id, principal(2), 2);
+        let quote = insert_post(Post {
+            quote_of: Some(original.id),
+            ..new_post(principal(3), "look".to_string(), Vec::new(), 3)
+        });
+
+        POSTS.with(|posts| remove_post(&mut posts.borrow_mut(), &original));
+        // Until the sweep gets to it, the repost shows nothing.
+        assert!(timeline::by_author(principal(3), principal(2), None, 10).unwrap().items.is_empty());
+
+        cleanup::sweep();
+        assert!(stored_post(repost.id).is_err());
+        assert!(timeline::by_author(principal(3), principal(2), None, 10).unwrap().items.is_empty());
+        assert!(REPOSTS.with(|reposts| reposts.borrow().is_empty()));
+        assert_eq!(stored_post(quote.id).unwrap().quote_of, Some(original.id));
+    }
+
+    #[test]
+    fn deleting_a_repost_releases_it() {
+        let original = insert_new_post(principal(1), "hello", 1);
+        let repost = store_repost(original.id, principal(2), 2);
+
+        POSTS.with(|posts| remove_post(&mut posts.borrow_mut(), &repost));
+
+        assert_eq!(stored_post(original.id).unwrap().repost_count, 0);
+        assert!(!REPOSTS.with(|reposts| reposts.borrow().contains_key(&(original.id, principal(2)))));
+    }
+}
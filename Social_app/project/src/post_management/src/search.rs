@@ -0,0 +1,460 @@
+//! Full-text search over post content. Content is split into case-folded terms and
+//! kept in an inverted index of `(term, created_at, post_id)` postings, each holding
+//! the positions the term occurs at so quoted phrases can be matched.
+//!
+//! A query's terms must all occur in a post (`"..."` additionally requires them in
+//! that order). Matches are ranked by how often and how rare their terms are, with a
+//! boost for recent posts. Pages resume after the last result's place in that
+//! ranking, with the recency boost taken as of the first page.
+
+use crate::mutes::Muted;
+use crate::timeline::{time_key, TimeKey};
+use crate::{privacy, POSTS, SEARCH_POSTINGS_MEMORY_ID, SEARCH_TERMS_MEMORY_ID};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::query;
+use ic_stable_structures::storable::Bound as StorableBound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::{Post, PostError, Visibility};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use unicode_xid::UnicodeXID;
+
+/// Longer terms are cut to this many characters.
+const MAX_TERM_CHARS: usize = 64;
+
+/// Query terms past this many are ignored.
+const MAX_QUERY_TERMS: usize = 10;
+
+/// Upper bound on the postings one search reads, newest first, so a common term
+/// still fits in one query. Older matches are not found.
+const MAX_SEARCH_SCAN: usize = 10_000;
+
+/// Upper bound on the matches one search ranks.
+const MAX_SEARCH_MATCHES: usize = 1_000;
+
+/// Age, in nanoseconds, at which a post's recency boost has halved.
+const RECENCY_HALF_LIFE: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// A case-folded word.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Term(String);
+
+impl Storable for Term {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Term(String::from_utf8(bytes.into_owned()).expect("stored search term is not UTF-8"))
+    }
+
+    const BOUND: StorableBound = StorableBound::Bounded {
+        max_size: (MAX_TERM_CHARS * 4) as u32,
+        is_fixed_size: false,
+    };
+}
+
+/// Where a term occurs in a post, counted in terms from the start of its content.
+struct Positions(Vec<u32>);
+
+impl Storable for Positions {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.0.iter().flat_map(|position| position.to_be_bytes()).collect())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Positions(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        )
+    }
+
+    const BOUND: StorableBound = StorableBound::Unbounded;
+}
+
+/// Narrows a search to one author and to posts created within `since..=until`
+/// (nanoseconds since the epoch).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SearchFilters {
+    pub author: Option<Principal>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+thread_local! {
+    /// `(term, created_at, post_id)` to the term's positions in that post.
+    static POSTINGS: RefCell<StableBTreeMap<(Term, u64, u64), Positions, Memory>> = memory::init_map(SEARCH_POSTINGS_MEMORY_ID);
+    /// How many posts contain each term.
+    static TERMS: RefCell<StableBTreeMap<Term, u64, Memory>> = memory::init_map(SEARCH_TERMS_MEMORY_ID);
+}
+
+/// The terms of `text` in order: runs of letters, digits, combining marks and
+/// connector punctuation, case-folded. Scripts written without spaces come out as
+/// one term per run.
+fn tokenize(text: &str) -> Vec<Term> {
+    text.split(|c: char| !c.is_xid_continue())
+        .filter(|word| !word.is_empty())
+        .map(|word| Term(word.to_lowercase().chars().take(MAX_TERM_CHARS).collect()))
+        .collect()
+}
+
+/// The terms of `text`, as plain strings.
+pub(crate) fn terms_of(text: &str) -> Vec<String> {
+    tokenize(text).into_iter().map(|Term(term)| term).collect()
+}
+
+fn positions(content: &str) -> BTreeMap<Term, Vec<u32>> {
+    let mut positions: BTreeMap<Term, Vec<u32>> = BTreeMap::new();
+    for (position, term) in tokenize(content).into_iter().enumerate() {
+        positions.entry(term).or_default().push(position as u32);
+    }
+    positions
+}
+
+/// Indexes `post`'s content. Only public posts can be found by search.
+pub(crate) fn insert(post: &Post) {
+    if post.visibility != Visibility::Public {
+        return;
+    }
+    let (created_at, id) = time_key(post);
+    for (term, positions) in positions(&post.content) {
+        TERMS.with(|terms| {
+            let mut terms = terms.borrow_mut();
+            let count = terms.get(&term).unwrap_or(0);
+            terms.insert(term.clone(), count + 1);
+        });
+        POSTINGS.with(|postings| postings.borrow_mut().insert((term, created_at, id), Positions(positions)));
+    }
+}
+
+pub(crate) fn remove(post: &Post) {
+    let (created_at, id) = time_key(post);
+    for term in positions(&post.content).into_keys() {
+        let removed = POSTINGS.with(|postings| postings.borrow_mut().remove(&(term.clone(), created_at, id)));
+        if removed.is_none() {
+            continue;
+        }
+        TERMS.with(|terms| {
+            let mut terms = terms.borrow_mut();
+            match terms.get(&term).unwrap_or(0) {
+                0 | 1 => terms.remove(&term),
+                count => terms.insert(term, count - 1),
+            };
+        });
+    }
+}
+
+/// Indexes every stored post. Only needed when upgrading from a build without search.
+pub(crate) fn rebuild() {
+    POSTINGS.with(|postings| postings.borrow_mut().clear_new());
+    TERMS.with(|terms| terms.borrow_mut().clear_new());
+    POSTS.with(|posts| {
+        for (_, post) in posts.borrow().iter() {
+            insert(&post);
+        }
+    });
+}
+
+/// Splits a query into clauses that must all match: single terms, and the runs of
+/// terms inside `"` quotes, which must occur in order. An unclosed quote runs to
+/// the end of the query.
+fn parse(query: &str) -> Vec<Vec<Term>> {
+    let mut clauses = Vec::new();
+    let mut remaining = MAX_QUERY_TERMS;
+    for (i, part) in query.split('"').enumerate() {
+        let mut terms = tokenize(part);
+        terms.truncate(remaining);
+        remaining -= terms.len();
+        if i % 2 == 1 {
+            clauses.push(terms);
+        } else {
+            clauses.extend(terms.into_iter().map(|term| vec![term]));
+        }
+    }
+    clauses.retain(|clause| !clause.is_empty());
+    clauses
+}
+
+/// Whether `clause` occurs in a post whose term positions are `positions`.
+fn occurs(clause: &[Term], positions: &BTreeMap<&Term, Positions>) -> bool {
+    let Some(Positions(first)) = positions.get(&clause[0]) else {
+        return false;
+    };
+    first.iter().any(|&start| {
+        clause.iter().enumerate().skip(1).all(|(offset, term)| {
+            positions
+                .get(term)
+                .is_some_and(|Positions(at)| at.contains(&(start + offset as u32)))
+        })
+    })
+}
+
+/// Where a page of results stopped: when the search was first run, so later pages
+/// rank by the same recency boost, and the score and time key of its last result.
+struct Position {
+    now: u64,
+    score: f64,
+    key: TimeKey,
+}
+
+impl Position {
+    fn encode(&self) -> Cursor {
+        [self.now, self.score.to_bits(), self.key.0, self.key.1]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+
+    fn decode(cursor: &[u8]) -> Result<Position, PostError> {
+        if cursor.len() != 32 {
+            return Err(PostError::InvalidCursor);
+        }
+        let value = |i: usize| u64::from_be_bytes(cursor[i * 8..(i + 1) * 8].try_into().unwrap());
+        Ok(Position {
+            now: value(0),
+            score: f64::from_bits(value(1)),
+            key: (value(2), value(3)),
+        })
+    }
+}
+
+/// Ranking order: higher scores first, and equal scores newest first.
+fn rank_order((a_score, a_key): (f64, TimeKey), (b_score, b_key): (f64, TimeKey)) -> Ordering {
+    b_score.total_cmp(&a_score).then_with(|| b_key.cmp(&a_key))
+}
+
+/// Posts matching `query` and `filters` that `viewer` may see and has not muted, with
+/// their scores, best first.
+fn ranked(query: &str, filters: &SearchFilters, viewer: Principal, now: u64) -> Vec<(f64, Post)> {
+    let clauses = parse(query);
+    let mut counts: BTreeMap<&Term, u64> = BTreeMap::new();
+    for term in clauses.iter().flatten() {
+        counts.insert(term, TERMS.with(|terms| terms.borrow().get(term).unwrap_or(0)));
+    }
+    // Walk the rarest term's postings and look the others up alongside.
+    let Some((&rarest, _)) = counts.iter().min_by_key(|(_, &count)| count) else {
+        return Vec::new();
+    };
+    if counts.values().any(|&count| count == 0) {
+        return Vec::new();
+    }
+
+    let oldest = Bound::Included((rarest.clone(), filters.since.unwrap_or(0), 0));
+    let newest = Bound::Included((rarest.clone(), filters.until.unwrap_or(u64::MAX), u64::MAX));
+    let total = POSTS.with(|posts| posts.borrow().len()) as f64;
+    let muted = Muted::of(viewer, now);
+
+    let mut matches: Vec<(f64, Post)> = Vec::new();
+    POSTINGS.with(|postings| {
+        POSTS.with(|posts| {
+            let postings = postings.borrow();
+            let posts = posts.borrow();
+            let candidates = postings.range((oldest, newest)).rev().take(MAX_SEARCH_SCAN);
+            for ((_, created_at, id), _) in candidates {
+                let positions: BTreeMap<&Term, Positions> = counts
+                    .keys()
+                    .filter_map(|&term| Some((term, postings.get(&(term.clone(), created_at, id))?)))
+                    .collect();
+                if positions.len() < counts.len() || !clauses.iter().all(|clause| occurs(clause, &positions)) {
+                    continue;
+                }
+                let Some(post) = posts.get(&id) else {
+                    continue;
+                };
+                if filters.author.is_some_and(|author| post.author != author)
+                    || !privacy::can_see(viewer, &post)
+                    || muted.hides(&post)
+                {
+                    continue;
+                }
+
+                let relevance: f64 = positions
+                    .iter()
+                    .map(|(term, Positions(at))| {
+                        let frequency = 1.0 + (at.len() as f64).ln();
+                        let rarity = (1.0 + total / counts[term] as f64).ln();
+                        frequency * rarity
+                    })
+                    .sum();
+                let age = now.saturating_sub(created_at) as f64 / RECENCY_HALF_LIFE as f64;
+                matches.push((relevance * (1.0 + 0.5f64.powf(age)), post));
+                if matches.len() == MAX_SEARCH_MATCHES {
+                    break;
+                }
+            }
+        })
+    });
+
+    matches.sort_by(|(a_score, a), (b_score, b)| rank_order((*a_score, time_key(a)), (*b_score, time_key(b))));
+    matches
+}
+
+/// One page of the posts matching `query`, best first. Words match case-insensitively
+/// and must all occur; wrap words in `"` to require them as a phrase.
+#[query]
+fn search_posts(
+    query: String,
+    filters: SearchFilters,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Post>, PostError> {
+    search_page(&query, &filters, ic_cdk::caller(), cursor.as_deref(), page_size(limit), time())
+}
+
+fn search_page(
+    query: &str,
+    filters: &SearchFilters,
+    viewer: Principal,
+    cursor: Option<&[u8]>,
+    limit: usize,
+    now: u64,
+) -> Result<Page<Post>, PostError> {
+    let after = cursor.map(Position::decode).transpose()?;
+    let now = after.as_ref().map_or(now, |after| after.now);
+    let mut matches: Vec<(f64, Post)> = ranked(query, filters, viewer, now)
+        .into_iter()
+        .filter(|(score, post)| {
+            after
+                .as_ref()
+                .is_none_or(|after| rank_order((after.score, after.key), (*score, time_key(post))) == Ordering::Less)
+        })
+        .take(limit + 1)
+        .collect();
+
+    let next_cursor = (matches.len() > limit).then(|| {
+        let (score, post) = &matches[limit - 1];
+        Position {
+            now,
+            score: *score,
+            key: time_key(post),
+        }
+        .encode()
+    });
+    matches.truncate(limit);
+    let items = matches.into_iter().map(|(_, post)| post).collect();
+    Ok(Page { items, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{insert_new_post, principal};
+
+    const HOUR: u64 = 60 * 60 * 1_000_000_000;
+
+    fn indexed(author: u8, content: &str, created_at: u64) -> Post {
+        insert_new_post(principal(author), content, created_at)
+    }
+
+    fn search(query: &str, filters: SearchFilters) -> Vec<u64> {
+        ranked(query, &filters, Principal::anonymous(), 100 * HOUR)
+            .iter()
+            .map(|(_, post)| post.id)
+            .collect()
+    }
+
+    fn words(text: &str) -> Vec<String> {
+        tokenize(text).into_iter().map(|Term(term)| term).collect()
+    }
+
+    #[test]
+    fn tokenizes_case_folded_words() {
+        assert_eq!(words("Hello, World! #Rust @bob"), vec!["hello", "world", "rust", "bob"]);
+        assert_eq!(words("Café—naïve snake_case"), vec!["café", "naïve", "snake_case"]);
+        assert!(words(" ... ").is_empty());
+    }
+
+    #[test]
+    fn requires_every_term_and_phrases_in_order() {
+        let both = indexed(1, "the quick brown fox", HOUR);
+        let reversed = indexed(1, "brown is not quick", 2 * HOUR);
+        indexed(1, "just a fox", 3 * HOUR);
+
+        let mut found = search("QUICK brown", SearchFilters::default());
+        found.sort();
+        assert_eq!(found, vec![both.id, reversed.id]);
+        assert_eq!(search("\"quick brown\"", SearchFilters::default()), vec![both.id]);
+        assert_eq!(search("\"quick brown\" fox", SearchFilters::default()), vec![both.id]);
+        assert!(search("quick zebra", SearchFilters::default()).is_empty());
+        assert!(search("  ", SearchFilters::default()).is_empty());
+    }
+
+    #[test]
+    fn filters_by_author_and_date() {
+        let early = indexed(1, "motoko news", HOUR);
+        let late = indexed(1, "motoko news", 5 * HOUR);
+        let other = indexed(2, "motoko news", 6 * HOUR);
+
+        let by_author = SearchFilters {
+            author: Some(principal(1)),
+            ..Default::default()
+        };
+        let mut found = search("motoko", by_author);
+        found.sort();
+        assert_eq!(found, vec![early.id, late.id]);
+
+        let window = SearchFilters {
+            since: Some(2 * HOUR),
+            until: Some(5 * HOUR),
+            ..Default::default()
+        };
+        assert_eq!(search("motoko", window), vec![late.id]);
+        assert_eq!(search("news", SearchFilters::default())[0], other.id);
+    }
+
+    #[test]
+    fn ranks_relevance_with_a_recency_boost() {
+        let old_repeated = indexed(1, "canister canister canister upgrades", 10 * HOUR);
+        let new_once = indexed(1, "canister upgrades", 99 * HOUR);
+        let old_once = indexed(1, "canister upgrades", 50 * HOUR);
+
+        assert_eq!(search("canister", SearchFilters::default()), vec![old_repeated.id, new_once.id, old_once.id]);
+    }
+
+    #[test]
+    fn follows_edits_and_deletes() {
+        let post = indexed(1, "hello stable memory", HOUR);
+        remove(&post);
+        let edited = Post {
+            content: "hello heap".to_string(),
+            ..post
+        };
+        insert(&edited);
+
+        assert!(search("stable", SearchFilters::default()).is_empty());
+        assert_eq!(search("heap", SearchFilters::default()), vec![edited.id]);
+
+        remove(&edited);
+        assert!(search("hello", SearchFilters::default()).is_empty());
+        assert!(TERMS.with(|terms| terms.borrow().is_empty()));
+    }
+
+    #[test]
+    fn pages_resume_after_the_last_result() {
+        let posts: Vec<u64> = (1..=3).map(|hour| indexed(1, "paged", hour * HOUR).id).collect();
+
+        let filters = SearchFilters::default();
+        let first = search_page("paged", &filters, Principal::anonymous(), None, 2, 4 * HOUR).unwrap();
+        assert_eq!(first.items.iter().map(|post| post.id).collect::<Vec<_>>(), vec![posts[2], posts[1]]);
+
+        // A better match arriving between pages, or time passing, shifts nothing.
+        indexed(1, "paged paged", 4 * HOUR);
+        let cursor = first.next_cursor.as_deref();
+        let second = search_page("paged", &filters, Principal::anonymous(), cursor, 2, 1_000 * HOUR).unwrap();
+        assert_eq!(second.items.iter().map(|post| post.id).collect::<Vec<_>>(), vec![posts[0]]);
+        assert!(second.next_cursor.is_none());
+        assert_eq!(
+            search_page("paged", &filters, Principal::anonymous(), Some(&[1]), 2, 4 * HOUR).unwrap_err(),
+            PostError::InvalidCursor
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! Helpers shared by the unit tests.
+
+use crate::{insert_post, new_post};
+use candid::Principal;
+use social_types::Post;
+
+/// A distinct principal for each `n`.
+pub(crate) fn principal(n: u8) -> Principal {
+    Principal::from_slice(&[n; 29])
+}
+
+/// Stores a new post without media the way `create_post` does, minus the timelines.
+pub(crate) fn insert_new_post(author: Principal, content: &str, created_at: u64) -> Post {
+    insert_post(new_post(author, content.to_string(), Vec::new(), created_at))
+}
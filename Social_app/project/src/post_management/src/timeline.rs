@@ -0,0 +1,329 @@
+//! Time-ordered secondary indexes over `POSTS` and the cursor pagination built on
+//! them. Every listing walks an index backwards from its cursor, so a page costs the
+//! same no matter how many posts are stored. Posts the viewer may not see are skipped
+//! along the way.
+
+use crate::mutes::Muted;
+use crate::{privacy, AUTHOR_TIMELINE_MEMORY_ID, POSTS, TIMELINE_MEMORY_ID};
+use candid::Principal;
+use ic_stable_structures::StableBTreeMap;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{Cursor, Page};
+use social_types::{Post, PostError, Visibility};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, BinaryHeap};
+use std::ops::Bound;
+
+/// Position of a post in a timeline: newest first, ties broken by ID.
+pub(crate) type TimeKey = (u64, u64);
+
+/// Upper bound on the index entries read for one page. A page cut short by it still
+/// carries a cursor, so the listing continues where it stopped.
+pub(crate) const MAX_PAGE_SCAN: usize = 2_000;
+
+thread_local! {
+    static TIMELINE: RefCell<StableBTreeMap<TimeKey, (), Memory>> = memory::init_map(TIMELINE_MEMORY_ID);
+    static AUTHOR_TIMELINE: RefCell<StableBTreeMap<(Principal, u64, u64), (), Memory>> = memory::init_map(AUTHOR_TIMELINE_MEMORY_ID);
+}
+
+pub(crate) fn time_key(post: &Post) -> TimeKey {
+    (post.created_at, post.id)
+}
+
+pub(crate) fn encode_cursor((created_at, id): TimeKey) -> Cursor {
+    [created_at.to_be_bytes(), id.to_be_bytes()].concat()
+}
+
+pub(crate) fn decode_cursor(cursor: &[u8]) -> Result<TimeKey, PostError> {
+    let created_at = cursor.get(..8).and_then(|bytes| bytes.try_into().ok());
+    let id = cursor.get(8..).and_then(|bytes| bytes.try_into().ok());
+    match (created_at, id) {
+        (Some(created_at), Some(id)) => Ok((u64::from_be_bytes(created_at), u64::from_be_bytes(id))),
+        _ => Err(PostError::InvalidCursor),
+    }
+}
+
+/// Indexes a top-level post or repost. Reposts only enter their reposter's timeline,
+/// so the public timeline shows every post once, and only public posts enter it at all.
+pub(crate) fn insert(post: &Post) {
+    let (created_at, id) = time_key(post);
+    if post.repost_of.is_none() && post.visibility == Visibility::Public {
+        TIMELINE.with(|index| index.borrow_mut().insert((created_at, id), ()));
+    }
+    AUTHOR_TIMELINE.with(|index| index.borrow_mut().insert((post.author, created_at, id), ()));
+}
+
+pub(crate) fn remove(post: &Post) {
+    let (created_at, id) = time_key(post);
+    TIMELINE.with(|index| index.borrow_mut().remove(&(created_at, id)));
+    AUTHOR_TIMELINE.with(|index| index.borrow_mut().remove(&(post.author, created_at, id)));
+}
+
+/// Rebuilds both indexes from `POSTS`. Only needed when upgrading from a build that
+/// predates them.
+pub(crate) fn rebuild() {
+    TIMELINE.with(|index| index.borrow_mut().clear_new());
+    AUTHOR_TIMELINE.with(|index| index.borrow_mut().clear_new());
+    POSTS.with(|posts| {
+        for (_, post) in posts.borrow().iter() {
+            if post.parent_post_id.is_none() {
+                insert(&post);
+            }
+        }
+    });
+}
+
+/// Keys of one author's posts older than `before`, newest first.
+fn author_keys(
+    index: &StableBTreeMap<(Principal, u64, u64), (), Memory>,
+    author: Principal,
+    before: Option<TimeKey>,
+) -> impl Iterator<Item = TimeKey> + '_ {
+    let oldest = Bound::Included((author, 0, 0));
+    let newest = match before {
+        Some((created_at, id)) => Bound::Excluded((author, created_at, id)),
+        None => Bound::Included((author, u64::MAX, u64::MAX)),
+    };
+    index
+        .range((oldest, newest))
+        .rev()
+        .map(|((_, created_at, id), _)| (created_at, id))
+}
+
+/// Loads up to `limit` posts that `viewer` may see from `keys`, in order, into a page
+/// whose cursor points at the last key read when more remain.
+pub(crate) fn page_of(keys: impl IntoIterator<Item = TimeKey>, viewer: Principal, limit: usize) -> Page<Post> {
+    page_where(keys, limit, |post| privacy::can_see(viewer, post))
+}
+
+/// Like `page_of`, keeping the posts for which `keep` holds.
+pub(crate) fn page_where(
+    keys: impl IntoIterator<Item = TimeKey>,
+    limit: usize,
+    keep: impl Fn(&Post) -> bool,
+) -> Page<Post> {
+    POSTS.with(|posts| {
+        let posts = posts.borrow();
+        let mut items = Vec::with_capacity(limit);
+        let mut last_read = None;
+        for (read, key) in keys.into_iter().enumerate() {
+            let kept = posts.get(&key.1).filter(|post| keep(post));
+            if read == MAX_PAGE_SCAN || (kept.is_some() && items.len() == limit) {
+                return Page {
+                    items,
+                    next_cursor: last_read.map(encode_cursor),
+                };
+            }
+            items.extend(kept);
+            last_read = Some(key);
+        }
+        Page {
+            items,
+            next_cursor: None,
+        }
+    })
+}
+
+/// The newest posts from everyone.
+pub(crate) fn recent(viewer: Principal, cursor: Option<&[u8]>, limit: usize) -> Result<Page<Post>, PostError> {
+    let before = cursor.map(decode_cursor).transpose()?;
+    Ok(TIMELINE.with(|index| {
+        let index = index.borrow();
+        let newer_than_cursor = match before {
+            Some(before) => index.range(..before),
+            None => index.range(..),
+        };
+        page_of(newer_than_cursor.rev().map(|(key, _)| key), viewer, limit)
+    }))
+}
+
+/// The newest posts by a single author.
+pub(crate) fn by_author(
+    viewer: Principal,
+    author: Principal,
+    cursor: Option<&[u8]>,
+    limit: usize,
+) -> Result<Page<Post>, PostError> {
+    let before = cursor.map(decode_cursor).transpose()?;
+    if !privacy::can_view(viewer, author) {
+        return Ok(Page {
+            items: Vec::new(),
+            next_cursor: None,
+        });
+    }
+    Ok(AUTHOR_TIMELINE.with(|index| page_of(author_keys(&index.borrow(), author, before), viewer, limit)))
+}
+
+/// The newest posts by any of `authors`, merged across their per-author timelines,
+/// leaving out what `viewer` muted as of `now`.
+pub(crate) fn by_authors(
+    viewer: Principal,
+    authors: &BTreeSet<Principal>,
+    cursor: Option<&[u8]>,
+    limit: usize,
+    now: u64,
+) -> Result<Page<Post>, PostError> {
+    let before = cursor.map(decode_cursor).transpose()?;
+    let muted = Muted::of(viewer, now);
+    Ok(AUTHOR_TIMELINE.with(|index| {
+        let index = index.borrow();
+        let mut timelines: Vec<_> = authors
+            .iter()
+            .filter(|&&author| privacy::can_view(viewer, author) && !muted.user(author))
+            .map(|&author| author_keys(&index, author, before))
+            .collect();
+        let mut heads: BinaryHeap<(TimeKey, usize)> = timelines
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, timeline)| Some((timeline.next()?, i)))
+            .collect();
+
+        let merged = std::iter::from_fn(|| {
+            let (key, i) = heads.pop()?;
+            if let Some(next) = timelines[i].next() {
+                heads.push((next, i));
+            }
+            Some(key)
+        });
+        page_where(merged, limit, |post| privacy::can_see(viewer, post) && !muted.hides(post))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+
+    fn insert_post(id: u64, author: Principal, created_at: u64) {
+        let post = Post {
+            id,
+            author,
+            content: format!("post {id}"),
+            media_urls: Vec::new(),
+            created_at,
+            updated_at: created_at,
+            like_count: 0,
+            parent_post_id: None,
+            reply_to: None,
+            reply_count: 0,
+            hidden: false,
+            repost_of: None,
+            quote_of: None,
+            repost_count: 0,
+            quote_count: 0,
+            mentions: Vec::new(),
+            visibility: Visibility::Public,
+            edit_count: 0,
+        };
+        POSTS.with(|posts| posts.borrow_mut().insert(id, post.clone()));
+        insert(&post);
+    }
+
+    fn ids(page: &Page<Post>) -> Vec<u64> {
+        page.items.iter().map(|post| post.id).collect()
+    }
+
+    #[test]
+    fn merges_authors_newest_first_across_pages() {
+        let alice = principal(1);
+        let bob = principal(2);
+        let carol = principal(3);
+        insert_post(1, alice, 10);
+        insert_post(2, bob, 20);
+        insert_post(3, carol, 30);
+        insert_post(4, alice, 40);
+        insert_post(5, bob, 40);
+
+        let authors: BTreeSet<Principal> = [alice, bob].into_iter().collect();
+        let first = by_authors(Principal::anonymous(), &authors, None, 2, 0).unwrap();
+        assert_eq!(ids(&first), vec![5, 4]);
+
+        let second = by_authors(Principal::anonymous(), &authors, first.next_cursor.as_deref(), 2, 0).unwrap();
+        assert_eq!(ids(&second), vec![2, 1]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[test]
+    fn pages_through_recent_and_per_author_timelines() {
+        let alice = principal(1);
+        let bob = principal(2);
+        insert_post(1, alice, 10);
+        insert_post(2, bob, 20);
+        insert_post(3, alice, 30);
+        insert_post(4, alice, 40);
+
+        let first = recent(Principal::anonymous(), None, 3).unwrap();
+        assert_eq!(ids(&first), vec![4, 3, 2]);
+        let second = recent(Principal::anonymous(), first.next_cursor.as_deref(), 3).unwrap();
+        assert_eq!(ids(&second), vec![1]);
+        assert!(second.next_cursor.is_none());
+
+        let first = by_author(Principal::anonymous(), alice, None, 2).unwrap();
+        assert_eq!(ids(&first), vec![4, 3]);
+        let second = by_author(Principal::anonymous(), alice, first.next_cursor.as_deref(), 2).unwrap();
+        assert_eq!(ids(&second), vec![1]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[test]
+    fn private_posts_are_skipped_without_shortening_pages() {
+        let (alice, bob, fan) = (principal(1), principal(2), principal(3));
+        for id in 1..=4 {
+            insert_post(id, alice, id * 10);
+        }
+        insert_post(5, bob, 5);
+        insert_post(6, bob, 60);
+        privacy::set_private(alice, true);
+        privacy::add_followers(alice, vec![fan]);
+
+        let stranger = principal(4);
+        let page = recent(stranger, None, 2).unwrap();
+        assert_eq!(ids(&page), vec![6, 5]);
+        assert!(page.next_cursor.is_none());
+        assert!(by_author(stranger, alice, None, 10).unwrap().items.is_empty());
+        let authors: BTreeSet<Principal> = [alice, bob].into_iter().collect();
+        assert_eq!(ids(&by_authors(stranger, &authors, None, 10, 0).unwrap()), vec![6, 5]);
+
+        assert_eq!(ids(&recent(fan, None, 3).unwrap()), vec![6, 4, 3]);
+        assert_eq!(ids(&by_author(alice, alice, None, 10).unwrap()), vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn only_public_posts_enter_the_recent_timeline() {
+        let alice = principal(1);
+        insert_post(1, alice, 10);
+        let unlisted = Post {
+            id: 2,
+            created_at: 20,
+            visibility: Visibility::Unlisted,
+            ..POSTS.with(|posts| posts.borrow().get(&1)).unwrap()
+        };
+        POSTS.with(|posts| posts.borrow_mut().insert(2, unlisted.clone()));
+        insert(&unlisted);
+
+        assert_eq!(ids(&recent(principal(2), None, 10).unwrap()), vec![1]);
+        assert_eq!(ids(&by_author(principal(2), alice, None, 10).unwrap()), vec![2, 1]);
+    }
+
+    #[test]
+    fn removed_posts_leave_the_timelines() {
+        let alice = principal(1);
+        insert_post(1, alice, 10);
+        insert_post(2, alice, 20);
+        let newest = POSTS.with(|posts| posts.borrow_mut().remove(&2)).unwrap();
+        remove(&newest);
+
+        assert_eq!(ids(&recent(Principal::anonymous(), None, 10).unwrap()), vec![1]);
+        assert_eq!(ids(&by_author(Principal::anonymous(), alice, None, 10).unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let authors = BTreeSet::new();
+        assert_eq!(
+            by_authors(Principal::anonymous(), &authors, Some(&[1, 2, 3]), 10, 0).unwrap_err(),
+            PostError::InvalidCursor
+        );
+        assert_eq!(recent(Principal::anonymous(), Some(&[0; 17]), 10).unwrap_err(), PostError::InvalidCursor);
+    }
+}
@@ -1,201 +1,743 @@
+mod outbox;
+#[cfg(test)]
+mod testing;
+
+use crate::outbox::Change;
 use candid::{CandidType, Principal};
 use ic_cdk::api::time;
 use ic_cdk_macros::*;
-use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{StableBTreeMap, StableCell};
 use serde::{Deserialize, Serialize};
+use social_types::guards::{authenticated_caller, caller_is_controller};
+use social_types::memory::{self, Memory};
+use social_types::pagination::{page_size, Cursor, Page};
+use social_types::versioned::Versioned;
+use social_types::{timer, versioned_storable, CanisterIds, Follow, GraphError};
 use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
 
-type Memory = VirtualMemory<DefaultMemoryImpl>;
+// Memories 0-2 held the string-keyed edges and whole-list adjacency maps written
+// before schema 2. `migrate_storage` reads them once; they are never written again.
+const LEGACY_FOLLOWS_MEMORY_ID: MemoryId = MemoryId::new(0);
+const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(3);
+const EDGES_MEMORY_ID: MemoryId = MemoryId::new(4);
+const FOLLOWERS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const FOLLOWING_MEMORY_ID: MemoryId = MemoryId::new(6);
+const STATS_MEMORY_ID: MemoryId = MemoryId::new(7);
+const CANISTER_IDS_MEMORY_ID: MemoryId = MemoryId::new(8);
+const PRIVATE_ACCOUNTS_MEMORY_ID: MemoryId = MemoryId::new(9);
+const FOLLOW_REQUESTS_MEMORY_ID: MemoryId = MemoryId::new(10);
+const SENT_FOLLOW_REQUESTS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const BLOCKS_MEMORY_ID: MemoryId = MemoryId::new(12);
+const BLOCKED_MEMORY_ID: MemoryId = MemoryId::new(13);
+const OUTBOX_MEMORY_ID: MemoryId = MemoryId::new(14);
+const PENDING_APPROVALS_MEMORY_ID: MemoryId = MemoryId::new(15);
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct Follow {
-    pub follower: Principal,
-    pub following: Principal,
-    pub created_at: u64,
-}
+/// Bump whenever a stored type's `Versioned::VERSION` changes or the storage layout
+/// does, so that `post_upgrade` rewrites existing records eagerly instead of migrating
+/// them on every read.
+///
+/// 2: composite-key edges, time-ordered adjacency and maintained counters.
+const SCHEMA_VERSION: u32 = 2;
+
+/// Follow edges pushed to `post_management` per call to `sync_follower_snapshot`.
+const FOLLOWER_SNAPSHOT_BATCH: usize = 1_000;
+
+/// Edges removed per call to `remove_user`, which keeps each call well inside the
+/// instruction limit.
+const REMOVAL_BATCH: usize = 500;
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+/// Follow requests approved per firing of the timer after accounts go public.
+const APPROVAL_BATCH: usize = 500;
+
+/// `(user, followed_at, other)`: one side of a follow edge, ordered by follow time.
+/// Follow requests and blocks are indexed the same way.
+type AdjacencyKey = (Principal, u64, Principal);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
 pub struct SocialStats {
     pub followers_count: u64,
     pub following_count: u64,
 }
 
+impl Versioned for SocialStats {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(SocialStats);
+
+/// The outcome of `follow_user`: following a private account only requests it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FollowStatus {
+    Following,
+    Requested,
+}
+
 thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
-    
-    static FOLLOWS: RefCell<StableBTreeMap<String, Follow, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
-        )
-    );
-    
-    static FOLLOWERS: RefCell<StableBTreeMap<Principal, Vec<Principal>, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-        )
-    );
-    
-    static FOLLOWING: RefCell<StableBTreeMap<Principal, Vec<Principal>, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
-        )
-    );
+    /// `(follower, following)` to the time of the follow.
+    static EDGES: RefCell<StableBTreeMap<(Principal, Principal), u64, Memory>> = memory::init_map(EDGES_MEMORY_ID);
+    /// `(following, followed_at, follower)`.
+    static FOLLOWERS: RefCell<StableBTreeMap<AdjacencyKey, (), Memory>> = memory::init_map(FOLLOWERS_MEMORY_ID);
+    /// `(follower, followed_at, following)`.
+    static FOLLOWING: RefCell<StableBTreeMap<AdjacencyKey, (), Memory>> = memory::init_map(FOLLOWING_MEMORY_ID);
+    static STATS: RefCell<StableBTreeMap<Principal, SocialStats, Memory>> = memory::init_map(STATS_MEMORY_ID);
+    static SCHEMA: RefCell<StableCell<u32, Memory>> = memory::init_cell(SCHEMA_VERSION_MEMORY_ID, 0);
+    static CANISTER_IDS: RefCell<StableCell<CanisterIds, Memory>> = memory::init_cell(CANISTER_IDS_MEMORY_ID, CanisterIds::default());
+    /// Accounts whose followers must be approved, as set in `user_management`.
+    static PRIVATE_ACCOUNTS: RefCell<StableBTreeMap<Principal, (), Memory>> = memory::init_map(PRIVATE_ACCOUNTS_MEMORY_ID);
+    /// `(target, requested_at, requester)`: pending requests to follow a private account.
+    static FOLLOW_REQUESTS: RefCell<StableBTreeMap<AdjacencyKey, (), Memory>> = memory::init_map(FOLLOW_REQUESTS_MEMORY_ID);
+    /// `(requester, target)` to the time of the request.
+    static SENT_FOLLOW_REQUESTS: RefCell<StableBTreeMap<(Principal, Principal), u64, Memory>> = memory::init_map(SENT_FOLLOW_REQUESTS_MEMORY_ID);
+    /// `(blocker, blocked)` to the time of the block.
+    static BLOCKS: RefCell<StableBTreeMap<(Principal, Principal), u64, Memory>> = memory::init_map(BLOCKS_MEMORY_ID);
+    /// `(blocker, blocked_at, blocked)`.
+    static BLOCKED: RefCell<StableBTreeMap<AdjacencyKey, (), Memory>> = memory::init_map(BLOCKED_MEMORY_ID);
+    /// Accounts that went public with follow requests still to approve.
+    static PENDING_APPROVALS: RefCell<StableBTreeMap<Principal, (), Memory>> = memory::init_map(PENDING_APPROVALS_MEMORY_ID);
 }
 
-fn follow_key(follower: Principal, following: Principal) -> String {
-    format!("{}#{}", follower.to_string(), following.to_string())
+/// Brings storage written by an older build up to this build's layout.
+fn migrate_storage() {
+    let stored = SCHEMA.with(|schema| *schema.borrow().get());
+    if stored >= SCHEMA_VERSION {
+        return;
+    }
+    if stored < 2 {
+        import_legacy_follows();
+    }
+    set_schema_version();
 }
 
-#[update]
-fn follow_user(following: Principal) -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot follow others".to_string());
-    }
-    
-    if caller == following {
-        return Err("You cannot follow yourself".to_string());
-    }
-    
-    let key = follow_key(caller, following);
-    
-    FOLLOWS.with(|follows| {
-        let mut follows = follows.borrow_mut();
-        
-        if follows.contains_key(&key) {
-            return Err("Already following this user".to_string());
-        }
-        
-        let follow = Follow {
-            follower: caller,
-            following,
-            created_at: time(),
-        };
-        
-        follows.insert(key, follow);
-        
-        // Update followers list
-        FOLLOWERS.with(|followers| {
-            let mut followers = followers.borrow_mut();
-            let mut follower_list = followers.get(&following).unwrap_or_default();
-            follower_list.push(caller);
-            followers.insert(following, follower_list);
-        });
-        
-        // Update following list
-        FOLLOWING.with(|following_map| {
-            let mut following_map = following_map.borrow_mut();
-            let mut following_list = following_map.get(&caller).unwrap_or_default();
-            following_list.push(following);
-            following_map.insert(caller, following_list);
-        });
-        
+/// Re-inserts every edge of the string-keyed map used before schema 2.
+fn import_legacy_follows() {
+    let legacy: StableBTreeMap<String, Follow, Memory> =
+        StableBTreeMap::init(memory::get(LEGACY_FOLLOWS_MEMORY_ID));
+    for (_, follow) in legacy.iter() {
+        insert_edge(follow.follower, follow.following, follow.created_at);
+    }
+}
+
+fn set_schema_version() {
+    SCHEMA.with(|schema| {
+        schema
+            .borrow_mut()
+            .set(SCHEMA_VERSION)
+            .expect("failed to update the schema version");
+    });
+}
+
+#[init]
+fn init() {
+    set_schema_version();
+}
+
+/// Edges, both adjacency indexes and the counters live in stable memory behind the
+/// memory manager, so there is nothing to serialize before an upgrade.
+#[pre_upgrade]
+fn pre_upgrade() {}
+
+#[post_upgrade]
+fn post_upgrade() {
+    migrate_storage();
+    // Upgrades clear the global timer.
+    schedule_approvals();
+    outbox::schedule();
+}
+
+/// Entry point of the global timer armed through `social_types::timer`.
+#[export_name = "canister_global_timer"]
+extern "C" fn global_timer() {
+    ic_cdk::setup();
+    approve_requests(time());
+    ic_cdk::spawn(outbox::deliver());
+}
+
+fn canister_ids() -> CanisterIds {
+    CANISTER_IDS.with(|ids| ids.borrow().get().clone())
+}
+
+#[update(guard = "caller_is_controller")]
+fn set_canister_ids(ids: CanisterIds) {
+    CANISTER_IDS.with(|cell| {
+        cell.borrow_mut()
+            .set(ids)
+            .expect("failed to update the canister IDs");
+    });
+    outbox::schedule();
+}
+
+#[query]
+fn get_canister_ids() -> CanisterIds {
+    canister_ids()
+}
+
+fn caller_is_user_management() -> Result<(), String> {
+    if canister_ids().user_management == Some(ic_cdk::caller()) {
         Ok(())
+    } else {
+        Err("only user_management can call this method".to_string())
+    }
+}
+
+/// Pushes `user`'s new follower count to `user_management`, which ranks user search
+/// results by it. Best effort: a lost notification is corrected by the next follow.
+fn is_private(user: Principal) -> bool {
+    PRIVATE_ACCOUNTS.with(|accounts| accounts.borrow().contains_key(&user))
+}
+
+/// Tells the other canisters that `follower` started or stopped following `following`.
+/// `post_management` keeps a snapshot of every edge, to decide who sees private
+/// accounts and followers-only posts.
+fn edge_changed(follower: Principal, following: Principal, followed: bool) {
+    outbox::push(Change::FollowerCountUpdated { user: following });
+    outbox::push(if followed {
+        Change::FollowersAdded {
+            user: following,
+            followers: vec![follower],
+        }
+    } else {
+        Change::FollowerRemoved {
+            user: following,
+            follower,
+        }
+    });
+}
+
+fn update_stats(user: Principal, change: impl FnOnce(&mut SocialStats)) {
+    STATS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        let mut entry = stats.get(&user).unwrap_or_default();
+        change(&mut entry);
+        stats.insert(user, entry);
+    });
+}
+
+/// Records that `follower` follows `following`. Returns `false` if they already did.
+fn insert_edge(follower: Principal, following: Principal, followed_at: u64) -> bool {
+    let inserted = EDGES.with(|edges| {
+        let mut edges = edges.borrow_mut();
+        if edges.contains_key(&(follower, following)) {
+            return false;
+        }
+        edges.insert((follower, following), followed_at);
+        true
+    });
+    if !inserted {
+        return false;
+    }
+
+    FOLLOWERS.with(|index| index.borrow_mut().insert((following, followed_at, follower), ()));
+    FOLLOWING.with(|index| index.borrow_mut().insert((follower, followed_at, following), ()));
+    update_stats(following, |stats| stats.followers_count += 1);
+    update_stats(follower, |stats| stats.following_count += 1);
+    true
+}
+
+/// Removes the edge from `follower` to `following`. Returns `false` if there was none.
+fn remove_edge(follower: Principal, following: Principal) -> bool {
+    let Some(followed_at) = EDGES.with(|edges| edges.borrow_mut().remove(&(follower, following))) else {
+        return false;
+    };
+
+    FOLLOWERS.with(|index| index.borrow_mut().remove(&(following, followed_at, follower)));
+    FOLLOWING.with(|index| index.borrow_mut().remove(&(follower, followed_at, following)));
+    update_stats(following, |stats| {
+        stats.followers_count = stats.followers_count.saturating_sub(1)
+    });
+    update_stats(follower, |stats| {
+        stats.following_count = stats.following_count.saturating_sub(1)
+    });
+    true
+}
+
+/// Records a request from `requester` to follow `target`. Returns `false` if one is
+/// already pending.
+fn insert_request(requester: Principal, target: Principal, requested_at: u64) -> bool {
+    let inserted = SENT_FOLLOW_REQUESTS.with(|sent| {
+        let mut sent = sent.borrow_mut();
+        if sent.contains_key(&(requester, target)) {
+            return false;
+        }
+        sent.insert((requester, target), requested_at);
+        true
+    });
+    if inserted {
+        FOLLOW_REQUESTS.with(|requests| requests.borrow_mut().insert((target, requested_at, requester), ()));
+    }
+    inserted
+}
+
+/// Drops the request from `requester` to follow `target`. Returns `false` if there was
+/// none.
+fn remove_request(requester: Principal, target: Principal) -> bool {
+    let Some(requested_at) = SENT_FOLLOW_REQUESTS.with(|sent| sent.borrow_mut().remove(&(requester, target))) else {
+        return false;
+    };
+    FOLLOW_REQUESTS.with(|requests| requests.borrow_mut().remove(&(target, requested_at, requester)));
+    true
+}
+
+/// The largest possible principal, for the upper bound of a per-user range.
+fn max_principal() -> Principal {
+    Principal::from_slice(&[u8::MAX; Principal::MAX_LENGTH_IN_BYTES])
+}
+
+fn encode_cursor(followed_at: u64, other: Principal) -> Cursor {
+    [&followed_at.to_be_bytes()[..], other.as_slice()].concat()
+}
+
+fn decode_cursor(cursor: &[u8]) -> Result<(u64, Principal), GraphError> {
+    let followed_at = cursor.get(..8).and_then(|bytes| bytes.try_into().ok());
+    let other = cursor.get(8..).and_then(|bytes| Principal::try_from_slice(bytes).ok());
+    match (followed_at, other) {
+        (Some(followed_at), Some(other)) => Ok((u64::from_be_bytes(followed_at), other)),
+        _ => Err(GraphError::InvalidCursor),
+    }
+}
+
+/// One page of `user`'s side of `index`, most recent follow first.
+fn adjacency_page(
+    index: &StableBTreeMap<AdjacencyKey, (), Memory>,
+    user: Principal,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Principal>, GraphError> {
+    let before = cursor.as_deref().map(decode_cursor).transpose()?;
+    let limit = page_size(limit);
+    let oldest = Bound::Included((user, 0, Principal::management_canister()));
+    let newest = match before {
+        Some((followed_at, other)) => Bound::Excluded((user, followed_at, other)),
+        None => Bound::Included((user, u64::MAX, max_principal())),
+    };
+
+    let mut entries: Vec<(u64, Principal)> = index
+        .range((oldest, newest))
+        .rev()
+        .take(limit + 1)
+        .map(|((_, followed_at, other), _)| (followed_at, other))
+        .collect();
+
+    let next_cursor = (entries.len() > limit).then(|| {
+        let (followed_at, other) = entries[limit - 1];
+        encode_cursor(followed_at, other)
+    });
+    entries.truncate(limit);
+    Ok(Page {
+        items: entries.into_iter().map(|(_, other)| other).collect(),
+        next_cursor,
     })
 }
 
-#[update]
-fn unfollow_user(following: Principal) -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot unfollow others".to_string());
-    }
-    
-    let key = follow_key(caller, following);
-    
-    FOLLOWS.with(|follows| {
-        let mut follows = follows.borrow_mut();
-        
-        if !follows.contains_key(&key) {
-            return Err("Not following this user".to_string());
-        }
-        
-        follows.remove(&key);
-        
-        // Update followers list
-        FOLLOWERS.with(|followers| {
-            let mut followers = followers.borrow_mut();
-            if let Some(mut follower_list) = followers.get(&following) {
-                follower_list.retain(|&x| x != caller);
-                followers.insert(following, follower_list);
-            }
-        });
-        
-        // Update following list
-        FOLLOWING.with(|following_map| {
-            let mut following_map = following_map.borrow_mut();
-            if let Some(mut following_list) = following_map.get(&caller) {
-                following_list.retain(|&x| x != following);
-                following_map.insert(caller, following_list);
-            }
+/// Every principal on `user`'s side of `index`, oldest follow first.
+fn adjacent(index: &StableBTreeMap<AdjacencyKey, (), Memory>, user: Principal) -> Vec<Principal> {
+    first_adjacent(index, user, usize::MAX)
+}
+
+/// The first `limit` principals on `user`'s side of `index`, oldest follow first.
+fn first_adjacent(
+    index: &StableBTreeMap<AdjacencyKey, (), Memory>,
+    user: Principal,
+    limit: usize,
+) -> Vec<Principal> {
+    index
+        .range((user, 0, Principal::management_canister())..)
+        .take_while(|((owner, _, _), _)| *owner == user)
+        .take(limit)
+        .map(|((_, _, other), _)| other)
+        .collect()
+}
+
+/// Removes the next batch of `user`'s follow edges and follow requests, in both
+/// directions, and of the blocks they made. Returns the users who lost a follower
+/// along with whether nothing is left.
+fn remove_edges_of(user: Principal) -> (Vec<Principal>, bool) {
+    let mut budget = REMOVAL_BATCH;
+    let following = FOLLOWING.with(|index| first_adjacent(&index.borrow(), user, budget));
+    for &other in &following {
+        remove_edge(user, other);
+    }
+    budget -= following.len();
+    let followers = FOLLOWERS.with(|index| first_adjacent(&index.borrow(), user, budget));
+    for &other in &followers {
+        remove_edge(other, user);
+    }
+    budget -= followers.len();
+
+    let requesters = FOLLOW_REQUESTS.with(|requests| first_adjacent(&requests.borrow(), user, budget));
+    for &requester in &requesters {
+        remove_request(requester, user);
+    }
+    budget -= requesters.len();
+    let requested: Vec<Principal> = SENT_FOLLOW_REQUESTS.with(|sent| {
+        sent.borrow()
+            .range((user, Principal::management_canister())..)
+            .take_while(|((requester, _), _)| *requester == user)
+            .take(budget)
+            .map(|((_, target), _)| target)
+            .collect()
+    });
+    for &target in &requested {
+        remove_request(user, target);
+    }
+    budget -= requested.len();
+    let blocked = BLOCKED.with(|index| first_adjacent(&index.borrow(), user, budget));
+    for &other in &blocked {
+        remove_block(user, other);
+    }
+    budget -= blocked.len();
+
+    let done = budget > 0;
+    if done {
+        STATS.with(|stats| stats.borrow_mut().remove(&user));
+        PRIVATE_ACCOUNTS.with(|accounts| accounts.borrow_mut().remove(&user));
+        PENDING_APPROVALS.with(|accounts| accounts.borrow_mut().remove(&user));
+    }
+    (following, done)
+}
+
+/// Called by `user_management` while deleting `user`'s account, until it returns `true`.
+/// Each call removes a bounded batch of the edges and follow requests from and to `user`
+/// and of the blocks `user` made. Blocks against `user` stay in place.
+#[update(guard = "caller_is_user_management")]
+fn remove_user(user: Principal) -> bool {
+    let was_private = is_private(user);
+    let (unfollowed, done) = remove_edges_of(user);
+    for other in unfollowed {
+        edge_changed(user, other, false);
+    }
+    if done && was_private {
+        outbox::push(Change::AccountPrivacyChanged {
+            user,
+            is_private: false,
         });
-        
-        Ok(())
+    }
+    done
+}
+
+/// Makes `user` private or public. Going public leaves the pending requests for
+/// `approve_pending` to let in; going private again before it is done stops it.
+fn set_privacy(user: Principal, private: bool) {
+    if private {
+        PRIVATE_ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(user, ()));
+        PENDING_APPROVALS.with(|accounts| accounts.borrow_mut().remove(&user));
+        return;
+    }
+    PRIVATE_ACCOUNTS.with(|accounts| accounts.borrow_mut().remove(&user));
+    let has_requests = FOLLOW_REQUESTS.with(|requests| !first_adjacent(&requests.borrow(), user, 1).is_empty());
+    if has_requests {
+        PENDING_APPROVALS.with(|accounts| accounts.borrow_mut().insert(user, ()));
+    }
+}
+
+/// Approves up to `limit` of the requests, oldest first, to accounts that went public
+/// at `now`. Returns the requesters approved for each account.
+fn approve_pending(limit: usize, now: u64) -> Vec<(Principal, Vec<Principal>)> {
+    let mut approved = Vec::new();
+    let mut budget = limit;
+    while budget > 0 {
+        let Some((user, ())) = PENDING_APPROVALS.with(|accounts| accounts.borrow().first_key_value()) else {
+            break;
+        };
+        let requesters = FOLLOW_REQUESTS.with(|requests| first_adjacent(&requests.borrow(), user, budget));
+        for &requester in &requesters {
+            remove_request(requester, user);
+            insert_edge(requester, user, now);
+        }
+        budget -= requesters.len();
+        if budget > 0 {
+            PENDING_APPROVALS.with(|accounts| accounts.borrow_mut().remove(&user));
+        }
+        if !requesters.is_empty() {
+            approved.push((user, requesters));
+        }
+    }
+    approved
+}
+
+/// Runs the next batch of approvals and tells the other canisters about them.
+fn approve_requests(now: u64) {
+    for (user, followers) in approve_pending(APPROVAL_BATCH, now) {
+        outbox::push(Change::FollowerCountUpdated { user });
+        outbox::push(Change::FollowersAdded { user, followers });
+    }
+    schedule_approvals();
+}
+
+/// Arms the timer if accounts that went public still have requests to approve.
+fn schedule_approvals() {
+    if PENDING_APPROVALS.with(|accounts| !accounts.borrow().is_empty()) {
+        timer::arm(time());
+    }
+}
+
+/// Mirrors the privacy setting `user_management` stores for `user`, and passes it on
+/// to `post_management`. Pending requests to an account going public are approved in
+/// batches afterwards.
+#[update(guard = "caller_is_user_management")]
+fn set_account_privacy(user: Principal, is_private: bool) {
+    set_privacy(user, is_private);
+    outbox::push(Change::AccountPrivacyChanged { user, is_private });
+    schedule_approvals();
+}
+
+/// Up to `limit` follow edges as `(follower, following)`, in key order after `after`.
+fn edges_after(after: Option<(Principal, Principal)>, limit: usize) -> Vec<(Principal, Principal)> {
+    let start = match after {
+        Some(edge) => Bound::Excluded(edge),
+        None => Bound::Unbounded,
+    };
+    EDGES.with(|edges| {
+        edges
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(edge, _)| edge)
+            .collect()
     })
 }
 
+/// Queues the next batch of follow edges after `after` for `post_management`'s follower
+/// snapshot and returns the last one queued, or `None` once every edge has been.
+/// Only needed once, to fill the snapshot with follows made before it existed; call
+/// it again with the returned edge until it returns `None`.
+#[update(guard = "caller_is_controller")]
+fn sync_follower_snapshot(after: Option<(Principal, Principal)>) -> Option<(Principal, Principal)> {
+    canister_ids().post_management?;
+    let edges = edges_after(after, FOLLOWER_SNAPSHOT_BATCH);
+    let mut followers: BTreeMap<Principal, Vec<Principal>> = BTreeMap::new();
+    for &(follower, following) in &edges {
+        followers.entry(following).or_default().push(follower);
+    }
+    for (user, followers) in followers {
+        outbox::push(Change::FollowersAdded { user, followers });
+    }
+    edges.last().copied().filter(|_| edges.len() == FOLLOWER_SNAPSHOT_BATCH)
+}
+
+/// Follows `following`, or if they are private, asks to.
+fn follow(follower: Principal, following: Principal, now: u64) -> Result<FollowStatus, GraphError> {
+    if follower == following {
+        return Err(GraphError::CannotFollowSelf);
+    }
+    if is_following(follower, following) {
+        return Err(GraphError::AlreadyFollowing { user: following });
+    }
+    if is_blocked(follower, following) || is_blocked(following, follower) {
+        return Err(GraphError::Blocked { user: following });
+    }
+    if is_private(following) {
+        if !insert_request(follower, following, now) {
+            return Err(GraphError::FollowRequestPending { user: following });
+        }
+        return Ok(FollowStatus::Requested);
+    }
+    // A request made while the account was private may still await approval.
+    remove_request(follower, following);
+    insert_edge(follower, following, now);
+    Ok(FollowStatus::Following)
+}
+
+/// Follows `following` at once, or for a private account, sends them a follow request
+/// to approve.
+#[update]
+fn follow_user(following: Principal) -> Result<FollowStatus, GraphError> {
+    let caller = authenticated_caller()?;
+    let status = follow(caller, following, time())?;
+    if status == FollowStatus::Following {
+        edge_changed(caller, following, true);
+    }
+    Ok(status)
+}
+
+#[update]
+fn unfollow_user(following: Principal) -> Result<(), GraphError> {
+    let caller = authenticated_caller()?;
+
+    if !remove_edge(caller, following) {
+        return Err(GraphError::NotFollowing { user: following });
+    }
+    edge_changed(caller, following, false);
+    Ok(())
+}
+
+/// One page of the requests to follow the caller, most recent first.
+#[query]
+fn get_pending_follow_requests(cursor: Option<Cursor>, limit: u64) -> Result<Page<Principal>, GraphError> {
+    let caller = authenticated_caller()?;
+    FOLLOW_REQUESTS.with(|requests| adjacency_page(&requests.borrow(), caller, cursor, limit))
+}
+
+/// Whether `requester` has asked to follow `target` and is still waiting.
+#[query]
+fn has_requested_follow(requester: Principal, target: Principal) -> bool {
+    SENT_FOLLOW_REQUESTS.with(|sent| sent.borrow().contains_key(&(requester, target)))
+}
+
+fn approve(target: Principal, requester: Principal, now: u64) -> Result<(), GraphError> {
+    if !remove_request(requester, target) {
+        return Err(GraphError::NoFollowRequest { user: requester });
+    }
+    insert_edge(requester, target, now);
+    Ok(())
+}
+
+#[update]
+fn approve_follow_request(requester: Principal) -> Result<(), GraphError> {
+    let caller = authenticated_caller()?;
+    approve(caller, requester, time())?;
+    edge_changed(requester, caller, true);
+    Ok(())
+}
+
+#[update]
+fn reject_follow_request(requester: Principal) -> Result<(), GraphError> {
+    let caller = authenticated_caller()?;
+    if !remove_request(requester, caller) {
+        return Err(GraphError::NoFollowRequest { user: requester });
+    }
+    Ok(())
+}
+
+/// Withdraws the caller's request to follow `target`.
+#[update]
+fn cancel_follow_request(target: Principal) -> Result<(), GraphError> {
+    let caller = authenticated_caller()?;
+    if !remove_request(caller, target) {
+        return Err(GraphError::NoFollowRequest { user: target });
+    }
+    Ok(())
+}
+
+/// Records that `blocker` blocked `blocked` at `now`, and drops every follow edge and
+/// follow request between them. Returns the edges removed, as `(follower, following)`.
+fn block(blocker: Principal, blocked: Principal, now: u64) -> Result<Vec<(Principal, Principal)>, GraphError> {
+    if blocker == blocked {
+        return Err(GraphError::CannotBlockSelf);
+    }
+    if is_blocked(blocker, blocked) {
+        return Err(GraphError::AlreadyBlocked { user: blocked });
+    }
+    BLOCKS.with(|blocks| blocks.borrow_mut().insert((blocker, blocked), now));
+    BLOCKED.with(|index| index.borrow_mut().insert((blocker, now, blocked), ()));
+
+    remove_request(blocker, blocked);
+    remove_request(blocked, blocker);
+    Ok([(blocker, blocked), (blocked, blocker)]
+        .into_iter()
+        .filter(|&(follower, following)| remove_edge(follower, following))
+        .collect())
+}
+
+/// Lifts the block of `blocked` by `blocker`. Returns `false` if there was none.
+fn remove_block(blocker: Principal, blocked: Principal) -> bool {
+    let Some(blocked_at) = BLOCKS.with(|blocks| blocks.borrow_mut().remove(&(blocker, blocked))) else {
+        return false;
+    };
+    BLOCKED.with(|index| index.borrow_mut().remove(&(blocker, blocked_at, blocked)));
+    true
+}
+
+/// Blocks `user`: unfollows each other, drops pending follow requests between you and
+/// keeps `user` from following you or seeing your posts until unblocked.
+#[update]
+fn block_user(user: Principal) -> Result<(), GraphError> {
+    let caller = authenticated_caller()?;
+    for (follower, following) in block(caller, user, time())? {
+        edge_changed(follower, following, false);
+    }
+    outbox::push(Change::UserBlocked {
+        blocker: caller,
+        blocked: user,
+    });
+    Ok(())
+}
+
+/// Unblocks `user`. Follow edges removed by the block are not restored.
+#[update]
+fn unblock_user(user: Principal) -> Result<(), GraphError> {
+    let caller = authenticated_caller()?;
+    if !remove_block(caller, user) {
+        return Err(GraphError::NotBlocked { user });
+    }
+    outbox::push(Change::UserUnblocked {
+        blocker: caller,
+        blocked: user,
+    });
+    Ok(())
+}
+
+/// One page of the users the caller blocked, most recent first.
+#[query]
+fn get_blocked(cursor: Option<Cursor>, limit: u64) -> Result<Page<Principal>, GraphError> {
+    let caller = authenticated_caller()?;
+    BLOCKED.with(|index| adjacency_page(&index.borrow(), caller, cursor, limit))
+}
+
+/// Whether `blocker` has blocked `blocked`.
+#[query]
+fn is_blocked(blocker: Principal, blocked: Principal) -> bool {
+    BLOCKS.with(|blocks| blocks.borrow().contains_key(&(blocker, blocked)))
+}
+
 #[query]
 fn is_following(follower: Principal, following: Principal) -> bool {
-    let key = follow_key(follower, following);
-    FOLLOWS.with(|follows| {
-        let follows = follows.borrow();
-        follows.contains_key(&key)
-    })
+    EDGES.with(|edges| edges.borrow().contains_key(&(follower, following)))
 }
 
+/// One page of `user`'s followers, most recent first.
 #[query]
-fn get_followers(user: Principal) -> Vec<Principal> {
-    FOLLOWERS.with(|followers| {
-        let followers = followers.borrow();
-        followers.get(&user).unwrap_or_default()
-    })
+fn get_followers(
+    user: Principal,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Principal>, GraphError> {
+    FOLLOWERS.with(|index| adjacency_page(&index.borrow(), user, cursor, limit))
 }
 
+/// One page of the users `user` follows, most recent first.
 #[query]
-fn get_following(user: Principal) -> Vec<Principal> {
-    FOLLOWING.with(|following| {
-        let following = following.borrow();
-        following.get(&user).unwrap_or_default()
-    })
+fn get_following(
+    user: Principal,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<Page<Principal>, GraphError> {
+    FOLLOWING.with(|index| adjacency_page(&index.borrow(), user, cursor, limit))
 }
 
 #[query]
 fn get_social_stats(user: Principal) -> SocialStats {
-    let followers_count = get_followers(user).len() as u64;
-    let following_count = get_following(user).len() as u64;
-    
-    SocialStats {
-        followers_count,
-        following_count,
-    }
+    STATS.with(|stats| stats.borrow().get(&user).unwrap_or_default())
 }
 
 #[query]
 fn get_mutual_followers(user1: Principal, user2: Principal) -> Vec<Principal> {
-    let followers1 = get_followers(user1);
-    let followers2 = get_followers(user2);
-    
+    let followers1 = FOLLOWERS.with(|index| adjacent(&index.borrow(), user1));
+
     followers1
         .into_iter()
-        .filter(|follower| followers2.contains(follower))
+        .filter(|&follower| is_following(follower, user2))
         .collect()
 }
 
 #[query]
 fn get_follow_suggestions(user: Principal, limit: u64) -> Vec<Principal> {
-    let following = get_following(user);
+    let following = FOLLOWING.with(|index| adjacent(&index.borrow(), user));
     let mut suggestions = Vec::new();
-    
+    let mut seen = BTreeSet::new();
+
     // Get followers of people you follow (friends of friends)
     for followed_user in following.iter() {
-        let their_following = get_following(*followed_user);
+        let their_following = FOLLOWING.with(|index| adjacent(&index.borrow(), *followed_user));
         for suggestion in their_following {
-            if suggestion != user && !following.contains(&suggestion) && !suggestions.contains(&suggestion) {
+            if suggestion != user
+                && !is_following(user, suggestion)
+                && !is_blocked(user, suggestion)
+                && !is_blocked(suggestion, user)
+                && seen.insert(suggestion)
+            {
                 suggestions.push(suggestion);
                 if suggestions.len() >= limit as usize {
                     break;
@@ -206,8 +748,287 @@ fn get_follow_suggestions(user: Principal, limit: u64) -> Vec<Principal> {
             break;
         }
     }
-    
+
     suggestions
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+    use ic_stable_structures::memory_manager::MemoryManager;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    type State = (
+        StableBTreeMap<(Principal, Principal), u64, Memory>,
+        StableBTreeMap<AdjacencyKey, (), Memory>,
+        StableBTreeMap<Principal, SocialStats, Memory>,
+    );
+
+    /// Opens the canister's stable structures on top of `memory` the same way a
+    /// freshly installed or upgraded module does.
+    fn open(memory: &DefaultMemoryImpl) -> State {
+        let manager = MemoryManager::init(memory.clone());
+        (
+            StableBTreeMap::init(manager.get(EDGES_MEMORY_ID)),
+            StableBTreeMap::init(manager.get(FOLLOWERS_MEMORY_ID)),
+            StableBTreeMap::init(manager.get(STATS_MEMORY_ID)),
+        )
+    }
+
+    #[test]
+    fn follow_edges_survive_upgrade() {
+        let memory = DefaultMemoryImpl::default();
+        let alice = principal(1);
+        let bob = principal(2);
+
+        {
+            let (mut edges, mut followers, mut stats) = open(&memory);
+            edges.insert((alice, bob), 42);
+            followers.insert((bob, 42, alice), ());
+            stats.insert(
+                bob,
+                SocialStats {
+                    followers_count: 1,
+                    following_count: 0,
+                },
+            );
+        }
+
+        let (edges, followers, stats) = open(&memory);
+        assert_eq!(edges.get(&(alice, bob)), Some(42));
+        assert!(edges.get(&(bob, alice)).is_none());
+        assert!(followers.contains_key(&(bob, 42, alice)));
+        assert_eq!(stats.get(&bob).unwrap().followers_count, 1);
+    }
+
+    #[test]
+    fn pages_followers_newest_first_and_keeps_counts() {
+        let star = principal(9);
+        for n in 1..=5 {
+            assert!(insert_edge(principal(n), star, u64::from(n) * 10));
+        }
+        assert!(!insert_edge(principal(1), star, 99));
+        assert!(remove_edge(principal(3), star));
+        assert!(!remove_edge(principal(3), star));
+
+        let first = get_followers(star, None, 2).unwrap();
+        assert_eq!(first.items, vec![principal(5), principal(4)]);
+        let second = get_followers(star, first.next_cursor, 2).unwrap();
+        assert_eq!(second.items, vec![principal(2), principal(1)]);
+        assert!(second.next_cursor.is_none());
+
+        assert_eq!(get_following(principal(1), None, 10).unwrap().items, vec![star]);
+        assert_eq!(
+            get_social_stats(star),
+            SocialStats {
+                followers_count: 4,
+                following_count: 0,
+            }
+        );
+        assert_eq!(get_social_stats(principal(2)).following_count, 1);
+        assert_eq!(get_social_stats(principal(3)).following_count, 0);
+    }
+
+    #[test]
+    fn pages_through_every_edge_for_the_follower_snapshot() {
+        for n in 1..=3 {
+            insert_edge(principal(n), principal(9), u64::from(n));
+            insert_edge(principal(9), principal(n), u64::from(n));
+        }
+
+        let first = edges_after(None, 4);
+        assert_eq!(first.len(), 4);
+        let rest = edges_after(first.last().copied(), 4);
+        assert_eq!(rest.len(), 2);
+        let all: BTreeSet<_> = first.into_iter().chain(rest).collect();
+        assert_eq!(all.len(), 6);
+        assert!(all.contains(&(principal(9), principal(2))));
+    }
+
+    #[test]
+    fn removing_a_user_clears_edges_in_both_directions() {
+        let leaving = principal(1);
+        for n in 2..=4 {
+            insert_edge(leaving, principal(n), u64::from(n));
+            insert_edge(principal(n), leaving, u64::from(n));
+        }
+        insert_edge(principal(2), principal(3), 9);
+
+        let (unfollowed, done) = remove_edges_of(leaving);
+        assert!(done);
+        assert_eq!(unfollowed, vec![principal(2), principal(3), principal(4)]);
+        assert!(!is_following(leaving, principal(2)));
+        assert!(!is_following(principal(2), leaving));
+        assert!(is_following(principal(2), principal(3)));
+        assert_eq!(get_social_stats(leaving), SocialStats::default());
+        assert_eq!(
+            get_social_stats(principal(3)),
+            SocialStats {
+                followers_count: 1,
+                following_count: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn following_a_private_account_needs_approval() {
+        let (owner, fan, other) = (principal(1), principal(2), principal(3));
+        set_privacy(owner, true);
+
+        assert_eq!(follow(fan, owner, 10), Ok(FollowStatus::Requested));
+        assert_eq!(
+            follow(fan, owner, 11),
+            Err(GraphError::FollowRequestPending { user: owner })
+        );
+        assert_eq!(follow(other, owner, 12), Ok(FollowStatus::Requested));
+        assert!(!is_following(fan, owner));
+        assert!(has_requested_follow(fan, owner));
+
+        let pending = FOLLOW_REQUESTS.with(|requests| adjacency_page(&requests.borrow(), owner, None, 10));
+        assert_eq!(pending.unwrap().items, vec![other, fan]);
+
+        assert_eq!(approve(owner, fan, 20), Ok(()));
+        assert!(is_following(fan, owner));
+        assert!(!has_requested_follow(fan, owner));
+        assert_eq!(approve(owner, fan, 21), Err(GraphError::NoFollowRequest { user: fan }));
+        assert_eq!(follow(fan, owner, 22), Err(GraphError::AlreadyFollowing { user: owner }));
+
+        // Going public lets everyone still waiting in.
+        set_privacy(owner, false);
+        assert_eq!(follow(principal(4), owner, 30), Ok(FollowStatus::Following));
+        assert_eq!(approve_pending(10, 31), vec![(owner, vec![other])]);
+        assert!(is_following(other, owner));
+        assert!(PENDING_APPROVALS.with(|accounts| accounts.borrow().is_empty()));
+    }
+
+    #[test]
+    fn going_public_approves_requests_in_batches() {
+        let owner = principal(1);
+        set_privacy(owner, true);
+        for n in 2..=4 {
+            follow(principal(n), owner, n as u64).unwrap();
+        }
+
+        set_privacy(owner, false);
+        assert_eq!(approve_pending(2, 10), vec![(owner, vec![principal(2), principal(3)])]);
+        assert!(has_requested_follow(principal(4), owner));
+
+        // Turning private again leaves the rest waiting for approval.
+        set_privacy(owner, true);
+        assert!(approve_pending(2, 11).is_empty());
+        assert!(has_requested_follow(principal(4), owner));
+
+        set_privacy(owner, false);
+        assert_eq!(approve_pending(2, 12), vec![(owner, vec![principal(4)])]);
+        assert_eq!(get_social_stats(owner).followers_count, 3);
+        assert!(PENDING_APPROVALS.with(|accounts| accounts.borrow().is_empty()));
+    }
+
+    #[test]
+    fn removing_a_user_drops_their_follow_requests() {
+        let (leaving, private) = (principal(1), principal(2));
+        set_privacy(private, true);
+        set_privacy(leaving, true);
+        follow(leaving, private, 1).unwrap();
+        follow(private, leaving, 2).unwrap();
+
+        let (_, done) = remove_edges_of(leaving);
+        assert!(done);
+        assert!(!has_requested_follow(leaving, private));
+        assert!(!has_requested_follow(private, leaving));
+        assert!(!is_private(leaving));
+        assert!(FOLLOW_REQUESTS.with(|requests| requests.borrow().is_empty()));
+    }
+
+    #[test]
+    fn blocking_cuts_edges_both_ways_and_prevents_following() {
+        let (blocker, pest, bystander) = (principal(1), principal(2), principal(3));
+        insert_edge(blocker, pest, 1);
+        insert_edge(pest, blocker, 2);
+        insert_edge(bystander, blocker, 3);
+
+        assert_eq!(block(blocker, pest, 10), Ok(vec![(blocker, pest), (pest, blocker)]));
+        assert!(!is_following(blocker, pest));
+        assert!(!is_following(pest, blocker));
+        assert!(is_following(bystander, blocker));
+        assert!(is_blocked(blocker, pest));
+        assert!(!is_blocked(pest, blocker));
+        assert_eq!(get_social_stats(blocker).followers_count, 1);
+
+        assert_eq!(block(blocker, pest, 11), Err(GraphError::AlreadyBlocked { user: pest }));
+        assert_eq!(block(blocker, blocker, 11), Err(GraphError::CannotBlockSelf));
+        assert_eq!(follow(pest, blocker, 12), Err(GraphError::Blocked { user: blocker }));
+        assert_eq!(follow(blocker, pest, 12), Err(GraphError::Blocked { user: pest }));
+
+        block(blocker, bystander, 13).unwrap();
+        let blocked = BLOCKED.with(|index| adjacency_page(&index.borrow(), blocker, None, 10));
+        assert_eq!(blocked.unwrap().items, vec![bystander, pest]);
+
+        assert!(remove_block(blocker, pest));
+        assert!(!remove_block(blocker, pest));
+        assert_eq!(follow(pest, blocker, 14), Ok(FollowStatus::Following));
+
+        let (_, done) = remove_edges_of(blocker);
+        assert!(done);
+        assert!(BLOCKS.with(|blocks| blocks.borrow().is_empty()));
+        assert!(BLOCKED.with(|index| index.borrow().is_empty()));
+    }
+
+    #[test]
+    fn blocking_drops_pending_follow_requests() {
+        let (private, pest) = (principal(1), principal(2));
+        set_privacy(private, true);
+        follow(pest, private, 1).unwrap();
+
+        assert_eq!(block(private, pest, 2), Ok(Vec::new()));
+        assert!(!has_requested_follow(pest, private));
+        assert!(FOLLOW_REQUESTS.with(|requests| requests.borrow().is_empty()));
+    }
+
+    #[test]
+    fn imports_string_keyed_follows() {
+        let alice = principal(1);
+        let bob = principal(2);
+        {
+            let mut legacy: StableBTreeMap<String, Follow, Memory> =
+                StableBTreeMap::init(memory::get(LEGACY_FOLLOWS_MEMORY_ID));
+            legacy.insert(
+                format!("{alice}#{bob}"),
+                Follow {
+                    follower: alice,
+                    following: bob,
+                    created_at: 7,
+                },
+            );
+        }
+
+        import_legacy_follows();
+        assert!(is_following(alice, bob));
+        assert!(!is_following(bob, alice));
+        assert_eq!(get_followers(bob, None, 10).unwrap().items, vec![alice]);
+        assert_eq!(get_social_stats(alice).following_count, 1);
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        assert_eq!(
+            get_followers(principal(1), Some(vec![1, 2, 3]), 10).unwrap_err(),
+            GraphError::InvalidCursor
+        );
+    }
+
+    #[test]
+    fn committed_candid_matches_exported_interface() {
+        use candid_parser::utils::{service_compatible, CandidSource};
+
+        let committed = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("social_graph.did");
+        service_compatible(
+            CandidSource::File(&committed),
+            CandidSource::Text(&__export_service()),
+        )
+        .expect("social_graph.did is out of date; run scripts/generate_candid.sh");
+    }
+}
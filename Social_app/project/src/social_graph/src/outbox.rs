@@ -0,0 +1,183 @@
+//! Changes mirrored to the other canisters: `post_management` keeps its own copy of
+//! follow edges, private accounts and blocks to filter posts by, and `user_management`
+//! ranks users by follower count. Each change is queued here by the message that makes
+//! it and delivered from the global timer by awaited calls, in order. A change leaves
+//! the queue only once its canister has applied it; a failed call is retried after
+//! `RETRY_DELAY` and holds back everything behind it, so the mirror never sees changes
+//! out of order. Applying a change twice is harmless, which covers a delivery cut short
+//! by an upgrade.
+
+use crate::{canister_ids, get_social_stats, OUTBOX_MEMORY_ID};
+use candid::{CandidType, Principal};
+use ic_cdk::api::call::CallResult;
+use ic_cdk::api::time;
+use ic_stable_structures::StableBTreeMap;
+use serde::{Deserialize, Serialize};
+use social_types::memory::{self, Memory};
+use social_types::versioned::Versioned;
+use social_types::{timer, versioned_storable};
+use std::cell::{Cell, RefCell};
+
+/// Changes delivered per firing of the timer.
+const DELIVERY_BATCH: usize = 100;
+
+/// Nanoseconds to wait before retrying a change that was not applied.
+const RETRY_DELAY: u64 = 60 * 1_000_000_000;
+
+/// One change to another canister, named after the method that applies it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Change {
+    FollowersAdded { user: Principal, followers: Vec<Principal> },
+    FollowerRemoved { user: Principal, follower: Principal },
+    AccountPrivacyChanged { user: Principal, is_private: bool },
+    UserBlocked { blocker: Principal, blocked: Principal },
+    UserUnblocked { blocker: Principal, blocked: Principal },
+    /// Sent to `user_management` with the count at delivery, so it is never stale.
+    FollowerCountUpdated { user: Principal },
+}
+
+impl Change {
+    /// The canister that applies this change, if one is configured.
+    fn target(&self) -> Option<Principal> {
+        match self {
+            Change::FollowerCountUpdated { .. } => canister_ids().user_management,
+            _ => canister_ids().post_management,
+        }
+    }
+}
+
+impl Versioned for Change {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(Change);
+
+thread_local! {
+    /// Changes yet to be applied, in the order they were made.
+    static OUTBOX: RefCell<StableBTreeMap<u64, Change, Memory>> = memory::init_map(OUTBOX_MEMORY_ID);
+    /// Set while a delivery is waiting on a call, so only one runs at a time.
+    static DELIVERING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn enqueue(change: Change) {
+    OUTBOX.with(|outbox| {
+        let mut outbox = outbox.borrow_mut();
+        let next = outbox.last_key_value().map_or(0, |(seq, _)| seq + 1);
+        outbox.insert(next, change);
+    });
+}
+
+/// Queues `change` for its canister, if one is configured.
+pub(crate) fn push(change: Change) {
+    if change.target().is_some() {
+        enqueue(change);
+        timer::arm(time());
+    }
+}
+
+/// Arms the timer if changes are waiting to be delivered.
+pub(crate) fn schedule() {
+    if OUTBOX.with(|outbox| !outbox.borrow().is_empty()) {
+        timer::arm(time());
+    }
+}
+
+async fn apply(target: Principal, change: Change) -> CallResult<()> {
+    match change {
+        Change::FollowersAdded { user, followers } => {
+            ic_cdk::call(target, "followers_added", (user, followers)).await
+        }
+        Change::FollowerRemoved { user, follower } => {
+            ic_cdk::call(target, "follower_removed", (user, follower)).await
+        }
+        Change::AccountPrivacyChanged { user, is_private } => {
+            ic_cdk::call(target, "account_privacy_changed", (user, is_private)).await
+        }
+        Change::UserBlocked { blocker, blocked } => {
+            ic_cdk::call(target, "user_blocked", (blocker, blocked)).await
+        }
+        Change::UserUnblocked { blocker, blocked } => {
+            ic_cdk::call(target, "user_unblocked", (blocker, blocked)).await
+        }
+        Change::FollowerCountUpdated { user } => {
+            let followers_count = get_social_stats(user).followers_count;
+            ic_cdk::call(target, "follower_count_updated", (user, followers_count)).await
+        }
+    }
+}
+
+/// Delivers the next batch of queued changes, oldest first, and arms the timer for
+/// whatever is left: at once, or after `RETRY_DELAY` if a call failed. Changes for a
+/// canister that is no longer configured are dropped.
+pub(crate) async fn deliver() {
+    if DELIVERING.with(|delivering| delivering.replace(true)) {
+        return;
+    }
+    let mut failed = false;
+    for _ in 0..DELIVERY_BATCH {
+        let Some((seq, change)) = OUTBOX.with(|outbox| outbox.borrow().first_key_value()) else {
+            break;
+        };
+        if let Some(target) = change.target() {
+            if apply(target, change).await.is_err() {
+                failed = true;
+                break;
+            }
+        }
+        OUTBOX.with(|outbox| outbox.borrow_mut().remove(&seq));
+    }
+    DELIVERING.with(|delivering| delivering.set(false));
+
+    if failed {
+        timer::arm(time().saturating_add(RETRY_DELAY));
+    } else {
+        schedule();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+    use crate::CANISTER_IDS;
+    use social_types::CanisterIds;
+
+    #[test]
+    fn queues_changes_in_the_order_they_were_made() {
+        let (user, follower) = (principal(1), principal(2));
+        enqueue(Change::FollowersAdded {
+            user,
+            followers: vec![follower],
+        });
+        enqueue(Change::FollowerRemoved { user, follower });
+        OUTBOX.with(|outbox| outbox.borrow_mut().remove(&0));
+        enqueue(Change::AccountPrivacyChanged { user, is_private: true });
+
+        let queued: Vec<(u64, Change)> = OUTBOX.with(|outbox| outbox.borrow().iter().collect());
+        assert_eq!(
+            queued,
+            vec![
+                (1, Change::FollowerRemoved { user, follower }),
+                (2, Change::AccountPrivacyChanged { user, is_private: true }),
+            ]
+        );
+    }
+
+    #[test]
+    fn sends_follower_counts_to_user_management() {
+        let (user_management, post_management) = (principal(8), principal(9));
+        CANISTER_IDS.with(|ids| {
+            ids.borrow_mut()
+                .set(CanisterIds {
+                    user_management: Some(user_management),
+                    post_management: Some(post_management),
+                    social_graph: None,
+                })
+                .unwrap();
+        });
+        let user = principal(1);
+
+        assert_eq!(Change::FollowerCountUpdated { user }.target(), Some(user_management));
+        assert_eq!(Change::AccountPrivacyChanged { user, is_private: true }.target(), Some(post_management));
+    }
+}
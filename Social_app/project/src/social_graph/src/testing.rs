@@ -0,0 +1,8 @@
+//! Helpers shared by the unit tests.
+
+use candid::Principal;
+
+/// A distinct principal for each `n`.
+pub(crate) fn principal(n: u8) -> Principal {
+    Principal::from_slice(&[n; 29])
+}
@@ -0,0 +1,104 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum UserError {
+    AnonymousCaller,
+    AlreadyRegistered,
+    UsernameTaken { suggestion: Option<String> },
+    InvalidUsername { reason: UsernameRejection },
+    /// Usernames can be changed again from `available_at`.
+    UsernameChangeCooldown { available_at: u64 },
+    NotFound,
+    /// The caller's account is being deleted; call `delete_account` again to finish.
+    DeletionInProgress,
+    /// A canister this call depends on is not configured or did not answer.
+    Unavailable { canister: String },
+}
+
+/// Why a username cannot be registered.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum UsernameRejection {
+    TooShort { min: u32 },
+    TooLong { max: u32 },
+    /// Only letters, digits and `_` are allowed.
+    InvalidCharacter { character: String },
+    /// Letters from more than one script, e.g. Latin mixed with Cyrillic.
+    MixedScripts,
+    Reserved,
+    Taken { suggestion: Option<String> },
+    /// Looks like a registered username, e.g. `a1ice` when `alice` is taken.
+    Confusable,
+}
+
+impl From<UsernameRejection> for UserError {
+    fn from(reason: UsernameRejection) -> Self {
+        match reason {
+            UsernameRejection::Taken { suggestion } => UserError::UsernameTaken { suggestion },
+            reason => UserError::InvalidUsername { reason },
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum PostError {
+    AnonymousCaller,
+    EmptyContent,
+    NotFound { post_id: u64 },
+    NotAuthor { post_id: u64 },
+    NotAReply { post_id: u64 },
+    NotEditable { post_id: u64 },
+    /// The edit window set by the controllers has passed.
+    EditWindowClosed { post_id: u64 },
+    AlreadyReposted { post_id: u64 },
+    NotReposted { post_id: u64 },
+    /// Followers-only and direct posts cannot be reposted or quoted.
+    NotShareable { post_id: u64 },
+    CannotMuteSelf,
+    NotMuted { user: Principal },
+    TooManyMutes { max: u32 },
+    TooManyMutedWords { max: u32 },
+    InvalidCursor,
+    /// A canister this call depends on is not configured or did not answer.
+    Unavailable { canister: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    AnonymousCaller,
+    CannotFollowSelf,
+    AlreadyFollowing { user: Principal },
+    NotFollowing { user: Principal },
+    /// `user` is private and already has a follow request from the caller.
+    FollowRequestPending { user: Principal },
+    NoFollowRequest { user: Principal },
+    CannotBlockSelf,
+    AlreadyBlocked { user: Principal },
+    NotBlocked { user: Principal },
+    /// The caller and `user` cannot follow each other because one has blocked the other.
+    Blocked { user: Principal },
+    InvalidCursor,
+}
+
+/// Returned by [`crate::guards::authenticated_caller`]; converts into the
+/// `AnonymousCaller` variant of each canister's error type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnonymousCaller;
+
+impl From<AnonymousCaller> for UserError {
+    fn from(_: AnonymousCaller) -> Self {
+        UserError::AnonymousCaller
+    }
+}
+
+impl From<AnonymousCaller> for PostError {
+    fn from(_: AnonymousCaller) -> Self {
+        PostError::AnonymousCaller
+    }
+}
+
+impl From<AnonymousCaller> for GraphError {
+    fn from(_: AnonymousCaller) -> Self {
+        GraphError::AnonymousCaller
+    }
+}
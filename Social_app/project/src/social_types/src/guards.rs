@@ -0,0 +1,45 @@
+use crate::errors::AnonymousCaller;
+use candid::Principal;
+
+/// Returns the caller of the current message, rejecting the anonymous principal.
+pub fn authenticated_caller() -> Result<Principal, AnonymousCaller> {
+    non_anonymous(ic_cdk::caller())
+}
+
+/// Guard for `#[update(guard = "caller_is_controller")]` admin endpoints.
+pub fn caller_is_controller() -> Result<(), String> {
+    if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("only controllers can call this method".to_string())
+    }
+}
+
+pub fn non_anonymous(principal: Principal) -> Result<Principal, AnonymousCaller> {
+    if principal == Principal::anonymous() {
+        Err(AnonymousCaller)
+    } else {
+        Ok(principal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::PostError;
+
+    #[test]
+    fn anonymous_principal_is_rejected() {
+        assert_eq!(non_anonymous(Principal::anonymous()), Err(AnonymousCaller));
+
+        let user = Principal::from_slice(&[1; 29]);
+        assert_eq!(non_anonymous(user), Ok(user));
+    }
+
+    #[test]
+    fn rejection_converts_into_canister_errors() {
+        let result: Result<Principal, PostError> =
+            non_anonymous(Principal::anonymous()).map_err(Into::into);
+        assert_eq!(result, Err(PostError::AnonymousCaller));
+    }
+}
@@ -0,0 +1,14 @@
+//! Types and helpers shared by the `user_management`, `post_management` and
+//! `social_graph` canisters, so payloads exchanged between them are type-checked
+//! on both sides.
+
+pub mod errors;
+pub mod guards;
+pub mod memory;
+pub mod pagination;
+pub mod timer;
+pub mod types;
+pub mod versioned;
+
+pub use errors::{GraphError, PostError, UserError, UsernameRejection};
+pub use types::{CanisterIds, Follow, LikeGiven, Mention, Post, PostWithAuthor, UserSummary, Visibility};
@@ -0,0 +1,36 @@
+//! Memory-manager setup shared by the canisters. Each canister gets its own copy of
+//! the manager, and lays out its stable structures by `MemoryId`.
+
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use std::cell::RefCell;
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+}
+
+/// Returns the virtual memory reserved for `id`.
+pub fn get(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(id))
+}
+
+/// Opens the stable map kept in memory `id`, ready to be stored in a `thread_local!`.
+pub fn init_map<K, V>(id: MemoryId) -> RefCell<StableBTreeMap<K, V, Memory>>
+where
+    K: Storable + Ord + Clone,
+    V: Storable,
+{
+    RefCell::new(StableBTreeMap::init(get(id)))
+}
+
+/// Opens the stable cell kept in memory `id`, writing `default` if it is empty.
+pub fn init_cell<T: Storable>(id: MemoryId, default: T) -> RefCell<StableCell<T, Memory>> {
+    RefCell::new(
+        StableCell::init(get(id), default)
+            .unwrap_or_else(|err| panic!("failed to initialize stable cell {id:?}: {err:?}")),
+    )
+}
@@ -0,0 +1,23 @@
+//! Cursor-based pagination shared by the listing endpoints.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Opaque position in a listing. Clients pass back the `next_cursor` of the
+/// previous page and must not interpret its contents.
+pub type Cursor = Vec<u8>;
+
+/// Upper bound applied to every `limit` argument.
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// `None` once the listing is exhausted.
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Clamps a requested page size to `1..=MAX_PAGE_SIZE`.
+pub fn page_size(limit: u64) -> usize {
+    limit.clamp(1, MAX_PAGE_SIZE) as usize
+}
@@ -0,0 +1,13 @@
+//! The system global timer, shared by everything in a canister that needs to run at
+//! a given time. A canister exports `canister_global_timer`, and re-arms the timer
+//! for its earliest remaining deadline each time it fires and after every upgrade,
+//! which clears it.
+
+/// Makes the global timer fire no later than `deadline` (nanoseconds since the
+/// epoch), keeping an earlier deadline that is already armed.
+pub fn arm(deadline: u64) {
+    let previous = ic_cdk::api::set_global_timer(deadline);
+    if previous != 0 && previous < deadline {
+        ic_cdk::api::set_global_timer(previous);
+    }
+}
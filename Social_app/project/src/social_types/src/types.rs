@@ -0,0 +1,382 @@
+use crate::versioned::{Migration, Versioned};
+use crate::versioned_storable;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// The public part of a user profile, as shown next to posts and in user lists.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UserSummary {
+    pub principal: Principal,
+    pub username: String,
+    pub display_name: String,
+    pub avatar_url: String,
+    pub is_verified: bool,
+}
+
+impl Versioned for UserSummary {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(UserSummary);
+
+/// A post or a reply to one. Who liked it is kept in a separate index; only the
+/// count lives here.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Post {
+    pub id: u64,
+    pub author: Principal,
+    pub content: String,
+    pub media_urls: Vec<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub like_count: u64,
+    /// The post this replies to; `None` for top-level posts.
+    pub parent_post_id: Option<u64>,
+    /// Author of the parent post, kept so a reply can say who it answers even after
+    /// the parent is deleted.
+    pub reply_to: Option<Principal>,
+    pub reply_count: u64,
+    /// Set by the parent post's author to keep a reply out of reply listings.
+    pub hidden: bool,
+    /// The post this reposts. Reposts have no content of their own.
+    pub repost_of: Option<u64>,
+    /// The post this quotes. It may since have been deleted.
+    pub quote_of: Option<u64>,
+    pub repost_count: u64,
+    pub quote_count: u64,
+    /// `@username`s in `content` that named a user when the post was last written.
+    pub mentions: Vec<Mention>,
+    pub visibility: Visibility,
+    /// How many times the post was edited. Earlier versions are kept as revisions.
+    pub edit_count: u32,
+}
+
+/// Who may read a post. Only public posts appear in the public timeline, hashtag
+/// listings, trends and search; the others are read through their author's profile,
+/// feeds and mentions by whoever they are meant for.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Public,
+    /// Readable by anyone, but kept out of public listings.
+    Unlisted,
+    FollowersOnly,
+    /// Readable by the users mentioned in it only.
+    Direct,
+}
+
+/// An `@username` in a post's content, with the byte range it covers.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Mention {
+    pub user: Principal,
+    pub username: String,
+    /// Byte offset of the `@`.
+    pub start: u64,
+    /// Byte offset just past the username.
+    pub end: u64,
+}
+
+/// `Post` as stored before likes moved to their own index.
+#[derive(CandidType, Deserialize)]
+struct PostV1 {
+    id: u64,
+    author: Principal,
+    content: String,
+    media_urls: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    #[allow(dead_code)]
+    likes: Vec<Principal>,
+    like_count: u64,
+}
+
+/// `Post` as stored before replies.
+#[derive(CandidType, Deserialize)]
+struct PostV2 {
+    id: u64,
+    author: Principal,
+    content: String,
+    media_urls: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    like_count: u64,
+}
+
+/// `Post` as stored before reposts and quotes.
+#[derive(CandidType, Deserialize)]
+struct PostV3 {
+    id: u64,
+    author: Principal,
+    content: String,
+    media_urls: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    like_count: u64,
+    parent_post_id: Option<u64>,
+    reply_to: Option<Principal>,
+    reply_count: u64,
+    hidden: bool,
+}
+
+/// `Post` as stored before mentions.
+#[derive(CandidType, Deserialize)]
+struct PostV4 {
+    id: u64,
+    author: Principal,
+    content: String,
+    media_urls: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    like_count: u64,
+    parent_post_id: Option<u64>,
+    reply_to: Option<Principal>,
+    reply_count: u64,
+    hidden: bool,
+    repost_of: Option<u64>,
+    quote_of: Option<u64>,
+    repost_count: u64,
+    quote_count: u64,
+}
+
+/// `Post` as stored before visibility levels.
+#[derive(CandidType, Deserialize)]
+struct PostV5 {
+    id: u64,
+    author: Principal,
+    content: String,
+    media_urls: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    like_count: u64,
+    parent_post_id: Option<u64>,
+    reply_to: Option<Principal>,
+    reply_count: u64,
+    hidden: bool,
+    repost_of: Option<u64>,
+    quote_of: Option<u64>,
+    repost_count: u64,
+    quote_count: u64,
+    mentions: Vec<Mention>,
+}
+
+/// `Post` as stored before edit history.
+#[derive(CandidType, Deserialize)]
+struct PostV6 {
+    id: u64,
+    author: Principal,
+    content: String,
+    media_urls: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    like_count: u64,
+    parent_post_id: Option<u64>,
+    reply_to: Option<Principal>,
+    reply_count: u64,
+    hidden: bool,
+    repost_of: Option<u64>,
+    quote_of: Option<u64>,
+    repost_count: u64,
+    quote_count: u64,
+    mentions: Vec<Mention>,
+    visibility: Visibility,
+}
+
+fn post_v1_to_v2(bytes: &[u8]) -> Vec<u8> {
+    let old: PostV1 = candid::decode_one(bytes).expect("failed to decode a version 1 post");
+    candid::encode_one(PostV2 {
+        id: old.id,
+        author: old.author,
+        content: old.content,
+        media_urls: old.media_urls,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        like_count: old.like_count,
+    })
+    .expect("failed to encode a version 2 post")
+}
+
+fn post_v2_to_v3(bytes: &[u8]) -> Vec<u8> {
+    let old: PostV2 = candid::decode_one(bytes).expect("failed to decode a version 2 post");
+    candid::encode_one(PostV3 {
+        id: old.id,
+        author: old.author,
+        content: old.content,
+        media_urls: old.media_urls,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        like_count: old.like_count,
+        parent_post_id: None,
+        reply_to: None,
+        reply_count: 0,
+        hidden: false,
+    })
+    .expect("failed to encode a version 3 post")
+}
+
+fn post_v3_to_v4(bytes: &[u8]) -> Vec<u8> {
+    let old: PostV3 = candid::decode_one(bytes).expect("failed to decode a version 3 post");
+    candid::encode_one(PostV4 {
+        id: old.id,
+        author: old.author,
+        content: old.content,
+        media_urls: old.media_urls,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        like_count: old.like_count,
+        parent_post_id: old.parent_post_id,
+        reply_to: old.reply_to,
+        reply_count: old.reply_count,
+        hidden: old.hidden,
+        repost_of: None,
+        quote_of: None,
+        repost_count: 0,
+        quote_count: 0,
+    })
+    .expect("failed to encode a version 4 post")
+}
+
+fn post_v4_to_v5(bytes: &[u8]) -> Vec<u8> {
+    let old: PostV4 = candid::decode_one(bytes).expect("failed to decode a version 4 post");
+    candid::encode_one(PostV5 {
+        id: old.id,
+        author: old.author,
+        content: old.content,
+        media_urls: old.media_urls,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        like_count: old.like_count,
+        parent_post_id: old.parent_post_id,
+        reply_to: old.reply_to,
+        reply_count: old.reply_count,
+        hidden: old.hidden,
+        repost_of: old.repost_of,
+        quote_of: old.quote_of,
+        repost_count: old.repost_count,
+        quote_count: old.quote_count,
+        mentions: Vec::new(),
+    })
+    .expect("failed to encode a version 5 post")
+}
+
+fn post_v5_to_v6(bytes: &[u8]) -> Vec<u8> {
+    let old: PostV5 = candid::decode_one(bytes).expect("failed to decode a version 5 post");
+    candid::encode_one(PostV6 {
+        id: old.id,
+        author: old.author,
+        content: old.content,
+        media_urls: old.media_urls,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        like_count: old.like_count,
+        parent_post_id: old.parent_post_id,
+        reply_to: old.reply_to,
+        reply_count: old.reply_count,
+        hidden: old.hidden,
+        repost_of: old.repost_of,
+        quote_of: old.quote_of,
+        repost_count: old.repost_count,
+        quote_count: old.quote_count,
+        mentions: old.mentions,
+        visibility: Visibility::Public,
+    })
+    .expect("failed to encode a version 6 post")
+}
+
+fn post_v6_to_v7(bytes: &[u8]) -> Vec<u8> {
+    let old: PostV6 = candid::decode_one(bytes).expect("failed to decode a version 6 post");
+    candid::encode_one(Post {
+        id: old.id,
+        author: old.author,
+        content: old.content,
+        media_urls: old.media_urls,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        like_count: old.like_count,
+        parent_post_id: old.parent_post_id,
+        reply_to: old.reply_to,
+        reply_count: old.reply_count,
+        hidden: old.hidden,
+        repost_of: old.repost_of,
+        quote_of: old.quote_of,
+        repost_count: old.repost_count,
+        quote_count: old.quote_count,
+        mentions: old.mentions,
+        visibility: old.visibility,
+        edit_count: 0,
+    })
+    .expect("failed to encode a version 7 post")
+}
+
+impl Versioned for Post {
+    const VERSION: u8 = 7;
+    const MIGRATIONS: &'static [Migration] = &[
+        Migration {
+            from: 1,
+            apply: post_v1_to_v2,
+        },
+        Migration {
+            from: 2,
+            apply: post_v2_to_v3,
+        },
+        Migration {
+            from: 3,
+            apply: post_v3_to_v4,
+        },
+        Migration {
+            from: 4,
+            apply: post_v4_to_v5,
+        },
+        Migration {
+            from: 5,
+            apply: post_v5_to_v6,
+        },
+        Migration {
+            from: 6,
+            apply: post_v6_to_v7,
+        },
+    ];
+}
+
+versioned_storable!(Post);
+
+/// A post together with its author's profile summary, if the author still exists.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PostWithAuthor {
+    pub post: Post,
+    pub author: Option<UserSummary>,
+}
+
+/// A like a user gave, as listed for their data export.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LikeGiven {
+    pub post_id: u64,
+    pub liked_at: u64,
+}
+
+/// Where the other canisters of the deployment live. Set by a controller after
+/// deploying, since the canisters call each other in both directions.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CanisterIds {
+    pub user_management: Option<Principal>,
+    pub post_management: Option<Principal>,
+    pub social_graph: Option<Principal>,
+}
+
+impl Versioned for CanisterIds {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(CanisterIds);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Follow {
+    pub follower: Principal,
+    pub following: Principal,
+    pub created_at: u64,
+}
+
+impl Versioned for Follow {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(Follow);
@@ -0,0 +1,250 @@
+//! Versioned binary encoding for records kept in stable memory.
+//!
+//! Every record is written as `[MAGIC, version, candid...]`. Records written before
+//! versioning existed are bare Candid (they start with `DIDL`) and are read as
+//! version 1. When a stored version is older than the type's current
+//! [`Versioned::VERSION`], the type's [`Migration`]s are applied one step at a time
+//! before decoding, so old records are upgraded lazily on every read. Canisters can
+//! also call [`migrate_map`] from `post_upgrade` to rewrite a whole map eagerly.
+//!
+//! To evolve a stored type: keep the previous shape around as a private struct, bump
+//! `VERSION`, and register a migration from the previous version that decodes the old
+//! shape and encodes the new one.
+
+use candid::CandidType;
+use ic_stable_structures::{Memory, StableBTreeMap, Storable};
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+
+/// First byte of every versioned record. Bare Candid always starts with `D`.
+pub const MAGIC: u8 = 0xFE;
+
+/// Version assigned to records stored as bare Candid, before tagging was introduced.
+pub const LEGACY_VERSION: u8 = 1;
+
+const CANDID_MAGIC: &[u8] = b"DIDL";
+
+/// Upgrades the Candid payload of a record from `from` to `from + 1`.
+pub struct Migration {
+    pub from: u8,
+    pub apply: fn(&[u8]) -> Vec<u8>,
+}
+
+/// A record type stored in stable memory under an explicit schema version.
+pub trait Versioned: CandidType + DeserializeOwned {
+    /// The version written by this build.
+    const VERSION: u8;
+
+    /// One migration per version step below [`Self::VERSION`].
+    const MIGRATIONS: &'static [Migration] = &[];
+}
+
+/// Encodes `value` with the current version tag.
+pub fn encode<T: Versioned>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![MAGIC, T::VERSION];
+    bytes.extend(candid::encode_one(value).expect("failed to encode stored record"));
+    bytes
+}
+
+/// Decodes a stored record, running migrations if it was written by an older build.
+pub fn decode<T: Versioned>(bytes: &[u8]) -> T {
+    let (mut version, payload) = split(bytes);
+    let mut payload = Cow::Borrowed(payload);
+
+    while version < T::VERSION {
+        let migration = T::MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == version)
+            .unwrap_or_else(|| panic!("no migration registered from version {version}"));
+        payload = Cow::Owned((migration.apply)(&payload));
+        version += 1;
+    }
+
+    assert_eq!(
+        version,
+        T::VERSION,
+        "stored record version {version} is newer than this build supports"
+    );
+    candid::decode_one(&payload).expect("failed to decode stored record")
+}
+
+/// Returns the version a record was stored under.
+pub fn stored_version(bytes: &[u8]) -> u8 {
+    split(bytes).0
+}
+
+fn split(bytes: &[u8]) -> (u8, &[u8]) {
+    match bytes {
+        [MAGIC, version, payload @ ..] => (*version, payload),
+        _ if bytes.starts_with(CANDID_MAGIC) => (LEGACY_VERSION, bytes),
+        _ => panic!("stored record has an unknown encoding"),
+    }
+}
+
+/// Rewrites every value in `map` with the current encoding, returning the number of
+/// entries rewritten. Reads already migrate lazily; this makes the upgrade permanent.
+pub fn migrate_map<K, V, M>(map: &mut StableBTreeMap<K, V, M>) -> u64
+where
+    K: Storable + Ord + Clone,
+    V: Storable,
+    M: Memory,
+{
+    let entries: Vec<(K, V)> = map.iter().collect();
+    let count = entries.len() as u64;
+    for (key, value) in entries {
+        map.insert(key, value);
+    }
+    count
+}
+
+/// Implements [`Storable`] for a [`Versioned`] type using the tagged encoding.
+#[macro_export]
+macro_rules! versioned_storable {
+    ($type:ty) => {
+        impl ::ic_stable_structures::Storable for $type {
+            fn to_bytes(&self) -> ::std::borrow::Cow<'_, [u8]> {
+                ::std::borrow::Cow::Owned($crate::versioned::encode(self))
+            }
+
+            fn from_bytes(bytes: ::std::borrow::Cow<[u8]>) -> Self {
+                $crate::versioned::decode(&bytes)
+            }
+
+            const BOUND: ::ic_stable_structures::storable::Bound =
+                ::ic_stable_structures::storable::Bound::Unbounded;
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+    use serde::Deserialize;
+
+    #[derive(CandidType, Deserialize, Debug, PartialEq)]
+    struct NoteV1 {
+        text: String,
+    }
+
+    #[derive(CandidType, Deserialize, Debug, PartialEq)]
+    struct NoteV2 {
+        text: String,
+        pinned: bool,
+    }
+
+    #[derive(CandidType, Deserialize, Debug, PartialEq)]
+    struct Note {
+        text: String,
+        pinned: bool,
+        tags: Vec<String>,
+    }
+
+    impl Versioned for Note {
+        const VERSION: u8 = 3;
+        const MIGRATIONS: &'static [Migration] = &[
+            Migration {
+                from: 1,
+                apply: |bytes| {
+                    let old: NoteV1 = candid::decode_one(bytes).unwrap();
+                    candid::encode_one(NoteV2 {
+                        text: old.text,
+                        pinned: false,
+                    })
+                    .unwrap()
+                },
+            },
+            Migration {
+                from: 2,
+                apply: |bytes| {
+                    let old: NoteV2 = candid::decode_one(bytes).unwrap();
+                    candid::encode_one(Note {
+                        text: old.text,
+                        pinned: old.pinned,
+                        tags: Vec::new(),
+                    })
+                    .unwrap()
+                },
+            },
+        ];
+    }
+
+    crate::versioned_storable!(Note);
+
+    fn note(text: &str) -> Note {
+        Note {
+            text: text.to_string(),
+            pinned: true,
+            tags: vec!["a".to_string()],
+        }
+    }
+
+    #[test]
+    fn round_trips_current_version() {
+        let bytes = encode(&note("hello"));
+        assert_eq!(&bytes[..2], &[MAGIC, 3]);
+        assert_eq!(stored_version(&bytes), 3);
+        assert_eq!(decode::<Note>(&bytes), note("hello"));
+    }
+
+    #[test]
+    fn bare_candid_is_read_as_legacy_version() {
+        let legacy = candid::encode_one(NoteV1 {
+            text: "old".to_string(),
+        })
+        .unwrap();
+        assert_eq!(stored_version(&legacy), LEGACY_VERSION);
+
+        let decoded: Note = decode(&legacy);
+        assert_eq!(decoded.text, "old");
+        assert!(!decoded.pinned);
+        assert!(decoded.tags.is_empty());
+    }
+
+    #[test]
+    fn migrations_start_from_the_stored_version() {
+        let mut bytes = vec![MAGIC, 2];
+        bytes.extend(
+            candid::encode_one(NoteV2 {
+                text: "v2".to_string(),
+                pinned: true,
+            })
+            .unwrap(),
+        );
+
+        let decoded: Note = decode(&bytes);
+        assert!(decoded.pinned);
+        assert_eq!(decoded.text, "v2");
+    }
+
+    #[test]
+    #[should_panic(expected = "newer than this build")]
+    fn rejects_records_from_newer_builds() {
+        let mut bytes = vec![MAGIC, 4];
+        bytes.extend(candid::encode_one(note("future")).unwrap());
+        decode::<Note>(&bytes);
+    }
+
+    #[test]
+    fn migrate_map_rewrites_legacy_entries() {
+        let memory = DefaultMemoryImpl::default();
+        {
+            let mut raw: StableBTreeMap<u64, Vec<u8>, _> = StableBTreeMap::init(memory.clone());
+            let legacy = candid::encode_one(NoteV1 {
+                text: "legacy".to_string(),
+            })
+            .unwrap();
+            raw.insert(1, legacy);
+            raw.insert(2, encode(&note("current")));
+        }
+
+        let mut notes: StableBTreeMap<u64, Note, _> = StableBTreeMap::init(memory.clone());
+        assert_eq!(migrate_map(&mut notes), 2);
+
+        let raw: StableBTreeMap<u64, Vec<u8>, _> = StableBTreeMap::init(memory);
+        for (_, bytes) in raw.iter() {
+            assert_eq!(stored_version(&bytes), Note::VERSION);
+        }
+        assert_eq!(notes.get(&1).unwrap().text, "legacy");
+    }
+}
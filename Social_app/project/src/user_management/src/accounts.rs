@@ -0,0 +1,186 @@
+//! Account deletion. The profile and username go at once; the user's posts, likes and
+//! follow edges live in other canisters and are removed there in batches. Progress is
+//! recorded in a tombstone after every step, so a deletion interrupted by a failed call
+//! or an upgrade resumes where it stopped when `delete_account` is called again, and
+//! the tombstone stays behind as a record of the deletion.
+
+use crate::{canister_ids, export, unavailable, search, username_changes, validation, ACCOUNT_TOMBSTONES_MEMORY_ID, USERS};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::StableBTreeMap;
+use serde::{Deserialize, Serialize};
+use social_types::guards::{authenticated_caller, caller_is_controller};
+use social_types::memory::{self, Memory};
+use social_types::versioned::Versioned;
+use social_types::{versioned_storable, UserError};
+use std::cell::RefCell;
+
+/// What is left of a deleted account, and how far its deletion has got.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountTombstone {
+    pub principal: Principal,
+    pub username: String,
+    /// When the account was created.
+    pub created_at: u64,
+    /// When its deletion was requested.
+    pub requested_at: u64,
+    /// Posts and likes are gone from `post_management`.
+    pub content_removed: bool,
+    /// Follow edges are gone from `social_graph`.
+    pub graph_removed: bool,
+    pub completed_at: Option<u64>,
+}
+
+impl Versioned for AccountTombstone {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(AccountTombstone);
+
+thread_local! {
+    /// `(principal, requested_at)`, so an account deleted more than once keeps a
+    /// tombstone per deletion.
+    static TOMBSTONES: RefCell<StableBTreeMap<(Principal, u64), AccountTombstone, Memory>> = memory::init_map(ACCOUNT_TOMBSTONES_MEMORY_ID);
+}
+
+fn tombstones_of(user: Principal) -> Vec<AccountTombstone> {
+    TOMBSTONES.with(|tombstones| {
+        tombstones
+            .borrow()
+            .range((user, 0)..)
+            .take_while(|((principal, _), _)| *principal == user)
+            .map(|(_, tombstone)| tombstone)
+            .collect()
+    })
+}
+
+/// The tombstone of `user`'s unfinished deletion, if one is under way.
+pub(crate) fn pending_deletion(user: Principal) -> Option<AccountTombstone> {
+    tombstones_of(user)
+        .into_iter()
+        .find(|tombstone| tombstone.completed_at.is_none())
+}
+
+fn save(tombstone: &AccountTombstone) {
+    TOMBSTONES.with(|tombstones| {
+        tombstones
+            .borrow_mut()
+            .insert((tombstone.principal, tombstone.requested_at), tombstone.clone())
+    });
+}
+
+/// Removes everything this canister holds for `user` and records the tombstone that
+/// tracks the rest of the deletion.
+fn begin_deletion(user: Principal, now: u64) -> Result<AccountTombstone, UserError> {
+    let profile = USERS
+        .with(|users| users.borrow_mut().remove(&user))
+        .ok_or(UserError::NotFound)?;
+    search::forget(&profile);
+    validation::release(&validation::canonical(&profile.username), user);
+    username_changes::forget(user);
+    export::forget(user);
+
+    let tombstone = AccountTombstone {
+        principal: user,
+        username: profile.username,
+        created_at: profile.created_at,
+        requested_at: now,
+        content_removed: false,
+        graph_removed: false,
+        completed_at: None,
+    };
+    save(&tombstone);
+    Ok(tombstone)
+}
+
+/// Calls `method` on `canister` with `user` until it reports that nothing is left.
+async fn drain(canister: Option<Principal>, name: &str, method: &str, user: Principal) -> Result<(), UserError> {
+    let canister = canister.ok_or_else(|| unavailable(name))?;
+    loop {
+        let (done,): (bool,) = ic_cdk::call(canister, method, (user,))
+            .await
+            .map_err(|_| unavailable(name))?;
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs whatever steps of a deletion are still outstanding, saving after each.
+async fn finish_deletion(mut tombstone: AccountTombstone) -> Result<AccountTombstone, UserError> {
+    let ids = canister_ids();
+    let user = tombstone.principal;
+    if !tombstone.content_removed {
+        drain(ids.post_management, "post_management", "delete_user_content", user).await?;
+        tombstone.content_removed = true;
+        save(&tombstone);
+    }
+    if !tombstone.graph_removed {
+        drain(ids.social_graph, "social_graph", "remove_user", user).await?;
+        tombstone.graph_removed = true;
+        save(&tombstone);
+    }
+    tombstone.completed_at = Some(time());
+    save(&tombstone);
+    Ok(tombstone)
+}
+
+/// Deletes the caller's account: their profile and username at once, then their posts,
+/// the likes they gave and every follow edge to or from them. If a step fails the
+/// error names the canister that did not answer, and calling again resumes from there.
+#[update]
+async fn delete_account() -> Result<AccountTombstone, UserError> {
+    let caller = authenticated_caller()?;
+    let tombstone = match pending_deletion(caller) {
+        Some(tombstone) => tombstone,
+        None => begin_deletion(caller, time())?,
+    };
+    finish_deletion(tombstone).await
+}
+
+/// Every recorded deletion of `principal`'s account, oldest first.
+#[query(guard = "caller_is_controller")]
+fn get_account_tombstones(principal: Principal) -> Vec<AccountTombstone> {
+    tombstones_of(principal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_user, principal};
+    use crate::USERNAMES;
+
+    #[test]
+    fn frees_the_profile_and_username_at_once() {
+        let alice = principal(1);
+        let profile = add_user(alice, "alice");
+
+        let tombstone = begin_deletion(alice, 10).unwrap();
+        assert_eq!(tombstone.username, "alice");
+        assert_eq!(tombstone.created_at, profile.created_at);
+        assert!(USERS.with(|users| !users.borrow().contains_key(&alice)));
+        assert!(USERNAMES.with(|usernames| usernames.borrow().is_empty()));
+        assert!(validation::check_available("alice", principal(2)).is_ok());
+
+        assert_eq!(pending_deletion(alice), Some(tombstone));
+        assert_eq!(begin_deletion(alice, 11).unwrap_err(), UserError::NotFound);
+    }
+
+    #[test]
+    fn keeps_a_tombstone_per_deletion() {
+        let bob = principal(1);
+        add_user(bob, "bob");
+        let mut first = begin_deletion(bob, 10).unwrap();
+        first.completed_at = Some(20);
+        save(&first);
+        assert_eq!(pending_deletion(bob), None);
+
+        add_user(bob, "bob");
+        begin_deletion(bob, 30).unwrap();
+        let tombstones = get_account_tombstones(bob);
+        assert_eq!(tombstones.len(), 2);
+        assert_eq!(tombstones[0], first);
+        assert_eq!(pending_deletion(bob).unwrap().requested_at, 30);
+    }
+}
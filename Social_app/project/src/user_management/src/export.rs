@@ -0,0 +1,222 @@
+//! Personal data export. `export_my_data` gathers the caller's profile from here, their
+//! posts and the likes they gave from `post_management`, and their followers and
+//! followings from `social_graph` into one JSON document. The document is kept in
+//! chunks small enough for a single response, and the client fetches them one by one
+//! with `get_export_chunk`.
+
+use crate::{canister_ids, unavailable, UserProfile, DATA_EXPORTS_MEMORY_ID, EXPORT_CHUNKS_MEMORY_ID, USERS};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::StableBTreeMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use social_types::guards::authenticated_caller;
+use social_types::memory::{self, Memory};
+use social_types::pagination::{Cursor, Page, MAX_PAGE_SIZE};
+use social_types::versioned::Versioned;
+use social_types::{versioned_storable, GraphError, LikeGiven, Post, PostError, UserError};
+use std::cell::RefCell;
+
+/// Version of the exported document's layout. Bump whenever a field is added, removed
+/// or changes meaning, so that importers can tell the layouts apart.
+const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Bytes per chunk, well below the response size limit.
+const EXPORT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The exported document, serialized to JSON.
+#[derive(Serialize)]
+struct ExportDocument {
+    format_version: u32,
+    exported_at: u64,
+    profile: UserProfile,
+    posts: Vec<Post>,
+    likes_given: Vec<LikeGiven>,
+    followers: Vec<Principal>,
+    following: Vec<Principal>,
+}
+
+/// A user's latest export, whose document is served in `chunk_count` chunks.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DataExport {
+    pub format_version: u32,
+    pub created_at: u64,
+    /// Size of the whole document in bytes.
+    pub size: u64,
+    pub chunk_count: u32,
+}
+
+impl Versioned for DataExport {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(DataExport);
+
+thread_local! {
+    /// Each user's latest export. A new export replaces the previous one.
+    static EXPORTS: RefCell<StableBTreeMap<Principal, DataExport, Memory>> = memory::init_map(DATA_EXPORTS_MEMORY_ID);
+    /// `(user, index)` to each chunk of their latest export's document.
+    static CHUNKS: RefCell<StableBTreeMap<(Principal, u32), Vec<u8>, Memory>> = memory::init_map(EXPORT_CHUNKS_MEMORY_ID);
+}
+
+/// Every item of a paged listing of `user`'s data on `canister`, fetched one page at a
+/// time.
+async fn collect<T, E>(canister: Option<Principal>, name: &str, method: &str, user: Principal) -> Result<Vec<T>, UserError>
+where
+    T: CandidType + DeserializeOwned,
+    E: CandidType + DeserializeOwned,
+{
+    let canister = canister.ok_or_else(|| unavailable(name))?;
+    let mut items = Vec::new();
+    let mut cursor: Option<Cursor> = None;
+    loop {
+        let (page,): (Result<Page<T>, E>,) = ic_cdk::call(canister, method, (user, cursor, MAX_PAGE_SIZE))
+            .await
+            .map_err(|_| unavailable(name))?;
+        let page = page.map_err(|_| unavailable(name))?;
+        items.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Drops `user`'s export, if any.
+pub(crate) fn forget(user: Principal) {
+    let Some(export) = EXPORTS.with(|exports| exports.borrow_mut().remove(&user)) else {
+        return;
+    };
+    CHUNKS.with(|chunks| {
+        let mut chunks = chunks.borrow_mut();
+        for index in 0..export.chunk_count {
+            chunks.remove(&(user, index));
+        }
+    });
+}
+
+/// Serializes `document` and stores it in chunks as `user`'s latest export.
+fn store(user: Principal, document: &ExportDocument) -> DataExport {
+    let json = serde_json::to_vec(document).expect("failed to serialize a data export");
+    forget(user);
+    let mut chunk_count = 0;
+    CHUNKS.with(|chunks| {
+        let mut chunks = chunks.borrow_mut();
+        for chunk in json.chunks(EXPORT_CHUNK_SIZE) {
+            chunks.insert((user, chunk_count), chunk.to_vec());
+            chunk_count += 1;
+        }
+    });
+    let export = DataExport {
+        format_version: document.format_version,
+        created_at: document.exported_at,
+        size: json.len() as u64,
+        chunk_count,
+    };
+    EXPORTS.with(|exports| exports.borrow_mut().insert(user, export.clone()));
+    export
+}
+
+/// Exports the caller's profile, posts, likes given, followers and followings as one
+/// JSON document, replacing any earlier export. Fetch the document with
+/// `get_export_chunk` for each index below `chunk_count` and concatenate the chunks.
+#[update]
+async fn export_my_data() -> Result<DataExport, UserError> {
+    let caller = authenticated_caller()?;
+    let profile = USERS
+        .with(|users| users.borrow().get(&caller))
+        .ok_or(UserError::NotFound)?;
+    let ids = canister_ids();
+
+    let posts = collect::<Post, PostError>(ids.post_management, "post_management", "get_authored_posts", caller).await?;
+    let likes_given = collect::<LikeGiven, PostError>(ids.post_management, "post_management", "get_likes_given", caller).await?;
+    let followers = collect::<Principal, GraphError>(ids.social_graph, "social_graph", "get_followers", caller).await?;
+    let following = collect::<Principal, GraphError>(ids.social_graph, "social_graph", "get_following", caller).await?;
+
+    let document = ExportDocument {
+        format_version: EXPORT_FORMAT_VERSION,
+        exported_at: time(),
+        profile,
+        posts,
+        likes_given,
+        followers,
+        following,
+    };
+    Ok(store(caller, &document))
+}
+
+/// Chunk `index` of the caller's latest export.
+#[query]
+fn get_export_chunk(index: u32) -> Result<Vec<u8>, UserError> {
+    let caller = authenticated_caller()?;
+    CHUNKS
+        .with(|chunks| chunks.borrow().get(&(caller, index)))
+        .ok_or(UserError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+
+    fn document(bio: String) -> ExportDocument {
+        ExportDocument {
+            format_version: EXPORT_FORMAT_VERSION,
+            exported_at: 42,
+            profile: UserProfile {
+                principal: principal(1),
+                username: "alice".to_string(),
+                display_name: "Alice".to_string(),
+                bio,
+                avatar_url: String::new(),
+                created_at: 1,
+                updated_at: 1,
+                is_verified: false,
+                is_private: false,
+            },
+            posts: Vec::new(),
+            likes_given: vec![LikeGiven { post_id: 7, liked_at: 9 }],
+            followers: vec![principal(2)],
+            following: Vec::new(),
+        }
+    }
+
+    fn reassemble(user: Principal, export: &DataExport) -> serde_json::Value {
+        let json: Vec<u8> = (0..export.chunk_count)
+            .flat_map(|index| CHUNKS.with(|chunks| chunks.borrow().get(&(user, index)).unwrap()))
+            .collect();
+        assert_eq!(json.len() as u64, export.size);
+        serde_json::from_slice(&json).unwrap()
+    }
+
+    #[test]
+    fn stores_a_versioned_json_document() {
+        let user = principal(1);
+        let export = store(user, &document("hi".to_string()));
+        assert_eq!(export.chunk_count, 1);
+        assert_eq!(export.format_version, EXPORT_FORMAT_VERSION);
+
+        let json = reassemble(user, &export);
+        assert_eq!(json["format_version"], 1);
+        assert_eq!(json["profile"]["username"], "alice");
+        assert_eq!(json["followers"][0], principal(2).to_text());
+        assert_eq!(json["likes_given"][0]["post_id"], 7);
+    }
+
+    #[test]
+    fn splits_large_documents_and_replaces_old_exports() {
+        let user = principal(1);
+        let large = store(user, &document("x".repeat(EXPORT_CHUNK_SIZE + 10)));
+        assert_eq!(large.chunk_count, 2);
+        assert_eq!(reassemble(user, &large)["profile"]["bio"].as_str().unwrap().len(), EXPORT_CHUNK_SIZE + 10);
+
+        let small = store(user, &document(String::new()));
+        assert_eq!(small.chunk_count, 1);
+        assert!(CHUNKS.with(|chunks| !chunks.borrow().contains_key(&(user, 1))));
+
+        forget(user);
+        assert!(EXPORTS.with(|exports| exports.borrow().is_empty()));
+        assert!(CHUNKS.with(|chunks| chunks.borrow().is_empty()));
+    }
+}
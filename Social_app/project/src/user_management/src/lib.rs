@@ -1,13 +1,50 @@
+mod accounts;
+mod export;
+mod search;
+mod username_changes;
+mod validation;
+#[cfg(test)]
+mod testing;
+
+use accounts::AccountTombstone;
+use export::DataExport;
 use candid::{CandidType, Principal};
 use ic_cdk::api::time;
 use ic_cdk_macros::*;
-use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{StableBTreeMap, StableCell};
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use social_types::guards::{authenticated_caller, caller_is_controller};
+use social_types::memory::{self, Memory};
+use social_types::versioned::{self, Migration, Versioned};
+use social_types::{versioned_storable, CanisterIds, UserError, UserSummary, UsernameRejection};
 use std::cell::RefCell;
 
-type Memory = VirtualMemory<DefaultMemoryImpl>;
+const USERS_MEMORY_ID: MemoryId = MemoryId::new(0);
+const USERNAMES_MEMORY_ID: MemoryId = MemoryId::new(1);
+const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(2);
+const CANISTER_IDS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const NAME_INDEX_MEMORY_ID: MemoryId = MemoryId::new(4);
+const FOLLOWER_COUNTS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const USERNAME_SKELETONS_MEMORY_ID: MemoryId = MemoryId::new(6);
+const RESERVED_USERNAMES_MEMORY_ID: MemoryId = MemoryId::new(7);
+const USERNAME_REDIRECTS_MEMORY_ID: MemoryId = MemoryId::new(8);
+const LAST_USERNAME_CHANGES_MEMORY_ID: MemoryId = MemoryId::new(9);
+const ACCOUNT_TOMBSTONES_MEMORY_ID: MemoryId = MemoryId::new(10);
+const DATA_EXPORTS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const EXPORT_CHUNKS_MEMORY_ID: MemoryId = MemoryId::new(12);
+const USERNAME_REDIRECT_EXPIRIES_MEMORY_ID: MemoryId = MemoryId::new(13);
+
+/// Bump whenever a stored type's `Versioned::VERSION` changes or an index is added,
+/// so that `post_upgrade` rewrites existing records eagerly instead of migrating them
+/// on every read.
+///
+/// 2: name search index.
+/// 3: usernames indexed in canonical form, with look-alike skeletons and reserved names.
+/// 4: private accounts.
+/// 5: expiry-ordered index of username redirects.
+/// 6: usernames fully case-folded; colliding accounts renamed.
+const SCHEMA_VERSION: u32 = 6;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct UserProfile {
@@ -19,6 +56,59 @@ pub struct UserProfile {
     pub created_at: u64,
     pub updated_at: u64,
     pub is_verified: bool,
+    /// Only followers see a private account's posts, and following it takes approval.
+    pub is_private: bool,
+}
+
+/// `UserProfile` as stored before private accounts.
+#[derive(CandidType, Deserialize)]
+struct UserProfileV1 {
+    principal: Principal,
+    username: String,
+    display_name: String,
+    bio: String,
+    avatar_url: String,
+    created_at: u64,
+    updated_at: u64,
+    is_verified: bool,
+}
+
+fn user_profile_v1_to_v2(bytes: &[u8]) -> Vec<u8> {
+    let old: UserProfileV1 = candid::decode_one(bytes).expect("failed to decode a version 1 profile");
+    candid::encode_one(UserProfile {
+        principal: old.principal,
+        username: old.username,
+        display_name: old.display_name,
+        bio: old.bio,
+        avatar_url: old.avatar_url,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        is_verified: old.is_verified,
+        is_private: false,
+    })
+    .expect("failed to encode a version 2 profile")
+}
+
+impl Versioned for UserProfile {
+    const VERSION: u8 = 2;
+    const MIGRATIONS: &'static [Migration] = &[Migration {
+        from: 1,
+        apply: user_profile_v1_to_v2,
+    }];
+}
+
+versioned_storable!(UserProfile);
+
+impl From<&UserProfile> for UserSummary {
+    fn from(profile: &UserProfile) -> Self {
+        UserSummary {
+            principal: profile.principal,
+            username: profile.username.clone(),
+            display_name: profile.display_name.clone(),
+            avatar_url: profile.avatar_url.clone(),
+            is_verified: profile.is_verified,
+        }
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -37,80 +127,187 @@ pub struct UpdateUserRequest {
 }
 
 thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
-    
-    static USERS: RefCell<StableBTreeMap<Principal, UserProfile, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
-        )
-    );
-    
-    static USERNAMES: RefCell<StableBTreeMap<String, Principal, Memory>> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-        )
-    );
+    static USERS: RefCell<StableBTreeMap<Principal, UserProfile, Memory>> = memory::init_map(USERS_MEMORY_ID);
+    static USERNAMES: RefCell<StableBTreeMap<String, Principal, Memory>> = memory::init_map(USERNAMES_MEMORY_ID);
+    static SCHEMA: RefCell<StableCell<u32, Memory>> = memory::init_cell(SCHEMA_VERSION_MEMORY_ID, 0);
+    static CANISTER_IDS: RefCell<StableCell<CanisterIds, Memory>> = memory::init_cell(CANISTER_IDS_MEMORY_ID, CanisterIds::default());
 }
 
-#[update]
-fn create_user(request: CreateUserRequest) -> Result<UserProfile, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot create profiles".to_string());
+/// Rewrites every stored record at its current version if the stored schema predates
+/// this build, and builds any index the stored schema lacks. Records are also migrated
+/// lazily on read, so for them this only makes it permanent.
+fn migrate_storage() {
+    let stored = SCHEMA.with(|schema| *schema.borrow().get());
+    if stored >= SCHEMA_VERSION {
+        return;
     }
-    
+    USERS.with(|map| versioned::migrate_map(&mut map.borrow_mut()));
+    if stored < 2 {
+        search::rebuild();
+    }
+    if stored < 3 {
+        reserve_default_usernames();
+    }
+    if stored < 6 {
+        let users = USERS.with(|users| {
+            users
+                .borrow()
+                .iter()
+                .map(|(principal, user)| (user.created_at, principal, user.username))
+                .collect()
+        });
+        for (user, username) in validation::rebuild(users) {
+            rename_colliding(user, username);
+        }
+        username_changes::reindex();
+    }
+    set_schema_version();
+}
+
+/// Moves `user` to the name `validation::rebuild` gave them because their old one now
+/// collides with an older account's. `post_management` picks up the new name with
+/// their next profile change, since no calls can be made during an upgrade.
+fn rename_colliding(user: Principal, username: String) {
     USERS.with(|users| {
         let mut users = users.borrow_mut();
+        if let Some(mut profile) = users.get(&user) {
+            search::remove(&profile);
+            profile.username = username;
+            search::insert(&profile);
+            users.insert(user, profile);
+        }
+    });
+}
+
+fn set_schema_version() {
+    SCHEMA.with(|schema| {
+        schema
+            .borrow_mut()
+            .set(SCHEMA_VERSION)
+            .expect("failed to update the schema version");
+    });
+}
+
+fn reserve_default_usernames() {
+    let names: Vec<String> = validation::DEFAULT_RESERVED_USERNAMES
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    validation::reserve(&names);
+}
+
+#[init]
+fn init() {
+    reserve_default_usernames();
+    set_schema_version();
+}
+
+/// Profiles and the username index live in stable memory behind the memory manager,
+/// so there is nothing to serialize before an upgrade.
+#[pre_upgrade]
+fn pre_upgrade() {}
+
+#[post_upgrade]
+fn post_upgrade() {
+    migrate_storage();
+    // Upgrades clear the global timer.
+    username_changes::schedule_release();
+}
+
+/// Entry point of the global timer armed through `social_types::timer`.
+#[export_name = "canister_global_timer"]
+extern "C" fn global_timer() {
+    ic_cdk::setup();
+    username_changes::release_expired(time());
+    username_changes::schedule_release();
+}
+
+fn canister_ids() -> CanisterIds {
+    CANISTER_IDS.with(|ids| ids.borrow().get().clone())
+}
+
+#[update(guard = "caller_is_controller")]
+fn set_canister_ids(ids: CanisterIds) {
+    CANISTER_IDS.with(|cell| {
+        cell.borrow_mut()
+            .set(ids)
+            .expect("failed to update the canister IDs");
+    });
+}
+
+#[query]
+fn get_canister_ids() -> CanisterIds {
+    canister_ids()
+}
+
+/// The error for a call to another canister that is not configured or did not answer.
+fn unavailable(canister: &str) -> UserError {
+    UserError::Unavailable {
+        canister: canister.to_string(),
+    }
+}
+
+/// Pushes a new or changed profile summary to `post_management` so its author cache
+/// stays fresh. Best effort: a lost notification only costs a later cache miss.
+fn notify_author_updated(user: &UserProfile) {
+    if let Some(post_management) = canister_ids().post_management {
+        let summary = UserSummary::from(user);
+        let _ = ic_cdk::notify(post_management, "author_updated", (summary,));
+    }
+}
+
+#[update]
+fn create_user(request: CreateUserRequest) -> Result<UserProfile, UserError> {
+    let caller = authenticated_caller()?;
+    
+    let user = USERS.with(|users| {
+        let mut users = users.borrow_mut();
         
         if users.contains_key(&caller) {
-            return Err("User already exists".to_string());
+            return Err(UserError::AlreadyRegistered);
+        }
+        if accounts::pending_deletion(caller).is_some() {
+            return Err(UserError::DeletionInProgress);
         }
+        let username = validation::check_available(&request.username, caller)?;
         
-        USERNAMES.with(|usernames| {
-            let mut usernames = usernames.borrow_mut();
-            
-            if usernames.contains_key(&request.username) {
-                return Err("Username already taken".to_string());
-            }
-            
-            let now = time();
-            let user = UserProfile {
-                principal: caller,
-                username: request.username.clone(),
-                display_name: request.display_name,
-                bio: request.bio,
-                avatar_url: request.avatar_url,
-                created_at: now,
-                updated_at: now,
-                is_verified: false,
-            };
-            
-            users.insert(caller, user.clone());
-            usernames.insert(request.username, caller);
-            
-            Ok(user)
-        })
-    })
+        let now = time();
+        let user = UserProfile {
+            principal: caller,
+            username: request.username,
+            display_name: request.display_name,
+            bio: request.bio,
+            avatar_url: request.avatar_url,
+            created_at: now,
+            updated_at: now,
+            is_verified: false,
+            is_private: false,
+        };
+        
+        users.insert(caller, user.clone());
+        validation::claim(&username, caller);
+        search::insert(&user);
+        
+        Ok(user)
+    })?;
+
+    notify_author_updated(&user);
+    Ok(user)
 }
 
 #[update]
-fn update_user(request: UpdateUserRequest) -> Result<UserProfile, String> {
-    let caller = ic_cdk::caller();
+fn update_user(request: UpdateUserRequest) -> Result<UserProfile, UserError> {
+    let caller = authenticated_caller()?;
     
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot update profiles".to_string());
-    }
-    
-    USERS.with(|users| {
+    let user = USERS.with(|users| {
         let mut users = users.borrow_mut();
         
         match users.get(&caller) {
             Some(mut user) => {
                 if let Some(display_name) = request.display_name {
+                    search::remove(&user);
                     user.display_name = display_name;
+                    search::insert(&user);
                 }
                 if let Some(bio) = request.bio {
                     user.bio = bio;
@@ -123,45 +320,102 @@ fn update_user(request: UpdateUserRequest) -> Result<UserProfile, String> {
                 users.insert(caller, user.clone());
                 Ok(user)
             }
-            None => Err("User not found".to_string()),
+            None => Err(UserError::NotFound),
         }
+    })?;
+
+    notify_author_updated(&user);
+    Ok(user)
+}
+
+/// Makes the caller's account private or public. `social_graph` is told first, since it
+/// decides who may follow; the setting is only stored here once it has taken effect.
+/// Turning public approves every pending follow request.
+#[update]
+async fn set_account_private(is_private: bool) -> Result<UserProfile, UserError> {
+    let caller = authenticated_caller()?;
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err(UserError::NotFound);
+    }
+    let social_graph = canister_ids().social_graph.ok_or_else(|| unavailable("social_graph"))?;
+    ic_cdk::call::<_, ()>(social_graph, "set_account_privacy", (caller, is_private))
+        .await
+        .map_err(|_| unavailable("social_graph"))?;
+
+    // The profile may have been deleted while the call was out.
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        let mut user = users.get(&caller).ok_or(UserError::NotFound)?;
+        user.is_private = is_private;
+        user.updated_at = time();
+        users.insert(caller, user.clone());
+        Ok(user)
     })
 }
 
 #[query]
-fn get_user(principal: Principal) -> Result<UserProfile, String> {
+fn get_user(principal: Principal) -> Result<UserProfile, UserError> {
     USERS.with(|users| {
         let users = users.borrow();
         match users.get(&principal) {
             Some(user) => Ok(user.clone()),
-            None => Err("User not found".to_string()),
+            None => Err(UserError::NotFound),
         }
     })
 }
 
+/// Batch lookup used by other canisters to hydrate authors. Unknown principals are
+/// skipped, so the result can be shorter than the request.
 #[query]
-fn get_user_by_username(username: String) -> Result<UserProfile, String> {
+fn get_users(principals: Vec<Principal>) -> Vec<UserSummary> {
+    USERS.with(|users| {
+        let users = users.borrow();
+        principals
+            .iter()
+            .filter_map(|principal| users.get(principal))
+            .map(|user| UserSummary::from(&user))
+            .collect()
+    })
+}
+
+/// Also resolves a name its owner gave up in a change, until its grace period ends.
+#[query]
+fn get_user_by_username(username: String) -> Result<UserProfile, UserError> {
     USERNAMES.with(|usernames| {
         let usernames = usernames.borrow();
-        match usernames.get(&username) {
+        match usernames.get(&validation::canonical(&username)) {
             Some(principal) => get_user(principal),
-            None => Err("User not found".to_string()),
+            None => Err(UserError::NotFound),
         }
     })
 }
 
+/// Batch lookup by username, used by `post_management` to resolve `@mentions`. Each
+/// user found comes paired with the name it was looked up by, which may be a variant
+/// or a given-up name rather than their current username. Unknown usernames are
+/// skipped, so the result can be shorter than the request.
 #[query]
-fn get_current_user() -> Result<UserProfile, String> {
-    let caller = ic_cdk::caller();
-    get_user(caller)
+fn get_users_by_usernames(usernames: Vec<String>) -> Vec<(String, UserSummary)> {
+    let index = USERNAMES.with(|index| {
+        let index = index.borrow();
+        usernames
+            .into_iter()
+            .filter_map(|username| Some((index.get(&validation::canonical(&username))?, username)))
+            .collect::<Vec<_>>()
+    });
+    USERS.with(|users| {
+        let users = users.borrow();
+        index
+            .into_iter()
+            .filter_map(|(principal, username)| Some((username, UserSummary::from(&users.get(&principal)?))))
+            .collect()
+    })
 }
 
 #[query]
-fn username_available(username: String) -> bool {
-    USERNAMES.with(|usernames| {
-        let usernames = usernames.borrow();
-        !usernames.contains_key(&username)
-    })
+fn get_current_user() -> Result<UserProfile, UserError> {
+    let caller = ic_cdk::caller();
+    get_user(caller)
 }
 
 #[query]
@@ -172,4 +426,113 @@ fn get_all_users() -> Vec<UserProfile> {
     })
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+    use ic_stable_structures::memory_manager::MemoryManager;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    type State = (
+        StableBTreeMap<Principal, UserProfile, Memory>,
+        StableBTreeMap<String, Principal, Memory>,
+    );
+
+    /// Opens the canister's stable structures on top of `memory` the same way a
+    /// freshly installed or upgraded module does.
+    fn open(memory: &DefaultMemoryImpl) -> State {
+        let manager = MemoryManager::init(memory.clone());
+        (
+            StableBTreeMap::init(manager.get(USERS_MEMORY_ID)),
+            StableBTreeMap::init(manager.get(USERNAMES_MEMORY_ID)),
+        )
+    }
+
+    fn profile(principal: Principal, username: &str) -> UserProfile {
+        UserProfile {
+            principal,
+            username: username.to_string(),
+            display_name: format!("{username} display"),
+            bio: "bio".to_string(),
+            avatar_url: "https://example.com/avatar.png".to_string(),
+            created_at: 1,
+            updated_at: 2,
+            is_verified: true,
+            is_private: false,
+        }
+    }
+
+    #[test]
+    fn profiles_and_usernames_survive_upgrade() {
+        let memory = DefaultMemoryImpl::default();
+        let alice = principal(1);
+        let bob = principal(2);
+
+        {
+            let (mut users, mut usernames) = open(&memory);
+            users.insert(alice, profile(alice, "alice"));
+            users.insert(bob, profile(bob, "bob"));
+            usernames.insert("alice".to_string(), alice);
+            usernames.insert("bob".to_string(), bob);
+        }
+
+        let (users, usernames) = open(&memory);
+        assert_eq!(users.len(), 2);
+        let restored = users.get(&alice).unwrap();
+        assert_eq!(restored.username, "alice");
+        assert_eq!(restored.display_name, "alice display");
+        assert!(restored.is_verified);
+        assert_eq!(usernames.get(&"bob".to_string()), Some(bob));
+    }
+
+    #[test]
+    fn profiles_written_before_versioning_still_load() {
+        let memory = DefaultMemoryImpl::default();
+        let alice = principal(1);
+
+        {
+            let manager = MemoryManager::init(memory.clone());
+            let mut raw: StableBTreeMap<Principal, Vec<u8>, Memory> =
+                StableBTreeMap::init(manager.get(USERS_MEMORY_ID));
+            let legacy = UserProfileV1 {
+                principal: alice,
+                username: "alice".to_string(),
+                display_name: "Alice".to_string(),
+                bio: String::new(),
+                avatar_url: String::new(),
+                created_at: 1,
+                updated_at: 1,
+                is_verified: false,
+            };
+            raw.insert(alice, candid::encode_one(legacy).unwrap());
+        }
+
+        let (mut users, _) = open(&memory);
+        let restored = users.get(&alice).unwrap();
+        assert_eq!(restored.username, "alice");
+        assert!(!restored.is_private);
+
+        assert_eq!(versioned::migrate_map(&mut users), 1);
+        let manager = MemoryManager::init(memory);
+        let raw: StableBTreeMap<Principal, Vec<u8>, Memory> =
+            StableBTreeMap::init(manager.get(USERS_MEMORY_ID));
+        assert_eq!(
+            versioned::stored_version(&raw.get(&alice).unwrap()),
+            UserProfile::VERSION
+        );
+    }
+
+    #[test]
+    fn committed_candid_matches_exported_interface() {
+        use candid_parser::utils::{service_compatible, CandidSource};
+
+        let committed = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("user_management.did");
+        service_compatible(
+            CandidSource::File(&committed),
+            CandidSource::Text(&__export_service()),
+        )
+        .expect("user_management.did is out of date; run scripts/generate_candid.sh");
+    }
+}
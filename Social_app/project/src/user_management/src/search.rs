@@ -0,0 +1,298 @@
+//! User search. Usernames and display names are case-folded into one sorted index,
+//! so every name starting with a query is a single range scan. Follower counts,
+//! which rank the results, are pushed here by `social_graph` on every follow change.
+
+use crate::{canister_ids, UserProfile, FOLLOWER_COUNTS_MEMORY_ID, NAME_INDEX_MEMORY_ID, USERS};
+use candid::Principal;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::storable::Bound as StorableBound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use social_types::memory::{self, Memory};
+use social_types::pagination::page_size;
+use social_types::UserSummary;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Names are indexed by at most this many leading characters.
+const MAX_NAME_CHARS: usize = 64;
+
+/// Upper bound on the index entries one search reads for prefix matches, and again
+/// for typo matches.
+const MAX_SEARCH_SCAN: usize = 1_000;
+
+/// A case-folded username or display name.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Name(String);
+
+impl Storable for Name {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Name(String::from_utf8(bytes.into_owned()).expect("stored name is not UTF-8"))
+    }
+
+    const BOUND: StorableBound = StorableBound::Bounded {
+        max_size: (MAX_NAME_CHARS * 4) as u32,
+        is_fixed_size: false,
+    };
+}
+
+thread_local! {
+    /// `(folded_name, user)` for each user's username and display name.
+    static NAME_INDEX: RefCell<StableBTreeMap<(Name, Principal), (), Memory>> = memory::init_map(NAME_INDEX_MEMORY_ID);
+    static FOLLOWER_COUNTS: RefCell<StableBTreeMap<Principal, u64, Memory>> = memory::init_map(FOLLOWER_COUNTS_MEMORY_ID);
+}
+
+fn fold(name: &str) -> Name {
+    Name(name.trim().to_lowercase().chars().take(MAX_NAME_CHARS).collect())
+}
+
+fn names(user: &UserProfile) -> [Name; 2] {
+    [fold(&user.username), fold(&user.display_name)]
+}
+
+pub(crate) fn insert(user: &UserProfile) {
+    NAME_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for name in names(user) {
+            index.insert((name, user.principal), ());
+        }
+    });
+}
+
+pub(crate) fn remove(user: &UserProfile) {
+    NAME_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for name in names(user) {
+            index.remove(&(name, user.principal));
+        }
+    });
+}
+
+/// Drops everything indexed for a deleted account.
+pub(crate) fn forget(user: &UserProfile) {
+    remove(user);
+    FOLLOWER_COUNTS.with(|counts| counts.borrow_mut().remove(&user.principal));
+}
+
+/// Indexes every stored profile. Only needed when upgrading from a build without search.
+pub(crate) fn rebuild() {
+    NAME_INDEX.with(|index| index.borrow_mut().clear_new());
+    USERS.with(|users| {
+        for (_, user) in users.borrow().iter() {
+            insert(&user);
+        }
+    });
+}
+
+fn caller_is_social_graph() -> Result<(), String> {
+    if canister_ids().social_graph == Some(ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("only social_graph can update follower counts".to_string())
+    }
+}
+
+/// Called by `social_graph` whenever `user` gains or loses a follower.
+#[update(guard = "caller_is_social_graph")]
+fn follower_count_updated(user: Principal, followers_count: u64) {
+    FOLLOWER_COUNTS.with(|counts| counts.borrow_mut().insert(user, followers_count));
+}
+
+/// Typos tolerated in a query of `len` characters.
+fn max_typos(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How closely a name matches a query; lower is better.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Match {
+    Exact,
+    Prefix,
+    Typos(usize),
+}
+
+/// Up to `MAX_SEARCH_SCAN` indexed names starting with `prefix`, in name order.
+fn starting_with(index: &StableBTreeMap<(Name, Principal), (), Memory>, prefix: &str) -> Vec<(String, Principal)> {
+    index
+        .range((Name(prefix.to_string()), Principal::management_canister())..)
+        .take_while(|((Name(name), _), _)| name.starts_with(prefix))
+        .take(MAX_SEARCH_SCAN)
+        .map(|((Name(name), user), _)| (name, user))
+        .collect()
+}
+
+/// Keeps the best match found for `user`.
+fn record(found: &mut BTreeMap<Principal, Match>, user: Principal, quality: Match) {
+    let best = found.entry(user).or_insert(quality);
+    *best = quality.min(*best);
+}
+
+/// The users whose names start with `query`, or failing enough of those, whose
+/// names start with something a few typos away from it. Typo matches share the
+/// query's first character, so only that part of the index is read.
+fn matches(query: &Name, limit: usize) -> BTreeMap<Principal, Match> {
+    let mut found = BTreeMap::new();
+    NAME_INDEX.with(|index| {
+        let index = index.borrow();
+        for (name, user) in starting_with(&index, &query.0) {
+            let quality = if name == query.0 { Match::Exact } else { Match::Prefix };
+            record(&mut found, user, quality);
+        }
+
+        let query: Vec<char> = query.0.chars().collect();
+        let typos = max_typos(query.len());
+        if found.len() >= limit || typos == 0 {
+            return;
+        }
+        for (name, user) in starting_with(&index, &query[0].to_string()) {
+            let name: Vec<char> = name.chars().collect();
+            // Compare against the name's beginning, give or take the typos.
+            let distance = (query.len().saturating_sub(typos)..=query.len() + typos)
+                .filter(|&len| len <= name.len())
+                .map(|len| edit_distance(&query, &name[..len]))
+                .min();
+            if let Some(distance) = distance.filter(|&distance| distance <= typos) {
+                record(&mut found, user, Match::Typos(distance));
+            }
+        }
+    });
+    found
+}
+
+/// Users whose username or display name starts with `query`, case-insensitively,
+/// with close misspellings as a fallback. Best matches come first, then verified
+/// users, then those with more followers.
+#[query]
+fn search_users(query: String, limit: u64) -> Vec<UserSummary> {
+    let query = fold(query.trim().trim_start_matches('@'));
+    if query.0.is_empty() {
+        return Vec::new();
+    }
+    let limit = page_size(limit);
+
+    let mut results: Vec<(Match, UserProfile, u64)> = USERS.with(|users| {
+        let users = users.borrow();
+        matches(&query, limit)
+            .into_iter()
+            .filter_map(|(user, quality)| {
+                let followers = FOLLOWER_COUNTS.with(|counts| counts.borrow().get(&user).unwrap_or(0));
+                Some((quality, users.get(&user)?, followers))
+            })
+            .collect()
+    });
+    results.sort_by_key(|(quality, user, followers)| {
+        (*quality, !user.is_verified, Reverse(*followers), user.username.clone())
+    });
+    results
+        .iter()
+        .take(limit)
+        .map(|(_, user, _)| UserSummary::from(user))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+
+    fn add_user(n: u8, username: &str, display_name: &str, is_verified: bool, followers: u64) {
+        let user = UserProfile {
+            principal: principal(n),
+            username: username.to_string(),
+            display_name: display_name.to_string(),
+            bio: String::new(),
+            avatar_url: String::new(),
+            created_at: 0,
+            updated_at: 0,
+            is_verified,
+            is_private: false,
+        };
+        USERS.with(|users| users.borrow_mut().insert(user.principal, user.clone()));
+        insert(&user);
+        FOLLOWER_COUNTS.with(|counts| counts.borrow_mut().insert(user.principal, followers));
+    }
+
+    fn usernames(query: &str) -> Vec<String> {
+        search_users(query.to_string(), 10)
+            .into_iter()
+            .map(|user| user.username)
+            .collect()
+    }
+
+    #[test]
+    fn matches_username_and_display_name_prefixes() {
+        add_user(1, "alice", "Alice Liddell", false, 0);
+        add_user(2, "bob", "Alfred Bob", false, 0);
+        add_user(3, "carol", "Carol", false, 0);
+
+        assert_eq!(usernames("AL"), vec!["alice", "bob"]);
+        assert_eq!(usernames("@alice"), vec!["alice"]);
+        assert_eq!(usernames("alfred b"), vec!["bob"]);
+        assert!(usernames(" ").is_empty());
+    }
+
+    #[test]
+    fn ranks_exact_then_verified_then_followers() {
+        add_user(1, "sam", "Sam", false, 0);
+        add_user(2, "samuel", "Samuel", false, 500);
+        add_user(3, "samantha", "Samantha", true, 10);
+        add_user(4, "sami", "Sami", false, 20);
+
+        assert_eq!(usernames("sam"), vec!["sam", "samantha", "samuel", "sami"]);
+    }
+
+    #[test]
+    fn falls_back_to_close_misspellings() {
+        add_user(1, "jonathan", "Jonathan", false, 0);
+        add_user(2, "joanna", "Joanna", false, 0);
+
+        assert_eq!(usernames("jonahtan"), vec!["jonathan"]);
+        assert_eq!(usernames("jonatan"), vec!["jonathan"]);
+        // Short queries must match exactly.
+        assert!(usernames("jao").is_empty());
+    }
+
+    #[test]
+    fn follows_display_name_changes() {
+        add_user(1, "dave", "Dave", false, 0);
+        let mut user = USERS.with(|users| users.borrow().get(&principal(1)).unwrap());
+        remove(&user);
+        user.display_name = "Zed".to_string();
+        insert(&user);
+
+        assert_eq!(usernames("zed"), vec!["dave"]);
+        assert!(usernames("dav").contains(&"dave".to_string()));
+        assert_eq!(NAME_INDEX.with(|index| index.borrow().len()), 2);
+    }
+
+    #[test]
+    fn edit_distance_counts_character_edits() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(edit_distance(&chars("é"), &chars("e")), 1);
+        assert_eq!(edit_distance(&chars(""), &chars("ab")), 2);
+    }
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the unit tests.
+
+use crate::{search, validation, UserProfile, USERS};
+use candid::Principal;
+
+/// A distinct principal for each `n`.
+pub(crate) fn principal(n: u8) -> Principal {
+    Principal::from_slice(&[n; 29])
+}
+
+/// Registers `user` as `username` the way `create_user` does, without needing a caller.
+pub(crate) fn add_user(user: Principal, username: &str) -> UserProfile {
+    let profile = UserProfile {
+        principal: user,
+        username: username.to_string(),
+        display_name: username.to_string(),
+        bio: String::new(),
+        avatar_url: String::new(),
+        created_at: 1,
+        updated_at: 1,
+        is_verified: false,
+        is_private: false,
+    };
+    let valid = validation::check_available(username, user).unwrap();
+    validation::claim(&valid, user);
+    search::insert(&profile);
+    USERS.with(|users| users.borrow_mut().insert(user, profile.clone()));
+    profile
+}
@@ -0,0 +1,303 @@
+//! Username changes. A name given up in a change keeps resolving to its old owner
+//! for a grace period, so existing links and mentions still work, and nobody else
+//! can claim it until the global timer releases it.
+
+use crate::validation::MAX_USERNAME_CHARS;
+use crate::{
+    notify_author_updated, search, validation, UserProfile, LAST_USERNAME_CHANGES_MEMORY_ID,
+    USERNAME_REDIRECTS_MEMORY_ID, USERNAME_REDIRECT_EXPIRIES_MEMORY_ID, USERS,
+};
+use candid::{CandidType, Principal};
+use ic_cdk::api::time;
+use ic_cdk_macros::update;
+use ic_stable_structures::storable::Bound as StorableBound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use social_types::guards::authenticated_caller;
+use social_types::memory::{self, Memory};
+use social_types::timer;
+use social_types::versioned::Versioned;
+use social_types::{versioned_storable, UserError};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Minimum time between two username changes by the same user.
+const USERNAME_CHANGE_COOLDOWN: u64 = 30 * DAY;
+
+/// How long a given-up username keeps resolving to its old owner.
+const USERNAME_REDIRECT_GRACE: u64 = 14 * DAY;
+
+/// A given-up username that still resolves to `user` until `expires_at`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UsernameRedirect {
+    pub user: Principal,
+    pub expires_at: u64,
+}
+
+impl Versioned for UsernameRedirect {
+    const VERSION: u8 = 1;
+}
+
+versioned_storable!(UsernameRedirect);
+
+/// The canonical form of a valid username, bounded so it can be part of a key.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Canonical(String);
+
+impl Storable for Canonical {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Canonical(String::from_utf8(bytes.into_owned()).expect("stored username is not UTF-8"))
+    }
+
+    const BOUND: StorableBound = StorableBound::Bounded {
+        max_size: (MAX_USERNAME_CHARS * 4) as u32,
+        is_fixed_size: false,
+    };
+}
+
+thread_local! {
+    /// Canonical form of each given-up username that still redirects.
+    static REDIRECTS: RefCell<StableBTreeMap<String, UsernameRedirect, Memory>> = memory::init_map(USERNAME_REDIRECTS_MEMORY_ID);
+    /// `(expires_at, canonical)` for every redirect, soonest first.
+    static EXPIRIES: RefCell<StableBTreeMap<(u64, Canonical), (), Memory>> = memory::init_map(USERNAME_REDIRECT_EXPIRIES_MEMORY_ID);
+    /// When each user last changed their username.
+    static LAST_CHANGES: RefCell<StableBTreeMap<Principal, u64, Memory>> = memory::init_map(LAST_USERNAME_CHANGES_MEMORY_ID);
+}
+
+fn insert_redirect(canonical: String, redirect: UsernameRedirect) {
+    EXPIRIES.with(|expiries| {
+        expiries
+            .borrow_mut()
+            .insert((redirect.expires_at, Canonical(canonical.clone())), ())
+    });
+    REDIRECTS.with(|redirects| redirects.borrow_mut().insert(canonical, redirect));
+}
+
+fn remove_redirect(canonical: &str) -> Option<UsernameRedirect> {
+    let redirect = REDIRECTS.with(|redirects| redirects.borrow_mut().remove(&canonical.to_string()))?;
+    EXPIRIES.with(|expiries| {
+        expiries
+            .borrow_mut()
+            .remove(&(redirect.expires_at, Canonical(canonical.to_string())))
+    });
+    Some(redirect)
+}
+
+/// Renames `user` to `new_username` at `now`, leaving a redirect behind. Taking back a
+/// name still in its grace period cancels that name's redirect. A change of case or
+/// width alone gives nothing up, so it leaves no redirect and starts no cooldown.
+fn rename(user: Principal, new_username: String, now: u64) -> Result<UserProfile, UserError> {
+    let mut profile = USERS
+        .with(|users| users.borrow().get(&user))
+        .ok_or(UserError::NotFound)?;
+    if profile.username == new_username {
+        return Ok(profile);
+    }
+    let old_canonical = validation::canonical(&profile.username);
+    let gives_up_name = validation::canonical(&new_username) != old_canonical;
+    if let Some(last_change) = LAST_CHANGES.with(|changes| changes.borrow().get(&user)).filter(|_| gives_up_name) {
+        let available_at = last_change.saturating_add(USERNAME_CHANGE_COOLDOWN);
+        if now < available_at {
+            return Err(UserError::UsernameChangeCooldown { available_at });
+        }
+    }
+    let username = validation::check_available(&new_username, user)?;
+
+    if gives_up_name {
+        remove_redirect(&username.canonical);
+        let redirect = UsernameRedirect {
+            user,
+            expires_at: now.saturating_add(USERNAME_REDIRECT_GRACE),
+        };
+        insert_redirect(old_canonical, redirect);
+        LAST_CHANGES.with(|changes| changes.borrow_mut().insert(user, now));
+    }
+    validation::claim(&username, user);
+
+    search::remove(&profile);
+    profile.username = new_username;
+    profile.updated_at = now;
+    search::insert(&profile);
+    USERS.with(|users| users.borrow_mut().insert(user, profile.clone()));
+    Ok(profile)
+}
+
+/// Changes the caller's username. Their old name keeps pointing at them for
+/// `USERNAME_REDIRECT_GRACE`, and they cannot change it again for
+/// `USERNAME_CHANGE_COOLDOWN`.
+#[update]
+fn change_username(new_username: String) -> Result<UserProfile, UserError> {
+    let caller = authenticated_caller()?;
+    let profile = rename(caller, new_username, time())?;
+    schedule_release();
+    notify_author_updated(&profile);
+    Ok(profile)
+}
+
+/// Frees every given-up username whose grace period ended by `now`.
+pub(crate) fn release_expired(now: u64) {
+    let expired: Vec<String> = EXPIRIES.with(|expiries| {
+        expiries
+            .borrow()
+            .iter()
+            .take_while(|((expires_at, _), _)| *expires_at <= now)
+            .map(|((_, canonical), _)| canonical.0)
+            .collect()
+    });
+    for canonical in expired {
+        if let Some(redirect) = remove_redirect(&canonical) {
+            validation::release(&canonical, redirect.user);
+        }
+    }
+}
+
+/// Frees every given-up username still redirecting to `user` and forgets when they
+/// last changed names. Used when their account is deleted.
+pub(crate) fn forget(user: Principal) {
+    let owned: Vec<String> = REDIRECTS.with(|redirects| {
+        redirects
+            .borrow()
+            .iter()
+            .filter(|(_, redirect)| redirect.user == user)
+            .map(|(canonical, _)| canonical)
+            .collect()
+    });
+    for canonical in owned {
+        remove_redirect(&canonical);
+        validation::release(&canonical, user);
+    }
+    LAST_CHANGES.with(|changes| changes.borrow_mut().remove(&user));
+}
+
+/// Arms the global timer for the next redirect to expire, if any.
+pub(crate) fn schedule_release() {
+    let next = EXPIRIES.with(|expiries| expiries.borrow().first_key_value());
+    if let Some(((deadline, _), _)) = next {
+        timer::arm(deadline);
+    }
+}
+
+/// Re-keys every redirect under the current canonical form of its name and claims
+/// the name again, after `validation::rebuild` cleared every claim. A redirect whose
+/// name now belongs to someone else is dropped. Also builds the expiry index.
+pub(crate) fn reindex() {
+    let redirects: Vec<(String, UsernameRedirect)> =
+        REDIRECTS.with(|redirects| redirects.borrow().iter().collect());
+    REDIRECTS.with(|redirects| redirects.borrow_mut().clear_new());
+    EXPIRIES.with(|expiries| expiries.borrow_mut().clear_new());
+    for (canonical, redirect) in redirects {
+        if let Ok(username) = validation::check(&canonical, redirect.user) {
+            validation::claim(&username, redirect.user);
+            insert_redirect(username.canonical, redirect);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_user, principal};
+    use crate::USERNAMES;
+
+    fn owner(username: &str) -> Option<Principal> {
+        USERNAMES.with(|usernames| usernames.borrow().get(&validation::canonical(username)))
+    }
+
+    #[test]
+    fn old_name_redirects_until_released() {
+        let alice = principal(1);
+        add_user(alice, "alice");
+
+        let profile = rename(alice, "alice_b".to_string(), DAY).unwrap();
+        assert_eq!(profile.username, "alice_b");
+        assert_eq!(owner("alice"), Some(alice));
+        assert_eq!(owner("alice_b"), Some(alice));
+        let found = crate::get_users_by_usernames(vec!["Alice".to_string()]);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].0.as_str(), found[0].1.username.as_str()), ("Alice", "alice_b"));
+        assert!(matches!(
+            validation::check_available("alice", principal(2)),
+            Err(social_types::UsernameRejection::Taken { .. })
+        ));
+
+        release_expired(DAY + USERNAME_REDIRECT_GRACE - 1);
+        assert_eq!(owner("alice"), Some(alice));
+        release_expired(DAY + USERNAME_REDIRECT_GRACE);
+        assert_eq!(owner("alice"), None);
+        assert!(validation::check_available("alice", principal(2)).is_ok());
+        assert_eq!(owner("alice_b"), Some(alice));
+    }
+
+    #[test]
+    fn enforces_a_cooldown_between_changes() {
+        let bob = principal(1);
+        add_user(bob, "bob");
+        rename(bob, "bobby".to_string(), DAY).unwrap();
+
+        assert_eq!(
+            rename(bob, "robert".to_string(), 2 * DAY).unwrap_err(),
+            UserError::UsernameChangeCooldown {
+                available_at: DAY + USERNAME_CHANGE_COOLDOWN
+            }
+        );
+        assert!(rename(bob, "robert".to_string(), DAY + USERNAME_CHANGE_COOLDOWN).is_ok());
+    }
+
+    #[test]
+    fn a_look_alike_rename_keeps_the_shared_skeleton_claimed() {
+        let alice = principal(1);
+        add_user(alice, "alice");
+        rename(alice, "a1ice".to_string(), DAY).unwrap();
+
+        release_expired(DAY + USERNAME_REDIRECT_GRACE);
+        assert_eq!(owner("alice"), None);
+        assert_eq!(
+            validation::check_available("alice", principal(2)).err(),
+            Some(social_types::UsernameRejection::Confusable)
+        );
+        assert!(EXPIRIES.with(|expiries| expiries.borrow().is_empty()));
+    }
+
+    #[test]
+    fn a_change_of_case_starts_no_cooldown() {
+        let bob = principal(1);
+        add_user(bob, "bob");
+        rename(bob, "Bob".to_string(), DAY).unwrap();
+        assert!(REDIRECTS.with(|redirects| redirects.borrow().is_empty()));
+
+        rename(bob, "robert".to_string(), DAY + 1).unwrap();
+        assert_eq!(owner("bob"), Some(bob));
+    }
+
+    #[test]
+    fn taking_a_name_back_cancels_its_redirect() {
+        let carol = principal(1);
+        add_user(carol, "carol");
+        rename(carol, "carol2".to_string(), 0).unwrap();
+        rename(carol, "carol".to_string(), USERNAME_CHANGE_COOLDOWN).unwrap();
+
+        release_expired(u64::MAX);
+        assert_eq!(owner("carol"), Some(carol));
+        assert_eq!(owner("carol2"), None);
+    }
+
+    #[test]
+    fn rejects_names_owned_by_others() {
+        add_user(principal(1), "dave");
+        add_user(principal(2), "erin");
+
+        assert_eq!(
+            rename(principal(2), "Dave".to_string(), 0).unwrap_err(),
+            UserError::UsernameTaken {
+                suggestion: Some("Dave1".to_string())
+            }
+        );
+        assert_eq!(rename(principal(3), "frank".to_string(), 0).unwrap_err(), UserError::NotFound);
+    }
+}
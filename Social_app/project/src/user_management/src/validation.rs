@@ -0,0 +1,366 @@
+//! Username rules. A username is registered under its canonical form, NFKC-normalized
+//! and case-folded, so `Alice`, `alice` and `ａｌｉｃｅ` are one name. Each name also
+//! claims its confusable skeleton (UTS #39), which keeps look-alikes such as `a1ice`
+//! from being registered next to `alice`, and reserved names are matched the same way.
+
+use crate::{RESERVED_USERNAMES_MEMORY_ID, USERNAMES, USERNAME_SKELETONS_MEMORY_ID, USERS};
+use candid::Principal;
+use ic_cdk_macros::{query, update};
+use ic_stable_structures::StableBTreeMap;
+use social_types::guards::caller_is_controller;
+use social_types::memory::{self, Memory};
+use social_types::UsernameRejection;
+use std::cell::RefCell;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{skeleton, GeneralSecurityProfile, MixedScript};
+
+pub(crate) const MIN_USERNAME_CHARS: usize = 3;
+pub(crate) const MAX_USERNAME_CHARS: usize = 20;
+
+/// Reserved when the canister is installed or upgraded to reserved-name support.
+/// Controllers can change the list afterwards.
+pub(crate) const DEFAULT_RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "moderator",
+    "root",
+    "security",
+    "staff",
+    "support",
+    "system",
+];
+
+thread_local! {
+    /// Skeleton of each registered username to its owner.
+    static SKELETONS: RefCell<StableBTreeMap<String, Principal, Memory>> = memory::init_map(USERNAME_SKELETONS_MEMORY_ID);
+    /// Skeleton of each reserved name to the name as it was reserved.
+    static RESERVED: RefCell<StableBTreeMap<String, String, Memory>> = memory::init_map(RESERVED_USERNAMES_MEMORY_ID);
+}
+
+/// A username that passed every check, in the forms it is indexed under.
+pub(crate) struct ValidUsername {
+    pub canonical: String,
+    pub skeleton: String,
+}
+
+/// NFKC with case folding: the form usernames are compared and indexed in.
+/// Upper-casing before lower-casing folds what lower-casing alone keeps apart, such as
+/// `ß` and `ss` or the final and medial sigma.
+pub(crate) fn canonical(username: &str) -> String {
+    let folded = username.nfkc().collect::<String>().to_uppercase().to_lowercase();
+    folded.nfkc().collect()
+}
+
+fn skeleton_of(canonical: &str) -> String {
+    skeleton(canonical).collect()
+}
+
+/// Length and character rules, checked on the canonical form.
+fn check_rules(username: &str) -> Result<String, UsernameRejection> {
+    let too_long = UsernameRejection::TooLong {
+        max: MAX_USERNAME_CHARS as u32,
+    };
+    // Nothing this many bytes long normalizes to an acceptable length, so skip the
+    // work of normalizing it.
+    if username.len() > MAX_USERNAME_CHARS * 4 * 4 {
+        return Err(too_long);
+    }
+
+    let canonical = canonical(username);
+    let length = canonical.chars().count();
+    if length < MIN_USERNAME_CHARS {
+        return Err(UsernameRejection::TooShort {
+            min: MIN_USERNAME_CHARS as u32,
+        });
+    }
+    if length > MAX_USERNAME_CHARS {
+        return Err(too_long);
+    }
+    if let Some(c) = canonical
+        .chars()
+        .find(|&c| c != '_' && !(c.is_alphanumeric() && c.identifier_allowed()))
+    {
+        return Err(UsernameRejection::InvalidCharacter {
+            character: c.to_string(),
+        });
+    }
+    if !canonical.as_str().is_single_script() {
+        return Err(UsernameRejection::MixedScripts);
+    }
+    Ok(canonical)
+}
+
+/// Checks that `username` may be registered by `claimant`: it follows the rules, is
+/// not reserved, and neither it nor a look-alike belongs to someone else.
+pub(crate) fn check_available(username: &str, claimant: Principal) -> Result<ValidUsername, UsernameRejection> {
+    check(username, claimant).map_err(|rejection| match rejection {
+        UsernameRejection::Taken { .. } => UsernameRejection::Taken {
+            suggestion: suggest(username, claimant),
+        },
+        rejection => rejection,
+    })
+}
+
+/// `check_available` without a suggestion for taken names.
+pub(crate) fn check(username: &str, claimant: Principal) -> Result<ValidUsername, UsernameRejection> {
+    let canonical = check_rules(username)?;
+    let skeleton = skeleton_of(&canonical);
+
+    if RESERVED.with(|reserved| reserved.borrow().contains_key(&skeleton)) {
+        return Err(UsernameRejection::Reserved);
+    }
+    USERNAMES.with(|usernames| {
+        let usernames = usernames.borrow();
+        match usernames.get(&canonical) {
+            Some(owner) if owner != claimant => Err(UsernameRejection::Taken { suggestion: None }),
+            _ => Ok(()),
+        }
+    })?;
+    match SKELETONS.with(|skeletons| skeletons.borrow().get(&skeleton)) {
+        Some(owner) if owner != claimant => Err(UsernameRejection::Confusable),
+        _ => Ok(ValidUsername { canonical, skeleton }),
+    }
+}
+
+/// `username` with 1, 2, 3... appended, shortened where needed to make room.
+fn variants(username: &str) -> impl Iterator<Item = String> + '_ {
+    (1u64..).map(move |n| {
+        let suffix = n.to_string();
+        let base: String = username.chars().take(MAX_USERNAME_CHARS - suffix.len()).collect();
+        base + &suffix
+    })
+}
+
+/// Proposes a variant of a taken `username` that `claimant` could register.
+fn suggest(username: &str, claimant: Principal) -> Option<String> {
+    variants(username)
+        .take(99)
+        .find(|candidate| check(candidate, claimant).is_ok())
+}
+
+/// Records `username` as `user`'s.
+pub(crate) fn claim(username: &ValidUsername, user: Principal) {
+    USERNAMES.with(|usernames| usernames.borrow_mut().insert(username.canonical.clone(), user));
+    SKELETONS.with(|skeletons| skeletons.borrow_mut().insert(username.skeleton.clone(), user));
+}
+
+/// Frees `canonical` if it still belongs to `user`, and its skeleton too unless
+/// `user`'s current username shares it.
+pub(crate) fn release(canonical: &str, user: Principal) {
+    USERNAMES.with(|usernames| {
+        let mut usernames = usernames.borrow_mut();
+        if usernames.get(&canonical.to_string()) == Some(user) {
+            usernames.remove(&canonical.to_string());
+        }
+    });
+    let skeleton = skeleton_of(canonical);
+    let current = USERS.with(|users| users.borrow().get(&user));
+    if current.is_some_and(|profile| skeleton_of(&self::canonical(&profile.username)) == skeleton) {
+        return;
+    }
+    SKELETONS.with(|skeletons| {
+        let mut skeletons = skeletons.borrow_mut();
+        if skeletons.get(&skeleton) == Some(user) {
+            skeletons.remove(&skeleton);
+        }
+    });
+}
+
+/// Re-indexes every registered username in canonical form, oldest account first so
+/// that when two names now collide, the first to register keeps it. Every other
+/// account of a collision is moved to a free numbered variant of its name once all
+/// names are indexed, and those renames are returned as `(user, new_username)`. Only
+/// needed when upgrading from a build that indexed names differently.
+pub(crate) fn rebuild(mut users: Vec<(u64, Principal, String)>) -> Vec<(Principal, String)> {
+    USERNAMES.with(|usernames| usernames.borrow_mut().clear_new());
+    SKELETONS.with(|skeletons| skeletons.borrow_mut().clear_new());
+    users.sort();
+    let mut colliding = Vec::new();
+    for (_, user, username) in users {
+        let canonical = canonical(&username);
+        let skeleton = skeleton_of(&canonical);
+        let claimed = USERNAMES.with(|usernames| usernames.borrow().contains_key(&canonical));
+        if claimed {
+            colliding.push((user, username));
+            continue;
+        }
+        USERNAMES.with(|usernames| usernames.borrow_mut().insert(canonical, user));
+        SKELETONS.with(|skeletons| {
+            let mut skeletons = skeletons.borrow_mut();
+            if !skeletons.contains_key(&skeleton) {
+                skeletons.insert(skeleton, user);
+            }
+        });
+    }
+
+    colliding
+        .into_iter()
+        .map(|(user, username)| {
+            // Names from before validation may break the rules whatever is appended,
+            // so fall back to plain numbered names, of which one is always free.
+            let (renamed, valid) = variants(&username)
+                .take(10_000)
+                .chain(variants("user"))
+                .find_map(|candidate| Some((candidate.clone(), check(&candidate, user).ok()?)))
+                .expect("numbered usernames ran out");
+            claim(&valid, user);
+            (user, renamed)
+        })
+        .collect()
+}
+
+pub(crate) fn reserve(names: &[String]) {
+    RESERVED.with(|reserved| {
+        let mut reserved = reserved.borrow_mut();
+        for name in names {
+            let canonical = canonical(name);
+            reserved.insert(skeleton_of(&canonical), canonical);
+        }
+    });
+}
+
+/// Reserves `names`, case- and look-alike-insensitively. Existing accounts keep
+/// their names.
+#[update(guard = "caller_is_controller")]
+fn reserve_usernames(names: Vec<String>) {
+    reserve(&names);
+}
+
+#[update(guard = "caller_is_controller")]
+fn unreserve_usernames(names: Vec<String>) {
+    RESERVED.with(|reserved| {
+        let mut reserved = reserved.borrow_mut();
+        for name in names {
+            reserved.remove(&skeleton_of(&canonical(&name)));
+        }
+    });
+}
+
+#[query]
+fn get_reserved_usernames() -> Vec<String> {
+    RESERVED.with(|reserved| reserved.borrow().iter().map(|(_, name)| name).collect())
+}
+
+/// Whether the caller could register `username` right now, and if not, why.
+#[query]
+fn username_available(username: String) -> Result<(), UsernameRejection> {
+    check_available(&username, ic_cdk::caller()).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::principal;
+
+    fn register(username: &str, user: Principal) -> Result<(), UsernameRejection> {
+        let username = check_available(username, user)?;
+        claim(&username, user);
+        Ok(())
+    }
+
+    #[test]
+    fn enforces_length_and_characters() {
+        assert_eq!(check_rules("al"), Err(UsernameRejection::TooShort { min: 3 }));
+        assert_eq!(check_rules(""), Err(UsernameRejection::TooShort { min: 3 }));
+        assert_eq!(check_rules(&"a".repeat(10_000)), Err(UsernameRejection::TooLong { max: 20 }));
+        assert_eq!(
+            check_rules("bob smith"),
+            Err(UsernameRejection::InvalidCharacter {
+                character: " ".to_string()
+            })
+        );
+        assert_eq!(
+            check_rules("bob!"),
+            Err(UsernameRejection::InvalidCharacter {
+                character: "!".to_string()
+            })
+        );
+        // Latin "p" and "l" around a Cyrillic "а".
+        assert_eq!(check_rules("pаypal"), Err(UsernameRejection::MixedScripts));
+        assert_eq!(check_rules("Zoë_42"), Ok("zoë_42".to_string()));
+        assert_eq!(check_rules("Дмитрий"), Ok("дмитрий".to_string()));
+    }
+
+    #[test]
+    fn normalizes_width_and_case() {
+        assert_eq!(canonical("ＡＬＩＣＥ"), "alice");
+        assert_eq!(canonical("Alice"), canonical("aLiCe"));
+        assert_eq!(canonical("STRASSE"), canonical("straße"));
+        assert_eq!(canonical("ὈΔΥΣΣΕΎΣ"), canonical("ὀδυσσεύς"));
+        // Precomposed and combining forms of "é" are the same name.
+        assert_eq!(canonical("re\u{301}my"), canonical("r\u{e9}my"));
+    }
+
+    #[test]
+    fn rejects_duplicates_and_look_alikes() {
+        let alice = principal(1);
+        assert_eq!(register("alice", alice), Ok(()));
+
+        assert_eq!(
+            register("Alice", principal(2)),
+            Err(UsernameRejection::Taken {
+                suggestion: Some("Alice1".to_string())
+            })
+        );
+        assert_eq!(register("a1ice", principal(2)), Err(UsernameRejection::Confusable));
+        // An owner checking their own name is not blocked by it.
+        assert!(check_available("ALICE", alice).is_ok());
+    }
+
+    #[test]
+    fn suggests_only_names_that_could_be_registered() {
+        assert_eq!(register("alice", principal(1)), Ok(()));
+        assert_eq!(register("alice1", principal(1)), Ok(()));
+        assert_eq!(register("a1ice2", principal(2)), Ok(()));
+        reserve(&["alice3".to_string()]);
+        assert_eq!(
+            check_available("alice", principal(3)).err(),
+            Some(UsernameRejection::Taken {
+                suggestion: Some("alice4".to_string())
+            })
+        );
+
+        let long = "abcdefghijklmnopqrst";
+        assert_eq!(register(long, principal(1)), Ok(()));
+        assert_eq!(
+            check_available(long, principal(3)).err(),
+            Some(UsernameRejection::Taken {
+                suggestion: Some("abcdefghijklmnopqrs1".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn reserved_names_match_case_and_look_alikes() {
+        reserve(&["Help".to_string()]);
+
+        assert_eq!(register("HELP", principal(1)), Err(UsernameRejection::Reserved));
+        assert_eq!(register("he1p", principal(1)), Err(UsernameRejection::Reserved));
+        assert_eq!(get_reserved_usernames(), vec!["help".to_string()]);
+
+        unreserve_usernames(vec!["help".to_string()]);
+        assert_eq!(register("help", principal(1)), Ok(()));
+    }
+
+    #[test]
+    fn rebuild_keeps_the_oldest_of_colliding_names() {
+        let (older, newer, newest) = (principal(1), principal(2), principal(3));
+        let renamed = rebuild(vec![
+            (20, newer, "Bob".to_string()),
+            (10, older, "bob".to_string()),
+            (30, newest, "bob1".to_string()),
+        ]);
+
+        assert_eq!(renamed, vec![(newer, "Bob2".to_string())]);
+        let owner = |name: &str| USERNAMES.with(|usernames| usernames.borrow().get(&name.to_string()));
+        assert_eq!(owner("bob"), Some(older));
+        assert_eq!(owner("bob1"), Some(newest));
+        assert_eq!(owner("bob2"), Some(newer));
+        assert_eq!(
+            check_available("bob2", principal(4)).err(),
+            Some(UsernameRejection::Taken {
+                suggestion: Some("bob21".to_string())
+            })
+        );
+    }
+}